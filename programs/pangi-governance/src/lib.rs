@@ -0,0 +1,967 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_spl::token::{Mint, TokenAccount};
+use pangi_vault::{StakeRecord, Vault};
+
+declare_id!("8vmzTMNNDGH3XvBifgc7h51bUXE1pioo9qvfRX6jEF92");
+
+// PANGI Governance - vePANGI-weighted parameter control
+//
+// PROPOSAL LIFECYCLE:
+// - Proposer with enough vePANGI voting power creates a proposal carrying one instruction
+// - Stakers vote For / Against / Abstain with their pangi-vault voting power
+// - finalize_proposal checks quorum and threshold, then queues behind the timelock
+// - execute_proposal CPIs the stored instruction signed by the governance authority PDA
+//
+// ADMIN HANDOFF:
+// Admin keys of pangi-token, pangi-vault, pangi-nft and special-distribution are
+// handed to the governance authority PDA ([b"governance_authority"]). From then on
+// their admin instructions are only reachable through a passed proposal.
+
+// Security constants
+const MIN_VOTING_PERIOD: i64 = 60 * 60; // 1 hour
+const MAX_VOTING_PERIOD: i64 = 14 * 24 * 60 * 60; // 14 days
+const MIN_TIMELOCK_DELAY: i64 = 60 * 60; // 1 hour
+const MAX_TIMELOCK_DELAY: i64 = 7 * 24 * 60 * 60; // 7 days
+const MIN_THRESHOLD_BPS: u16 = 5000; // Simple majority of For + Against
+const MAX_BPS: u16 = 10000;
+const MAX_DESCRIPTION_LEN: usize = 200;
+const MAX_PROPOSAL_ACCOUNTS: usize = 16;
+const MAX_PROPOSAL_DATA_LEN: usize = 512;
+
+// Safe math macros for overflow protection
+macro_rules! safe_add {
+    ($a:expr, $b:expr) => {{
+        $a.checked_add($b).ok_or(ErrorCode::Overflow)?
+    }};
+}
+
+macro_rules! safe_mul {
+    ($a:expr, $b:expr) => {{
+        $a.checked_mul($b).ok_or(ErrorCode::Overflow)?
+    }};
+}
+
+macro_rules! safe_div {
+    ($a:expr, $b:expr) => {{
+        let divisor = $b;
+        if divisor == 0 {
+            return Err(ErrorCode::DivisionByZero.into());
+        }
+        $a.checked_div(divisor).ok_or(ErrorCode::Underflow)?
+    }};
+}
+
+macro_rules! safe_percentage {
+    ($amount:expr, $basis_points:expr) => {{
+        safe_div!(safe_mul!($amount, $basis_points as u64), 10000u64)
+    }};
+}
+
+#[program]
+pub mod pangi_governance {
+    use super::*;
+
+    pub fn initialize_governance(
+        ctx: Context<InitializeGovernance>,
+        quorum_bps: u16,
+        threshold_bps: u16,
+        voting_period: i64,
+        timelock_delay: i64,
+        proposal_threshold: u64,
+    ) -> Result<()> {
+        validate_governance_params(quorum_bps, threshold_bps, voting_period, timelock_delay)?;
+
+        let governance = &mut ctx.accounts.governance;
+        let clock = Clock::get()?;
+
+        governance.pangi_mint = ctx.accounts.pangi_mint.key();
+        governance.quorum_bps = quorum_bps;
+        governance.threshold_bps = threshold_bps;
+        governance.voting_period = voting_period;
+        governance.timelock_delay = timelock_delay;
+        governance.proposal_threshold = proposal_threshold;
+        governance.proposal_count = 0;
+        governance.bump = ctx.bumps.governance;
+        governance.authority_bump = ctx.bumps.governance_authority;
+
+        emit!(GovernanceInitializedEvent {
+            governance: governance.key(),
+            governance_authority: ctx.accounts.governance_authority.key(),
+            pangi_mint: governance.pangi_mint,
+            quorum_bps,
+            threshold_bps,
+            voting_period,
+            timelock_delay,
+            proposal_threshold,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create a proposal carrying one instruction to execute once passed
+    /// Proposer must hold at least `proposal_threshold` vePANGI in a PANGI vault
+    /// remaining_accounts must hold every pangi-vault ve_supply shard of the PANGI mint, in shard order
+    pub fn create_proposal(
+        ctx: Context<CreateProposal>,
+        description: String,
+        instruction: ProposalInstruction,
+    ) -> Result<()> {
+        let governance = &mut ctx.accounts.governance;
+        let stake = &ctx.accounts.stake_record;
+        let clock = Clock::get()?;
+
+        // Depositor, or holder of the stake's position NFT
+        require!(
+            pangi_vault::stake_owner(stake, &ctx.accounts.position_account)?
+                == ctx.accounts.proposer.key(),
+            ErrorCode::Unauthorized
+        );
+
+        // Input validation
+        require!(
+            description.len() <= MAX_DESCRIPTION_LEN,
+            ErrorCode::DescriptionTooLong
+        );
+        require!(
+            instruction.accounts.len() <= MAX_PROPOSAL_ACCOUNTS,
+            ErrorCode::TooManyAccounts
+        );
+        require!(
+            instruction.data.len() <= MAX_PROPOSAL_DATA_LEN,
+            ErrorCode::InstructionDataTooLong
+        );
+
+        // Proposer must have enough voting power: what the stake counts for in the supply
+        // (opted-out stakes have none)
+        let voting_power =
+            pangi_vault::voting_power_at(stake.ve_amount, stake.ve_unlock_at, clock.unix_timestamp)?;
+        require!(
+            voting_power >= governance.proposal_threshold,
+            ErrorCode::InsufficientVotingPower
+        );
+
+        // Snapshot quorum against the current PANGI voting supply (summed over the mint's shards)
+        let total_voting_power = pangi_vault::total_voting_power_at(
+            ctx.remaining_accounts,
+            &governance.pangi_mint,
+            clock.unix_timestamp,
+        )?;
+        let quorum_votes = safe_percentage!(total_voting_power, governance.quorum_bps);
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.governance = governance.key();
+        proposal.id = governance.proposal_count;
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.description = description;
+        proposal.instruction = instruction;
+        proposal.created_at = clock.unix_timestamp;
+        proposal.voting_ends_at = safe_add!(clock.unix_timestamp, governance.voting_period);
+        proposal.executable_at = 0;
+        proposal.for_votes = 0;
+        proposal.against_votes = 0;
+        proposal.abstain_votes = 0;
+        proposal.quorum_votes = quorum_votes;
+        proposal.state = ProposalState::Voting;
+        proposal.bump = ctx.bumps.proposal;
+
+        governance.proposal_count = safe_add!(governance.proposal_count, 1);
+
+        emit!(ProposalCreatedEvent {
+            proposal: proposal.key(),
+            id: proposal.id,
+            proposer: proposal.proposer,
+            target_program: proposal.instruction.program_id,
+            quorum_votes,
+            voting_ends_at: proposal.voting_ends_at,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Vote on a proposal with a pangi-vault stake's vePANGI voting power
+    /// One vote per stake record; weight is the stake's voting power at proposal creation
+    pub fn cast_vote(ctx: Context<CastVote>, choice: VoteChoice) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        let stake = &ctx.accounts.stake_record;
+        let clock = Clock::get()?;
+
+        // Depositor, or holder of the stake's position NFT
+        require!(
+            pangi_vault::stake_owner(stake, &ctx.accounts.position_account)?
+                == ctx.accounts.voter.key(),
+            ErrorCode::Unauthorized
+        );
+
+        require!(
+            proposal.state == ProposalState::Voting,
+            ErrorCode::ProposalNotVoting
+        );
+        require!(
+            clock.unix_timestamp < proposal.voting_ends_at,
+            ErrorCode::VotingEnded
+        );
+
+        // Stakes opened after the proposal cannot vote (no deposit-vote-withdraw)
+        require!(
+            stake.staked_at <= proposal.created_at,
+            ErrorCode::StakeTooRecent
+        );
+
+        // Lock as of proposal creation: later top-ups and re-locks add no weight
+        let weight = vote_weight(stake, &ctx.accounts.vault, proposal.created_at)?;
+        require!(weight > 0, ErrorCode::NoVotingPower);
+
+        match choice {
+            VoteChoice::For => proposal.for_votes = safe_add!(proposal.for_votes, weight),
+            VoteChoice::Against => {
+                proposal.against_votes = safe_add!(proposal.against_votes, weight)
+            }
+            VoteChoice::Abstain => {
+                proposal.abstain_votes = safe_add!(proposal.abstain_votes, weight)
+            }
+        }
+
+        let vote_record = &mut ctx.accounts.vote_record;
+        vote_record.proposal = proposal.key();
+        vote_record.stake_record = stake.key();
+        vote_record.voter = ctx.accounts.voter.key();
+        vote_record.choice = choice;
+        vote_record.weight = weight;
+        vote_record.voted_at = clock.unix_timestamp;
+
+        emit!(VoteCastEvent {
+            proposal: proposal.key(),
+            voter: vote_record.voter,
+            stake_record: vote_record.stake_record,
+            choice,
+            weight,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Close voting: queue behind the timelock if quorum and threshold are met,
+    /// otherwise mark defeated (permissionless)
+    pub fn finalize_proposal(ctx: Context<FinalizeProposal>) -> Result<()> {
+        let governance = &ctx.accounts.governance;
+        let proposal = &mut ctx.accounts.proposal;
+        let clock = Clock::get()?;
+
+        require!(
+            proposal.state == ProposalState::Voting,
+            ErrorCode::ProposalNotVoting
+        );
+        require!(
+            clock.unix_timestamp >= proposal.voting_ends_at,
+            ErrorCode::VotingStillActive
+        );
+
+        if proposal_passed(
+            proposal.for_votes,
+            proposal.against_votes,
+            proposal.abstain_votes,
+            proposal.quorum_votes,
+            governance.threshold_bps,
+        )? {
+            proposal.state = ProposalState::Queued;
+            proposal.executable_at = safe_add!(clock.unix_timestamp, governance.timelock_delay);
+        } else {
+            proposal.state = ProposalState::Defeated;
+        }
+
+        emit!(ProposalFinalizedEvent {
+            proposal: proposal.key(),
+            state: proposal.state,
+            for_votes: proposal.for_votes,
+            against_votes: proposal.against_votes,
+            abstain_votes: proposal.abstain_votes,
+            quorum_votes: proposal.quorum_votes,
+            executable_at: proposal.executable_at,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Execute a queued proposal once its timelock has passed (permissionless)
+    /// remaining_accounts must contain the target program and every account
+    /// in the stored instruction
+    pub fn execute_proposal<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteProposal<'info>>,
+    ) -> Result<()> {
+        let governance = &ctx.accounts.governance;
+        let proposal = &mut ctx.accounts.proposal;
+        let clock = Clock::get()?;
+
+        require!(
+            proposal.state == ProposalState::Queued,
+            ErrorCode::ProposalNotQueued
+        );
+        require!(
+            clock.unix_timestamp >= proposal.executable_at,
+            ErrorCode::TimelockActive
+        );
+
+        // Mark executed before the CPI so the proposal cannot run twice
+        proposal.state = ProposalState::Executed;
+
+        let instruction = Instruction {
+            program_id: proposal.instruction.program_id,
+            accounts: proposal
+                .instruction
+                .accounts
+                .iter()
+                .map(|meta| AccountMeta {
+                    pubkey: meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: proposal.instruction.data.clone(),
+        };
+
+        let seeds = &[b"governance_authority".as_ref(), &[governance.authority_bump]];
+        let signer = &[&seeds[..]];
+
+        invoke_signed(&instruction, ctx.remaining_accounts, signer)?;
+
+        emit!(ProposalExecutedEvent {
+            proposal: proposal.key(),
+            target_program: proposal.instruction.program_id,
+            executed_by: ctx.accounts.executor.key(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Proposer can withdraw a proposal while it is still being voted on
+    pub fn cancel_proposal(ctx: Context<CancelProposal>) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+
+        require!(
+            ctx.accounts.proposer.key() == proposal.proposer,
+            ErrorCode::Unauthorized
+        );
+        validate_cancellable(proposal.state)?;
+
+        proposal.state = ProposalState::Canceled;
+
+        emit!(ProposalCanceledEvent {
+            proposal: proposal.key(),
+            proposer: proposal.proposer,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Update governance parameters
+    /// Only callable by the governance authority PDA, i.e. through a passed proposal
+    pub fn update_governance_config(
+        ctx: Context<UpdateGovernanceConfig>,
+        quorum_bps: Option<u16>,
+        threshold_bps: Option<u16>,
+        voting_period: Option<i64>,
+        timelock_delay: Option<i64>,
+        proposal_threshold: Option<u64>,
+    ) -> Result<()> {
+        let governance = &mut ctx.accounts.governance;
+
+        let quorum_bps = quorum_bps.unwrap_or(governance.quorum_bps);
+        let threshold_bps = threshold_bps.unwrap_or(governance.threshold_bps);
+        let voting_period = voting_period.unwrap_or(governance.voting_period);
+        let timelock_delay = timelock_delay.unwrap_or(governance.timelock_delay);
+        validate_governance_params(quorum_bps, threshold_bps, voting_period, timelock_delay)?;
+
+        governance.quorum_bps = quorum_bps;
+        governance.threshold_bps = threshold_bps;
+        governance.voting_period = voting_period;
+        governance.timelock_delay = timelock_delay;
+        if let Some(threshold) = proposal_threshold {
+            governance.proposal_threshold = threshold;
+        }
+
+        emit!(GovernanceConfigUpdatedEvent {
+            governance: governance.key(),
+            quorum_bps: governance.quorum_bps,
+            threshold_bps: governance.threshold_bps,
+            voting_period: governance.voting_period,
+            timelock_delay: governance.timelock_delay,
+            proposal_threshold: governance.proposal_threshold,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// Vote weight of a stake: its vePANGI voting power at `snapshot_at`, from the
+// balance and lock end recorded in the stake's checkpoint history at that time
+fn vote_weight(stake: &StakeRecord, vault: &Vault, snapshot_at: i64) -> Result<u64> {
+    // Opted-out stakes are not in the supply quorum is taken from (opt-out is one-way)
+    if stake.ve_opt_out {
+        return Ok(0);
+    }
+    pangi_vault::stake_voting_power_at(stake, vault, snapshot_at)
+}
+
+// Quorum counts every vote; the threshold is measured on decisive votes (For + Against)
+fn proposal_passed(
+    for_votes: u64,
+    against_votes: u64,
+    abstain_votes: u64,
+    quorum_votes: u64,
+    threshold_bps: u16,
+) -> Result<bool> {
+    let total_votes = safe_add!(safe_add!(for_votes, against_votes), abstain_votes);
+    let quorum_reached = total_votes >= quorum_votes;
+
+    let decisive_votes = safe_add!(for_votes, against_votes) as u128;
+    let threshold_reached = for_votes > 0
+        && safe_mul!(for_votes as u128, MAX_BPS as u128)
+            >= safe_mul!(decisive_votes, threshold_bps as u128);
+
+    Ok(quorum_reached && threshold_reached)
+}
+
+// Once queued a proposal has passed: it belongs to the voters, not the proposer
+fn validate_cancellable(state: ProposalState) -> Result<()> {
+    require!(
+        state == ProposalState::Voting,
+        ErrorCode::ProposalNotCancellable
+    );
+    Ok(())
+}
+
+// Helper function to validate governance parameters
+fn validate_governance_params(
+    quorum_bps: u16,
+    threshold_bps: u16,
+    voting_period: i64,
+    timelock_delay: i64,
+) -> Result<()> {
+    require!(
+        (1..=MAX_BPS).contains(&quorum_bps),
+        ErrorCode::InvalidQuorum
+    );
+    require!(
+        (MIN_THRESHOLD_BPS..=MAX_BPS).contains(&threshold_bps),
+        ErrorCode::InvalidThreshold
+    );
+    require!(
+        (MIN_VOTING_PERIOD..=MAX_VOTING_PERIOD).contains(&voting_period),
+        ErrorCode::InvalidVotingPeriod
+    );
+    require!(
+        (MIN_TIMELOCK_DELAY..=MAX_TIMELOCK_DELAY).contains(&timelock_delay),
+        ErrorCode::InvalidTimelockDelay
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeGovernance<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Governance::INIT_SPACE,
+        seeds = [b"governance"],
+        bump
+    )]
+    pub governance: Account<'info, Governance>,
+    /// CHECK: PDA that signs executed proposals, holds no data
+    #[account(
+        seeds = [b"governance_authority"],
+        bump
+    )]
+    pub governance_authority: UncheckedAccount<'info>,
+    pub pangi_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateProposal<'info> {
+    #[account(
+        mut,
+        seeds = [b"governance"],
+        bump = governance.bump
+    )]
+    pub governance: Account<'info, Governance>,
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + Proposal::INIT_SPACE,
+        seeds = [b"proposal", governance.key().as_ref(), &governance.proposal_count.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    #[account(
+        constraint = vault.token_mint == governance.pangi_mint @ ErrorCode::InvalidVault,
+        constraint = pangi_vault::vault_carries_voting_power(&vault) @ ErrorCode::VaultNotApproved
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump,
+        seeds::program = pangi_vault::ID
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    // Required for receipt positions (holder of the position NFT signs)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CastVote<'info> {
+    #[account(
+        seeds = [b"governance"],
+        bump = governance.bump
+    )]
+    pub governance: Account<'info, Governance>,
+    #[account(
+        mut,
+        seeds = [b"proposal", governance.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    #[account(
+        constraint = vault.token_mint == governance.pangi_mint @ ErrorCode::InvalidVault,
+        constraint = pangi_vault::vault_carries_voting_power(&vault) @ ErrorCode::VaultNotApproved
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump,
+        seeds::program = pangi_vault::ID
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    // Required for receipt positions (holder of the position NFT signs)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = voter,
+        space = 8 + VoteRecord::INIT_SPACE,
+        seeds = [b"vote", proposal.key().as_ref(), stake_record.key().as_ref()],
+        bump
+    )]
+    pub vote_record: Account<'info, VoteRecord>,
+    #[account(mut)]
+    pub voter: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeProposal<'info> {
+    #[account(
+        seeds = [b"governance"],
+        bump = governance.bump
+    )]
+    pub governance: Account<'info, Governance>,
+    #[account(
+        mut,
+        seeds = [b"proposal", governance.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteProposal<'info> {
+    #[account(
+        seeds = [b"governance"],
+        bump = governance.bump
+    )]
+    pub governance: Account<'info, Governance>,
+    #[account(
+        mut,
+        seeds = [b"proposal", governance.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    pub executor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelProposal<'info> {
+    #[account(
+        seeds = [b"governance"],
+        bump = governance.bump
+    )]
+    pub governance: Account<'info, Governance>,
+    #[account(
+        mut,
+        seeds = [b"proposal", governance.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    pub proposer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGovernanceConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"governance"],
+        bump = governance.bump
+    )]
+    pub governance: Account<'info, Governance>,
+    #[account(
+        seeds = [b"governance_authority"],
+        bump = governance.authority_bump
+    )]
+    pub governance_authority: Signer<'info>,
+}
+
+/// Governance - Global voting parameters
+#[account]
+#[derive(InitSpace)]
+pub struct Governance {
+    pub pangi_mint: Pubkey,            // Only vaults staking this mint carry voting power
+    pub quorum_bps: u16,               // Share of total vePANGI that must vote
+    pub threshold_bps: u16,            // Share of For in For + Against needed to pass
+    pub voting_period: i64,            // Voting window in seconds
+    pub timelock_delay: i64,           // Delay between queue and execution
+    pub proposal_threshold: u64,       // Minimum vePANGI to create a proposal
+    pub proposal_count: u64,           // Next proposal id
+    pub bump: u8,                      // PDA bump seed
+    pub authority_bump: u8,            // governance_authority PDA bump seed
+}
+
+/// Proposal - One instruction to execute through the governance authority
+#[account]
+#[derive(InitSpace)]
+pub struct Proposal {
+    pub governance: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    #[max_len(200)]
+    pub description: String,           // Title or link to the full proposal
+    pub instruction: ProposalInstruction,
+    pub created_at: i64,
+    pub voting_ends_at: i64,
+    pub executable_at: i64,            // Set when queued (0 before)
+    pub for_votes: u64,
+    pub against_votes: u64,
+    pub abstain_votes: u64,
+    pub quorum_votes: u64,             // Snapshot of quorum at creation
+    pub state: ProposalState,
+    pub bump: u8,
+}
+
+/// VoteRecord - One vote per (proposal, stake record)
+#[account]
+#[derive(InitSpace)]
+pub struct VoteRecord {
+    pub proposal: Pubkey,
+    pub stake_record: Pubkey,
+    pub voter: Pubkey,
+    pub choice: VoteChoice,
+    pub weight: u64,
+    pub voted_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct ProposalInstruction {
+    pub program_id: Pubkey,
+    #[max_len(16)]
+    pub accounts: Vec<ProposalAccountMeta>,
+    #[max_len(512)]
+    pub data: Vec<u8>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct ProposalAccountMeta {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum ProposalState {
+    Voting,
+    Defeated,
+    Queued,
+    Executed,
+    Canceled,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum VoteChoice {
+    For,
+    Against,
+    Abstain,
+}
+
+#[event]
+pub struct GovernanceInitializedEvent {
+    pub governance: Pubkey,
+    pub governance_authority: Pubkey,
+    pub pangi_mint: Pubkey,
+    pub quorum_bps: u16,
+    pub threshold_bps: u16,
+    pub voting_period: i64,
+    pub timelock_delay: i64,
+    pub proposal_threshold: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalCreatedEvent {
+    pub proposal: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub target_program: Pubkey,
+    pub quorum_votes: u64,
+    pub voting_ends_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VoteCastEvent {
+    pub proposal: Pubkey,
+    pub voter: Pubkey,
+    pub stake_record: Pubkey,
+    pub choice: VoteChoice,
+    pub weight: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalFinalizedEvent {
+    pub proposal: Pubkey,
+    pub state: ProposalState,
+    pub for_votes: u64,
+    pub against_votes: u64,
+    pub abstain_votes: u64,
+    pub quorum_votes: u64,
+    pub executable_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalExecutedEvent {
+    pub proposal: Pubkey,
+    pub target_program: Pubkey,
+    pub executed_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalCanceledEvent {
+    pub proposal: Pubkey,
+    pub proposer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GovernanceConfigUpdatedEvent {
+    pub governance: Pubkey,
+    pub quorum_bps: u16,
+    pub threshold_bps: u16,
+    pub voting_period: i64,
+    pub timelock_delay: i64,
+    pub proposal_threshold: u64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Unauthorized: caller is not the authority")]
+    Unauthorized,
+    #[msg("Arithmetic overflow detected")]
+    Overflow,
+    #[msg("Arithmetic underflow detected")]
+    Underflow,
+    #[msg("Division by zero")]
+    DivisionByZero,
+    #[msg("Invalid quorum (must be 1-10000 basis points)")]
+    InvalidQuorum,
+    #[msg("Invalid threshold (must be 5000-10000 basis points)")]
+    InvalidThreshold,
+    #[msg("Invalid voting period (1 hour to 14 days)")]
+    InvalidVotingPeriod,
+    #[msg("Invalid timelock delay (1 hour to 7 days)")]
+    InvalidTimelockDelay,
+    #[msg("Proposal description too long (max 200 bytes)")]
+    DescriptionTooLong,
+    #[msg("Too many accounts in proposal instruction (max 16)")]
+    TooManyAccounts,
+    #[msg("Proposal instruction data too long (max 512 bytes)")]
+    InstructionDataTooLong,
+    #[msg("Vault does not stake the governance token")]
+    InvalidVault,
+    #[msg("Vault does not carry governance voting power")]
+    VaultNotApproved,
+    #[msg("Insufficient voting power")]
+    InsufficientVotingPower,
+    #[msg("Stake has no voting power")]
+    NoVotingPower,
+    #[msg("Stake was created after the proposal")]
+    StakeTooRecent,
+    #[msg("Proposal is not open for voting")]
+    ProposalNotVoting,
+    #[msg("Voting period has ended")]
+    VotingEnded,
+    #[msg("Voting period is still active")]
+    VotingStillActive,
+    #[msg("Proposal is not queued")]
+    ProposalNotQueued,
+    #[msg("Timelock has not expired")]
+    TimelockActive,
+    #[msg("Proposal can no longer be canceled")]
+    ProposalNotCancellable,
+}
+
+// ============================================
+// Voting Tests
+// ============================================
+
+#[cfg(test)]
+mod voting_tests {
+    use super::*;
+    use pangi_vault::BalanceCheckpoint;
+
+    const CREATED_AT: i64 = 1_700_000_000;
+    const DAY: i64 = 24 * 60 * 60;
+
+    // Stake with the given (timestamp, amount, counted lock end) balance history
+    fn stake_with_history(history: &[(i64, u64, i64)]) -> StakeRecord {
+        let mut stake = StakeRecord {
+            amount: history.last().map_or(0, |(_, amount, _)| *amount),
+            staked_at: history.first().map_or(0, |(timestamp, _, _)| *timestamp),
+            unlock_at: history.last().map_or(0, |(_, _, unlock_at)| *unlock_at),
+            ..Default::default()
+        };
+        for (i, (timestamp, amount, ve_unlock_at)) in history.iter().enumerate() {
+            stake.balance_checkpoints[i] = BalanceCheckpoint {
+                slot: i as u64,
+                timestamp: *timestamp,
+                amount: *amount,
+                ve_unlock_at: *ve_unlock_at,
+                ve_epoch: 0,
+            };
+        }
+        stake.checkpoint_count = history.len() as u8;
+        stake.checkpoint_head = history.len() as u8;
+        stake
+    }
+
+    #[test]
+    fn top_up_after_proposal_adds_no_weight() {
+        let vault = Vault::default();
+        let lock_end = CREATED_AT + 300 * DAY;
+        let before = stake_with_history(&[(CREATED_AT - 10 * DAY, 1_000_000_000, lock_end)]);
+        let topped_up = stake_with_history(&[
+            (CREATED_AT - 10 * DAY, 1_000_000_000, lock_end),
+            (CREATED_AT + DAY, 50_000_000_000, lock_end),
+        ]);
+
+        let weight = vote_weight(&before, &vault, CREATED_AT).unwrap();
+        assert!(weight > 0);
+        assert_eq!(vote_weight(&topped_up, &vault, CREATED_AT).unwrap(), weight);
+    }
+
+    #[test]
+    fn relock_after_proposal_keeps_the_snapshot_lock_end() {
+        let vault = Vault::default();
+        let short_end = CREATED_AT + 14 * DAY;
+        let short = stake_with_history(&[(CREATED_AT - 10 * DAY, 1_000_000_000, short_end)]);
+        // Withdraw and redeposit into a fresh full-length lock after the proposal
+        let relocked = stake_with_history(&[
+            (CREATED_AT - 10 * DAY, 1_000_000_000, short_end),
+            (CREATED_AT + DAY, 0, 0),
+            (CREATED_AT + 2 * DAY, 1_000_000_000, CREATED_AT + 360 * DAY),
+        ]);
+
+        let weight = vote_weight(&short, &vault, CREATED_AT).unwrap();
+        assert_eq!(
+            weight,
+            pangi_vault::voting_power_at(1_000_000_000, short_end, CREATED_AT).unwrap()
+        );
+        assert_eq!(vote_weight(&relocked, &vault, CREATED_AT).unwrap(), weight);
+    }
+
+    #[test]
+    fn withdrawn_before_proposal_has_no_weight() {
+        let stake = stake_with_history(&[
+            (CREATED_AT - 10 * DAY, 1_000_000_000, CREATED_AT + 300 * DAY),
+            (CREATED_AT - DAY, 0, 0),
+        ]);
+        assert_eq!(vote_weight(&stake, &Vault::default(), CREATED_AT).unwrap(), 0);
+    }
+
+    #[test]
+    fn stake_outside_the_supply_at_snapshot_has_no_weight() {
+        let vault = Vault::default();
+        // Counted lock end 0: opted out, or the vault did not carry voting power
+        let uncounted = stake_with_history(&[(CREATED_AT - 10 * DAY, 1_000_000_000, 0)]);
+        assert_eq!(vote_weight(&uncounted, &vault, CREATED_AT).unwrap(), 0);
+
+        // Counted under an older epoch: the vault's share has since left the supply
+        let counted =
+            stake_with_history(&[(CREATED_AT - 10 * DAY, 1_000_000_000, CREATED_AT + 300 * DAY)]);
+        assert!(vote_weight(&counted, &vault, CREATED_AT).unwrap() > 0);
+        let bumped = Vault {
+            ve_epoch: 1,
+            ..Default::default()
+        };
+        assert_eq!(vote_weight(&counted, &bumped, CREATED_AT).unwrap(), 0);
+    }
+
+    #[test]
+    fn opted_out_stake_has_no_weight() {
+        let vault = Vault::default();
+        let mut stake =
+            stake_with_history(&[(CREATED_AT - 10 * DAY, 1_000_000_000, CREATED_AT + 300 * DAY)]);
+        assert!(vote_weight(&stake, &vault, CREATED_AT).unwrap() > 0);
+
+        stake.ve_opt_out = true;
+        assert_eq!(vote_weight(&stake, &vault, CREATED_AT).unwrap(), 0);
+    }
+
+    #[test]
+    fn threshold_counts_decisive_votes_and_quorum_counts_all() {
+        // Exactly the threshold passes; abstain only helps quorum
+        assert!(proposal_passed(50, 50, 0, 100, 5000).unwrap());
+        assert!(!proposal_passed(49, 51, 0, 100, 5000).unwrap());
+        assert!(proposal_passed(60, 40, 50, 150, 6000).unwrap());
+        assert!(!proposal_passed(60, 40, 49, 150, 6000).unwrap());
+        // No For votes never passes, even with a zero quorum
+        assert!(!proposal_passed(0, 0, 10, 0, 5000).unwrap());
+    }
+
+    #[test]
+    fn only_proposals_in_voting_can_be_canceled() {
+        assert!(validate_cancellable(ProposalState::Voting).is_ok());
+        assert!(validate_cancellable(ProposalState::Queued).is_err());
+        assert!(validate_cancellable(ProposalState::Defeated).is_err());
+        assert!(validate_cancellable(ProposalState::Executed).is_err());
+        assert!(validate_cancellable(ProposalState::Canceled).is_err());
+    }
+
+    #[test]
+    fn governance_params_are_bounded() {
+        assert!(
+            validate_governance_params(1, MIN_THRESHOLD_BPS, MIN_VOTING_PERIOD, MIN_TIMELOCK_DELAY)
+                .is_ok()
+        );
+        assert!(
+            validate_governance_params(MAX_BPS, MAX_BPS, MAX_VOTING_PERIOD, MAX_TIMELOCK_DELAY)
+                .is_ok()
+        );
+        assert!(validate_governance_params(0, 6000, DAY, DAY).is_err());
+        assert!(validate_governance_params(2000, MIN_THRESHOLD_BPS - 1, DAY, DAY).is_err());
+        assert!(validate_governance_params(2000, 6000, MAX_VOTING_PERIOD + 1, DAY).is_err());
+        assert!(validate_governance_params(2000, 6000, DAY, MIN_TIMELOCK_DELAY - 1).is_err());
+    }
+}
@@ -0,0 +1,1702 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer as SystemTransfer};
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+use pangi_vault::program::PangiVault;
+use pangi_vault::{StakeRecord, UnbondingEntry, Vault};
+
+declare_id!("DEvqoTixMDbNN3mNJ3XfvFzqTUBjKdexCbeYQ57bE1hY");
+
+// PANGI Liquid Staking - pooled pangi-vault stake with a stPANGI receipt
+//
+// POOL MODEL:
+// - Anyone deposits PANGI into the pool buffer and receives stPANGI
+// - stake_buffer moves buffer liquidity above the target into one designated vault,
+//   staked by the pool authority PDA via CPI; the PDA cannot vote, so the stake is
+//   opted out of vePANGI voting power and does not weigh on governance quorum
+// - compound re-stakes vault rewards; stPANGI is not rebased, so its exchange
+//   rate (pooled PANGI per stPANGI) grows instead
+// - rewards the vault stake has earned but not yet compounded count toward the
+//   exchange rate (net of the reward fee), so compound timing cannot be front-run
+// - the exchange rate carries a virtual offset (VIRTUAL_OFFSET PANGI backing as many
+//   stPANGI), so donations to the buffer cannot inflate it against later depositors
+//
+// WITHDRAWALS:
+// - withdraw: instant from the buffer, minus the instant withdrawal fee (stays in the pool)
+// - request_withdrawal: burns stPANGI now, fixes the PANGI owed in a ticket that is
+//   claimable after the withdrawal delay once the buffer holds enough; unstake_from_vault
+//   refills the buffer for open tickets once the pool's vault lock has ended (or the
+//   vault is deactivated, which unlocks every stake)
+// - vaults with an unbonding period refill through the vault queue instead:
+//   request_vault_unstake opens an unbonding entry, complete_vault_unstake releases it
+//   into the buffer; unbonding PANGI keeps backing stPANGI meanwhile
+//
+// The designated vault must compound into its stake mint and accept deposits from
+// the pool authority (Open or allowlisted access).
+
+// Security constants
+const MIN_STAKE_AMOUNT: u64 = 1_000_000; // Matches pangi-vault's minimum deposit
+const MAX_REWARD_FEE_BPS: u16 = 2000; // Max 20% of compounded rewards
+const MAX_INSTANT_WITHDRAW_FEE_BPS: u16 = 500; // Max 5% for instant withdrawals
+const MAX_BUFFER_TARGET_BPS: u16 = 5000; // Keep at most 50% of the pool liquid
+const MAX_WITHDRAWAL_DELAY: i64 = 30 * 24 * 60 * 60; // 30 days
+const BPS_DENOMINATOR: u64 = 10000;
+const VIRTUAL_OFFSET: u64 = 1_000_000; // Virtual stPANGI and PANGI in the exchange rate (inflation attack guard)
+
+// Safe math macros for overflow protection
+macro_rules! safe_add {
+    ($a:expr, $b:expr) => {{
+        $a.checked_add($b).ok_or(ErrorCode::Overflow)?
+    }};
+}
+
+macro_rules! safe_sub {
+    ($a:expr, $b:expr) => {{
+        $a.checked_sub($b).ok_or(ErrorCode::Underflow)?
+    }};
+}
+
+macro_rules! safe_mul {
+    ($a:expr, $b:expr) => {{
+        $a.checked_mul($b).ok_or(ErrorCode::Overflow)?
+    }};
+}
+
+macro_rules! safe_div {
+    ($a:expr, $b:expr) => {{
+        let divisor = $b;
+        if divisor == 0 {
+            return Err(ErrorCode::DivisionByZero.into());
+        }
+        $a.checked_div(divisor).ok_or(ErrorCode::Underflow)?
+    }};
+}
+
+macro_rules! safe_percentage {
+    ($amount:expr, $basis_points:expr) => {{
+        safe_div!(safe_mul!($amount, $basis_points as u64), BPS_DENOMINATOR)
+    }};
+}
+
+#[program]
+pub mod pangi_liquid_staking {
+    use super::*;
+
+    /// Create a pool staking into `vault`; the signer must hold the vault's Master NFT and becomes
+    /// the pool admin (one pool per vault, so it cannot be claimed by a stranger first)
+    /// Funds the pool authority PDA with rent for its vault stake record
+    pub fn initialize_pool(
+        ctx: Context<InitializePool>,
+        reward_fee_bps: u16,
+        instant_withdraw_fee_bps: u16,
+        buffer_target_bps: u16,
+        withdrawal_delay: i64,
+    ) -> Result<()> {
+        validate_pool_config(
+            reward_fee_bps,
+            instant_withdraw_fee_bps,
+            buffer_target_bps,
+            withdrawal_delay,
+        )?;
+
+        let vault = &ctx.accounts.vault;
+        require!(vault.is_active, ErrorCode::VaultNotSupported);
+        require!(
+            vault.reward_mint == vault.token_mint && !vault.receipt_mode,
+            ErrorCode::VaultNotSupported
+        );
+
+        // Pool authority pays for the vault stake record on the first stake_buffer
+        let rent = Rent::get()?;
+        let funding = safe_add!(
+            rent.minimum_balance(8 + StakeRecord::INIT_SPACE),
+            rent.minimum_balance(0)
+        );
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                SystemTransfer {
+                    from: ctx.accounts.admin.to_account_info(),
+                    to: ctx.accounts.pool_authority.to_account_info(),
+                },
+            ),
+            funding,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        let clock = Clock::get()?;
+
+        pool.admin = ctx.accounts.admin.key();
+        pool.vault = vault.key();
+        pool.token_mint = vault.token_mint;
+        pool.st_mint = ctx.accounts.st_mint.key();
+        pool.buffer = ctx.accounts.buffer.key();
+        pool.fee_recipient = ctx.accounts.fee_recipient.key();
+        pool.reward_fee_bps = reward_fee_bps;
+        pool.instant_withdraw_fee_bps = instant_withdraw_fee_bps;
+        pool.buffer_target_bps = buffer_target_bps;
+        pool.withdrawal_delay = withdrawal_delay;
+        pool.staked_amount = 0;
+        pool.pending_withdrawals = 0;
+        pool.next_ticket_id = 0;
+        pool.total_rewards = 0;
+        pool.unbonding_amount = 0;
+        pool.created_at = clock.unix_timestamp;
+        pool.bump = ctx.bumps.pool;
+        pool.authority_bump = ctx.bumps.pool_authority;
+
+        emit!(PoolInitializedEvent {
+            pool: pool.key(),
+            vault: pool.vault,
+            st_mint: pool.st_mint,
+            admin: pool.admin,
+            reward_fee_bps,
+            instant_withdraw_fee_bps,
+            buffer_target_bps,
+            withdrawal_delay,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Deposit PANGI into the buffer and mint stPANGI at the current exchange rate
+    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
+        require!(amount >= MIN_STAKE_AMOUNT, ErrorCode::AmountTooSmall);
+
+        let pool = &ctx.accounts.pool;
+        let rewards = unsettled_rewards(
+            pool,
+            &ctx.accounts.vault,
+            &ctx.accounts.stake_record,
+            &ctx.accounts.reward_token_account,
+        )?;
+        let total_pooled = pooled_tokens(pool, ctx.accounts.buffer.amount, rewards)?;
+        let shares = shares_for_deposit(amount, total_pooled, ctx.accounts.st_mint.supply)?;
+        require!(shares > 0, ErrorCode::AmountTooSmall);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.buffer.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let pool_key = pool.key();
+        let seeds = &[b"pool_authority", pool_key.as_ref(), &[pool.authority_bump]];
+        let signer = &[&seeds[..]];
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.st_mint.to_account_info(),
+                    to: ctx.accounts.user_st_account.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                signer,
+            ),
+            shares,
+        )?;
+
+        emit!(DepositedEvent {
+            pool: pool_key,
+            user: ctx.accounts.user.key(),
+            amount,
+            shares,
+            total_pooled: safe_add!(total_pooled, amount),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Burn stPANGI for PANGI paid instantly from the buffer
+    /// The instant withdrawal fee stays in the pool for remaining holders
+    pub fn withdraw(ctx: Context<Withdraw>, shares: u64) -> Result<()> {
+        require!(shares > 0, ErrorCode::AmountTooSmall);
+
+        let pool = &ctx.accounts.pool;
+        let rewards = unsettled_rewards(
+            pool,
+            &ctx.accounts.vault,
+            &ctx.accounts.stake_record,
+            &ctx.accounts.reward_token_account,
+        )?;
+        let total_pooled = pooled_tokens(pool, ctx.accounts.buffer.amount, rewards)?;
+        let amount = tokens_for_shares(shares, total_pooled, ctx.accounts.st_mint.supply)?;
+        let fee = safe_percentage!(amount, pool.instant_withdraw_fee_bps);
+        let payout = safe_sub!(amount, fee);
+
+        // Ticket holders have first claim on the buffer
+        let liquid = ctx.accounts.buffer.amount.saturating_sub(pool.pending_withdrawals);
+        require!(payout <= liquid, ErrorCode::InsufficientBuffer);
+
+        burn_shares(
+            &ctx.accounts.token_program,
+            &ctx.accounts.st_mint,
+            &ctx.accounts.user_st_account,
+            &ctx.accounts.user,
+            shares,
+        )?;
+
+        let pool_key = pool.key();
+        let seeds = &[b"pool_authority", pool_key.as_ref(), &[pool.authority_bump]];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.buffer.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                signer,
+            ),
+            payout,
+        )?;
+
+        emit!(WithdrawnEvent {
+            pool: pool_key,
+            user: ctx.accounts.user.key(),
+            shares,
+            amount: payout,
+            fee,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Burn stPANGI now for a ticket on the PANGI it is worth (no fee)
+    /// Claimable after the withdrawal delay once the buffer can cover it
+    pub fn request_withdrawal(ctx: Context<RequestWithdrawal>, shares: u64) -> Result<()> {
+        require!(shares > 0, ErrorCode::AmountTooSmall);
+
+        let pool = &ctx.accounts.pool;
+        let rewards = unsettled_rewards(
+            pool,
+            &ctx.accounts.vault,
+            &ctx.accounts.stake_record,
+            &ctx.accounts.reward_token_account,
+        )?;
+        let total_pooled = pooled_tokens(pool, ctx.accounts.buffer.amount, rewards)?;
+        let amount = tokens_for_shares(shares, total_pooled, ctx.accounts.st_mint.supply)?;
+        require!(amount > 0, ErrorCode::AmountTooSmall);
+
+        burn_shares(
+            &ctx.accounts.token_program,
+            &ctx.accounts.st_mint,
+            &ctx.accounts.user_st_account,
+            &ctx.accounts.user,
+            shares,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        let clock = Clock::get()?;
+
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.pool = pool.key();
+        ticket.owner = ctx.accounts.user.key();
+        ticket.id = pool.next_ticket_id;
+        ticket.amount = amount;
+        ticket.requested_at = clock.unix_timestamp;
+        ticket.claimable_at = safe_add!(clock.unix_timestamp, pool.withdrawal_delay);
+        ticket.bump = ctx.bumps.ticket;
+
+        pool.next_ticket_id = safe_add!(pool.next_ticket_id, 1);
+        pool.pending_withdrawals = safe_add!(pool.pending_withdrawals, amount);
+
+        emit!(WithdrawalRequestedEvent {
+            pool: pool.key(),
+            user: ticket.owner,
+            ticket_id: ticket.id,
+            shares,
+            amount,
+            claimable_at: ticket.claimable_at,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out a withdrawal ticket and close it (rent back to the owner)
+    pub fn claim_withdrawal(ctx: Context<ClaimWithdrawal>) -> Result<()> {
+        let ticket = &ctx.accounts.ticket;
+        let clock = Clock::get()?;
+
+        require!(
+            clock.unix_timestamp >= ticket.claimable_at,
+            ErrorCode::WithdrawalNotReady
+        );
+        require!(
+            ctx.accounts.buffer.amount >= ticket.amount,
+            ErrorCode::InsufficientBuffer
+        );
+
+        let pool_key = ctx.accounts.pool.key();
+        let seeds = &[
+            b"pool_authority",
+            pool_key.as_ref(),
+            &[ctx.accounts.pool.authority_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.buffer.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                signer,
+            ),
+            ticket.amount,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.pending_withdrawals = safe_sub!(pool.pending_withdrawals, ticket.amount);
+
+        emit!(WithdrawalClaimedEvent {
+            pool: pool_key,
+            user: ticket.owner,
+            ticket_id: ticket.id,
+            amount: ticket.amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Stake buffer liquidity above the target into the vault (permissionless crank)
+    /// `proof` is forwarded for allowlisted vaults, and remaining_accounts (the global cap
+    /// shards of the vault's token_mint) for the vault's global cap check
+    pub fn stake_buffer<'info>(
+        ctx: Context<'_, '_, '_, 'info, StakeBuffer<'info>>,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let buffer_before = ctx.accounts.buffer.amount;
+
+        let liquid = buffer_before.saturating_sub(pool.pending_withdrawals);
+        let total_pooled = pooled_tokens(pool, buffer_before, 0)?;
+        let target = safe_percentage!(total_pooled, pool.buffer_target_bps);
+        let excess = liquid.saturating_sub(target);
+        require!(excess >= MIN_STAKE_AMOUNT, ErrorCode::NothingToStake);
+
+        let pool_key = pool.key();
+        let seeds = &[b"pool_authority", pool_key.as_ref(), &[pool.authority_bump]];
+        let signer = &[&seeds[..]];
+
+        pangi_vault::cpi::deposit_tokens(
+            CpiContext::new_with_signer(
+                ctx.accounts.vault_program.to_account_info(),
+                pangi_vault::cpi::accounts::DepositTokens {
+                    vault: ctx.accounts.vault.to_account_info(),
+                    stake_record: ctx.accounts.stake_record.to_account_info(),
+                    user_token_account: ctx.accounts.buffer.to_account_info(),
+                    vault_token_account: ctx.accounts.vault_token_account.to_account_info(),
+                    ve_supply: ctx.accounts.ve_supply.to_account_info(),
+                    global_caps: ctx.accounts.global_caps.to_account_info(),
+                    global_cap_shard: ctx.accounts.global_cap_shard.to_account_info(),
+                    master_nft_account: None,
+                    referrer_stats: None,
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                },
+                signer,
+            )
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec()),
+            excess,
+            proof,
+        )?;
+
+        // The pool authority cannot vote, so its stake stays out of the voting supply
+        let stake = StakeRecord::try_deserialize(&mut &ctx.accounts.stake_record.try_borrow_data()?[..])?;
+        if !stake.ve_opt_out {
+            pangi_vault::cpi::opt_out_of_voting(
+                CpiContext::new_with_signer(
+                    ctx.accounts.vault_program.to_account_info(),
+                    pangi_vault::cpi::accounts::OptOutOfVoting {
+                        vault: ctx.accounts.vault.to_account_info(),
+                        stake_record: ctx.accounts.stake_record.to_account_info(),
+                        ve_supply: ctx.accounts.ve_supply.to_account_info(),
+                        position_account: None,
+                        authority: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                    signer,
+                ),
+                crate::ID,
+                seeds.iter().map(|seed| seed.to_vec()).collect(),
+            )?;
+        }
+
+        // The vault may partially fill a capped deposit
+        ctx.accounts.buffer.reload()?;
+        let staked = safe_sub!(buffer_before, ctx.accounts.buffer.amount);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.staked_amount = safe_add!(pool.staked_amount, staked);
+
+        emit!(BufferStakedEvent {
+            pool: pool_key,
+            amount: staked,
+            staked_amount: pool.staked_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Compound the pool's vault rewards into its stake (permissionless crank)
+    /// The reward fee is taken as newly minted stPANGI for the fee recipient
+    pub fn compound(ctx: Context<Compound>) -> Result<()> {
+        let staked_before = ctx.accounts.stake_record.amount;
+        let supply = ctx.accounts.st_mint.supply;
+
+        let pool_key = ctx.accounts.pool.key();
+        let seeds = &[
+            b"pool_authority",
+            pool_key.as_ref(),
+            &[ctx.accounts.pool.authority_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        pangi_vault::cpi::compound_rewards(CpiContext::new_with_signer(
+            ctx.accounts.vault_program.to_account_info(),
+            pangi_vault::cpi::accounts::CompoundRewards {
+                vault: ctx.accounts.vault.to_account_info(),
+                stake_record: ctx.accounts.stake_record.to_account_info(),
+                vault_token_account: ctx.accounts.vault_token_account.to_account_info(),
+                reward_token_account: ctx.accounts.reward_token_account.to_account_info(),
+                reward_reserve: ctx
+                    .accounts
+                    .reward_reserve
+                    .as_ref()
+                    .map(|reserve| reserve.to_account_info()),
+                ve_supply: ctx.accounts.ve_supply.to_account_info(),
+                global_cap_shard: ctx.accounts.global_cap_shard.to_account_info(),
+                position_account: None,
+                guardian_config: None,
+                guardian_nft_account: None,
+                caller: ctx.accounts.pool_authority.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+            signer,
+        ))?;
+
+        ctx.accounts.stake_record.reload()?;
+        let rewards = safe_sub!(ctx.accounts.stake_record.amount, staked_before);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.staked_amount = safe_add!(pool.staked_amount, rewards);
+        pool.total_rewards = safe_add!(pool.total_rewards, rewards);
+
+        // Every earned reward is now in the stake
+        let total_pooled = pooled_tokens(pool, ctx.accounts.buffer.amount, 0)?;
+        let fee = safe_percentage!(rewards, pool.reward_fee_bps);
+        let fee_shares = fee_shares_for(fee, total_pooled, supply)?;
+
+        if fee_shares > 0 {
+            token::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    MintTo {
+                        mint: ctx.accounts.st_mint.to_account_info(),
+                        to: ctx.accounts.fee_recipient.to_account_info(),
+                        authority: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                    signer,
+                ),
+                fee_shares,
+            )?;
+        }
+
+        emit!(PoolCompoundedEvent {
+            pool: pool_key,
+            rewards,
+            fee,
+            fee_shares,
+            total_pooled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw from the vault into the buffer to cover open tickets (permissionless crank)
+    /// Only once the pool's vault lock has ended or the vault is deactivated, so the pool
+    /// never pays an early-unlock penalty
+    /// Vaults with an unbonding period go through request_vault_unstake instead
+    pub fn unstake_from_vault(ctx: Context<UnstakeFromVault>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let clock = Clock::get()?;
+
+        // Deactivation unlocks every vault stake penalty-free
+        require!(
+            !ctx.accounts.vault.is_active
+                || clock.unix_timestamp >= ctx.accounts.stake_record.unlock_at,
+            ErrorCode::PoolStakeLocked
+        );
+
+        let shortfall = pool
+            .pending_withdrawals
+            .saturating_sub(ctx.accounts.buffer.amount);
+        let amount = shortfall.min(ctx.accounts.stake_record.amount);
+        require!(amount > 0, ErrorCode::NothingToUnstake);
+
+        let pool_key = pool.key();
+        let seeds = &[b"pool_authority", pool_key.as_ref(), &[pool.authority_bump]];
+        let signer = &[&seeds[..]];
+
+        pangi_vault::cpi::withdraw_tokens(
+            CpiContext::new_with_signer(
+                ctx.accounts.vault_program.to_account_info(),
+                pangi_vault::cpi::accounts::WithdrawTokens {
+                    vault: ctx.accounts.vault.to_account_info(),
+                    stake_record: ctx.accounts.stake_record.to_account_info(),
+                    user_token_account: ctx.accounts.buffer.to_account_info(),
+                    vault_token_account: ctx.accounts.vault_token_account.to_account_info(),
+                    reward_token_account: ctx.accounts.reward_token_account.to_account_info(),
+                    ve_supply: ctx.accounts.ve_supply.to_account_info(),
+                    global_cap_shard: ctx.accounts.global_cap_shard.to_account_info(),
+                    position_account: None,
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.staked_amount = safe_sub!(pool.staked_amount, amount);
+
+        emit!(VaultUnstakedEvent {
+            pool: pool_key,
+            amount,
+            staked_amount: pool.staked_amount,
+            pending_withdrawals: pool.pending_withdrawals,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Open a vault unbonding entry for tickets the buffer and open entries cannot cover
+    /// (permissionless crank, for vaults with an unbonding period)
+    /// Only once the pool's vault lock has ended or the vault is deactivated; the caller tops
+    /// up the pool authority for the entry's rent if needed (it returns to the pool authority
+    /// on completion)
+    pub fn request_vault_unstake(ctx: Context<RequestVaultUnstake>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let clock = Clock::get()?;
+
+        // Deactivation unlocks every vault stake penalty-free
+        require!(
+            !ctx.accounts.vault.is_active
+                || clock.unix_timestamp >= ctx.accounts.stake_record.unlock_at,
+            ErrorCode::PoolStakeLocked
+        );
+
+        let covered = safe_add!(ctx.accounts.buffer.amount, pool.unbonding_amount);
+        let shortfall = pool.pending_withdrawals.saturating_sub(covered);
+        let amount = shortfall.min(ctx.accounts.stake_record.amount);
+        require!(amount > 0, ErrorCode::NothingToUnstake);
+
+        // Pool authority pays the entry rent and must stay rent exempt
+        let rent = Rent::get()?;
+        let needed = safe_add!(
+            rent.minimum_balance(8 + UnbondingEntry::INIT_SPACE),
+            rent.minimum_balance(0)
+        );
+        let top_up = needed.saturating_sub(ctx.accounts.pool_authority.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    SystemTransfer {
+                        from: ctx.accounts.caller.to_account_info(),
+                        to: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+
+        let unbonding_before = ctx.accounts.stake_record.unbonding_amount;
+
+        let pool_key = pool.key();
+        let seeds = &[b"pool_authority", pool_key.as_ref(), &[pool.authority_bump]];
+        let signer = &[&seeds[..]];
+
+        pangi_vault::cpi::request_unstake(
+            CpiContext::new_with_signer(
+                ctx.accounts.vault_program.to_account_info(),
+                pangi_vault::cpi::accounts::RequestUnstake {
+                    vault: ctx.accounts.vault.to_account_info(),
+                    stake_record: ctx.accounts.stake_record.to_account_info(),
+                    unbonding_entry: ctx.accounts.unbonding_entry.to_account_info(),
+                    vault_token_account: ctx.accounts.vault_token_account.to_account_info(),
+                    reward_token_account: ctx.accounts.reward_token_account.to_account_info(),
+                    ve_supply: ctx.accounts.ve_supply.to_account_info(),
+                    global_cap_shard: ctx.accounts.global_cap_shard.to_account_info(),
+                    position_account: None,
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+        )?;
+
+        ctx.accounts.stake_record.reload()?;
+        let unbonding = safe_sub!(ctx.accounts.stake_record.unbonding_amount, unbonding_before);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.staked_amount = safe_sub!(pool.staked_amount, amount);
+        pool.unbonding_amount = safe_add!(pool.unbonding_amount, unbonding);
+
+        emit!(VaultUnstakeRequestedEvent {
+            pool: pool_key,
+            amount: unbonding,
+            staked_amount: pool.staked_amount,
+            unbonding_amount: pool.unbonding_amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Release one of the pool's vault unbonding entries into the buffer (permissionless crank)
+    pub fn complete_vault_unstake(ctx: Context<CompleteVaultUnstake>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let buffer_before = ctx.accounts.buffer.amount;
+
+        let pool_key = pool.key();
+        let seeds = &[b"pool_authority", pool_key.as_ref(), &[pool.authority_bump]];
+        let signer = &[&seeds[..]];
+
+        pangi_vault::cpi::complete_unstake(CpiContext::new_with_signer(
+            ctx.accounts.vault_program.to_account_info(),
+            pangi_vault::cpi::accounts::CompleteUnstake {
+                vault: ctx.accounts.vault.to_account_info(),
+                stake_record: ctx.accounts.stake_record.to_account_info(),
+                unbonding_entry: ctx.accounts.unbonding_entry.to_account_info(),
+                user_token_account: ctx.accounts.buffer.to_account_info(),
+                vault_token_account: ctx.accounts.vault_token_account.to_account_info(),
+                position_account: None,
+                authority: ctx.accounts.pool_authority.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+            signer,
+        ))?;
+
+        ctx.accounts.buffer.reload()?;
+        let released = safe_sub!(ctx.accounts.buffer.amount, buffer_before);
+
+        let pool = &mut ctx.accounts.pool;
+        pool.unbonding_amount = safe_sub!(pool.unbonding_amount, released);
+
+        emit!(VaultUnstakeCompletedEvent {
+            pool: pool_key,
+            amount: released,
+            unbonding_amount: pool.unbonding_amount,
+            pending_withdrawals: pool.pending_withdrawals,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Admin updates fees, buffer target, withdrawal delay and fee recipient
+    pub fn set_pool_config(
+        ctx: Context<SetPoolConfig>,
+        reward_fee_bps: u16,
+        instant_withdraw_fee_bps: u16,
+        buffer_target_bps: u16,
+        withdrawal_delay: i64,
+    ) -> Result<()> {
+        validate_pool_config(
+            reward_fee_bps,
+            instant_withdraw_fee_bps,
+            buffer_target_bps,
+            withdrawal_delay,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.reward_fee_bps = reward_fee_bps;
+        pool.instant_withdraw_fee_bps = instant_withdraw_fee_bps;
+        pool.buffer_target_bps = buffer_target_bps;
+        pool.withdrawal_delay = withdrawal_delay;
+        pool.fee_recipient = ctx.accounts.fee_recipient.key();
+
+        emit!(PoolConfigUpdatedEvent {
+            pool: pool.key(),
+            fee_recipient: pool.fee_recipient,
+            reward_fee_bps,
+            instant_withdraw_fee_bps,
+            buffer_target_bps,
+            withdrawal_delay,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+fn validate_pool_config(
+    reward_fee_bps: u16,
+    instant_withdraw_fee_bps: u16,
+    buffer_target_bps: u16,
+    withdrawal_delay: i64,
+) -> Result<()> {
+    require!(reward_fee_bps <= MAX_REWARD_FEE_BPS, ErrorCode::FeeTooHigh);
+    require!(
+        instant_withdraw_fee_bps <= MAX_INSTANT_WITHDRAW_FEE_BPS,
+        ErrorCode::FeeTooHigh
+    );
+    require!(
+        buffer_target_bps <= MAX_BUFFER_TARGET_BPS,
+        ErrorCode::InvalidBufferTarget
+    );
+    require!(
+        (0..=MAX_WITHDRAWAL_DELAY).contains(&withdrawal_delay),
+        ErrorCode::InvalidWithdrawalDelay
+    );
+
+    Ok(())
+}
+
+// PANGI backing stPANGI: buffer plus the vault stake, its unbonding entries and its
+// uncompounded rewards, net of tickets
+// Tickets may exceed the buffer while they wait for unstake_from_vault, so add before subtracting
+fn pooled_tokens(pool: &Pool, buffer_amount: u64, unsettled_rewards: u64) -> Result<u64> {
+    let held = safe_add!(safe_add!(buffer_amount, pool.staked_amount), pool.unbonding_amount);
+    Ok(safe_sub!(safe_add!(held, unsettled_rewards), pool.pending_withdrawals))
+}
+
+// Vault rewards the pool's stake has earned but not compounded yet, net of the reward fee
+// (compound mints the fee as stPANGI); the stake record only exists once the pool has staked
+// Capped at what the vault reward pool can actually pay out
+fn unsettled_rewards(
+    pool: &Pool,
+    vault: &Vault,
+    stake_record: &AccountInfo,
+    reward_token_account: &TokenAccount,
+) -> Result<u64> {
+    if stake_record.data_is_empty() {
+        return Ok(0);
+    }
+    let stake = StakeRecord::try_deserialize(&mut &stake_record.try_borrow_data()?[..])?;
+    let rewards = pangi_vault::pending_stake_rewards(vault, &stake, Clock::get()?.unix_timestamp)?
+        .min(pangi_vault::spendable_rewards(vault, reward_token_account));
+    Ok(safe_sub!(rewards, safe_percentage!(rewards, pool.reward_fee_bps)))
+}
+
+fn burn_shares<'info>(
+    token_program: &Program<'info, Token>,
+    st_mint: &Account<'info, Mint>,
+    user_st_account: &Account<'info, TokenAccount>,
+    user: &Signer<'info>,
+    shares: u64,
+) -> Result<()> {
+    token::burn(
+        CpiContext::new(
+            token_program.to_account_info(),
+            Burn {
+                mint: st_mint.to_account_info(),
+                from: user_st_account.to_account_info(),
+                authority: user.to_account_info(),
+            },
+        ),
+        shares,
+    )
+}
+
+/// stPANGI minted for depositing `amount` into a pool holding `total_pooled` PANGI
+/// Pro rata with VIRTUAL_OFFSET added to both sides (rounded down), so 1:1 into an empty pool
+/// PANGI donated to the buffer mostly accrues to the virtual shares: inflating the rate to
+/// round later depositors down costs the donor far more than it takes from them
+/// Outstanding stPANGI with nothing backing it cannot be priced, so deposits are refused
+pub fn shares_for_deposit(amount: u64, total_pooled: u64, supply: u64) -> Result<u64> {
+    require!(supply == 0 || total_pooled > 0, ErrorCode::PoolUnbacked);
+    let shares = safe_div!(
+        safe_mul!(amount as u128, safe_add!(supply as u128, VIRTUAL_OFFSET as u128)),
+        safe_add!(total_pooled as u128, VIRTUAL_OFFSET as u128)
+    );
+    u64::try_from(shares).map_err(|_| ErrorCode::Overflow.into())
+}
+
+/// PANGI redeemable for `shares` stPANGI at the same offset rate (rounded down, in the pool's favour)
+/// Never more than the pool holds, even when it is worth less than its supply
+pub fn tokens_for_shares(shares: u64, total_pooled: u64, supply: u64) -> Result<u64> {
+    require!(shares <= supply, ErrorCode::InsufficientShares);
+    let tokens = safe_div!(
+        safe_mul!(shares as u128, safe_add!(total_pooled as u128, VIRTUAL_OFFSET as u128)),
+        safe_add!(supply as u128, VIRTUAL_OFFSET as u128)
+    );
+    u64::try_from(tokens.min(total_pooled as u128)).map_err(|_| ErrorCode::Overflow.into())
+}
+
+/// stPANGI minted so that the fee recipient owns `fee` of `total_pooled`
+/// shares = fee × (supply + offset) / (total_pooled + offset - fee), diluting holders by exactly `fee`
+pub fn fee_shares_for(fee: u64, total_pooled: u64, supply: u64) -> Result<u64> {
+    if fee == 0 || supply == 0 {
+        return Ok(0);
+    }
+    let shares = safe_div!(
+        safe_mul!(fee as u128, safe_add!(supply as u128, VIRTUAL_OFFSET as u128)),
+        safe_sub!(safe_add!(total_pooled as u128, VIRTUAL_OFFSET as u128), fee as u128)
+    );
+    u64::try_from(shares).map_err(|_| ErrorCode::Overflow.into())
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Pool::INIT_SPACE,
+        seeds = [b"pool", vault.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: Data-less PDA; signs vault CPIs and owns the buffer and stPANGI mint
+    #[account(
+        mut,
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    pub vault: Account<'info, Vault>,
+    #[account(
+        constraint = master_nft_account.mint == vault.nft_mint
+            && master_nft_account.owner == admin.key()
+            && master_nft_account.amount == 1 @ ErrorCode::NotVaultMaster
+    )]
+    pub master_nft_account: Account<'info, TokenAccount>,  // Admin's vault Master NFT token account
+    #[account(
+        constraint = token_mint.key() == vault.token_mint @ ErrorCode::InvalidMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        mint::decimals = token_mint.decimals,
+        mint::authority = pool_authority,
+        seeds = [b"st_mint", pool.key().as_ref()],
+        bump
+    )]
+    pub st_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        token::mint = token_mint,
+        token::authority = pool_authority,
+        seeds = [b"buffer", pool.key().as_ref()],
+        bump
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        constraint = fee_recipient.mint == st_mint.key() @ ErrorCode::InvalidMint
+    )]
+    pub fee_recipient: Account<'info, TokenAccount>,  // stPANGI account receiving reward fees
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: PDA signer only
+    #[account(
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump = pool.authority_bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = st_mint.key() == pool.st_mint @ ErrorCode::InvalidMint
+    )]
+    pub st_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        constraint = buffer.key() == pool.buffer @ ErrorCode::InvalidBuffer
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ ErrorCode::Unauthorized
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_st_account.mint == pool.st_mint @ ErrorCode::InvalidMint
+    )]
+    pub user_st_account: Account<'info, TokenAccount>,
+    #[account(
+        constraint = vault.key() == pool.vault @ ErrorCode::InvalidVault
+    )]
+    pub vault: Account<'info, Vault>,
+    /// CHECK: Pool's vault stake record (uninitialized until the first stake_buffer)
+    #[account(
+        seeds = [b"stake", vault.key().as_ref(), pool_authority.key().as_ref()],
+        bump,
+        seeds::program = pangi_vault::ID
+    )]
+    pub stake_record: UncheckedAccount<'info>,
+    #[account(
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVault
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>, // Caps uncompounded rewards at what it holds
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: PDA signer only
+    #[account(
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump = pool.authority_bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = st_mint.key() == pool.st_mint @ ErrorCode::InvalidMint
+    )]
+    pub st_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        constraint = buffer.key() == pool.buffer @ ErrorCode::InvalidBuffer
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_token_account.mint == pool.token_mint @ ErrorCode::InvalidMint
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_st_account.owner == user.key() @ ErrorCode::Unauthorized
+    )]
+    pub user_st_account: Account<'info, TokenAccount>,
+    #[account(
+        constraint = vault.key() == pool.vault @ ErrorCode::InvalidVault
+    )]
+    pub vault: Account<'info, Vault>,
+    /// CHECK: Pool's vault stake record (uninitialized until the first stake_buffer)
+    #[account(
+        seeds = [b"stake", vault.key().as_ref(), pool_authority.key().as_ref()],
+        bump,
+        seeds::program = pangi_vault::ID
+    )]
+    pub stake_record: UncheckedAccount<'info>,
+    #[account(
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVault
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>, // Caps uncompounded rewards at what it holds
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + WithdrawalTicket::INIT_SPACE,
+        seeds = [b"ticket", pool.key().as_ref(), &pool.next_ticket_id.to_le_bytes()],
+        bump
+    )]
+    pub ticket: Account<'info, WithdrawalTicket>,
+    #[account(
+        mut,
+        constraint = st_mint.key() == pool.st_mint @ ErrorCode::InvalidMint
+    )]
+    pub st_mint: Account<'info, Mint>,
+    #[account(
+        constraint = buffer.key() == pool.buffer @ ErrorCode::InvalidBuffer
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_st_account.owner == user.key() @ ErrorCode::Unauthorized
+    )]
+    pub user_st_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA only (stake record seed)
+    #[account(
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump = pool.authority_bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(
+        constraint = vault.key() == pool.vault @ ErrorCode::InvalidVault
+    )]
+    pub vault: Account<'info, Vault>,
+    /// CHECK: Pool's vault stake record (uninitialized until the first stake_buffer)
+    #[account(
+        seeds = [b"stake", vault.key().as_ref(), pool_authority.key().as_ref()],
+        bump,
+        seeds::program = pangi_vault::ID
+    )]
+    pub stake_record: UncheckedAccount<'info>,
+    #[account(
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVault
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>, // Caps uncompounded rewards at what it holds
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: PDA signer only
+    #[account(
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump = pool.authority_bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"ticket", pool.key().as_ref(), &ticket.id.to_le_bytes()],
+        bump = ticket.bump,
+        constraint = ticket.owner == user.key() @ ErrorCode::Unauthorized
+    )]
+    pub ticket: Account<'info, WithdrawalTicket>,
+    #[account(
+        mut,
+        constraint = buffer.key() == pool.buffer @ ErrorCode::InvalidBuffer
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_token_account.mint == pool.token_mint @ ErrorCode::InvalidMint
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct StakeBuffer<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: Data-less PDA; stakes in the vault and pays its stake record rent
+    #[account(
+        mut,
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump = pool.authority_bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = buffer.key() == pool.buffer @ ErrorCode::InvalidBuffer
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault.key() == pool.vault @ ErrorCode::InvalidVault
+    )]
+    pub vault: Account<'info, Vault>,
+    /// CHECK: Pool's stake record, created on first stake and validated by pangi-vault
+    #[account(mut)]
+    pub stake_record: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub vault_token_account: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub ve_supply: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    pub global_caps: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub global_cap_shard: UncheckedAccount<'info>,
+    pub vault_program: Program<'info, PangiVault>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Compound<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: PDA signer only
+    #[account(
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump = pool.authority_bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = st_mint.key() == pool.st_mint @ ErrorCode::InvalidMint
+    )]
+    pub st_mint: Account<'info, Mint>,
+    #[account(
+        constraint = buffer.key() == pool.buffer @ ErrorCode::InvalidBuffer
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = fee_recipient.key() == pool.fee_recipient @ ErrorCode::InvalidFeeRecipient
+    )]
+    pub fee_recipient: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault.key() == pool.vault @ ErrorCode::InvalidVault
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), pool_authority.key().as_ref()],
+        bump,
+        seeds::program = pangi_vault::ID
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub vault_token_account: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub reward_token_account: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault; the vault's insurance reserve, drawn on when its reward pool is short
+    #[account(mut)]
+    pub reward_reserve: Option<UncheckedAccount<'info>>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub ve_supply: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub global_cap_shard: UncheckedAccount<'info>,
+    pub vault_program: Program<'info, PangiVault>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeFromVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: PDA signer only
+    #[account(
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump = pool.authority_bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = buffer.key() == pool.buffer @ ErrorCode::InvalidBuffer
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault.key() == pool.vault @ ErrorCode::InvalidVault
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), pool_authority.key().as_ref()],
+        bump,
+        seeds::program = pangi_vault::ID
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub vault_token_account: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub reward_token_account: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub ve_supply: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub global_cap_shard: UncheckedAccount<'info>,
+    pub vault_program: Program<'info, PangiVault>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RequestVaultUnstake<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: Data-less PDA; pays the unbonding entry rent
+    #[account(
+        mut,
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump = pool.authority_bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(
+        constraint = buffer.key() == pool.buffer @ ErrorCode::InvalidBuffer
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault.key() == pool.vault @ ErrorCode::InvalidVault
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), pool_authority.key().as_ref()],
+        bump,
+        seeds::program = pangi_vault::ID
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    /// CHECK: New unbonding entry, created and validated by pangi-vault
+    #[account(mut)]
+    pub unbonding_entry: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub vault_token_account: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub reward_token_account: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub ve_supply: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub global_cap_shard: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    pub vault_program: Program<'info, PangiVault>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CompleteVaultUnstake<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: Data-less PDA; owns the buffer and receives the entry rent back
+    #[account(
+        mut,
+        seeds = [b"pool_authority", pool.key().as_ref()],
+        bump = pool.authority_bump
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        constraint = buffer.key() == pool.buffer @ ErrorCode::InvalidBuffer
+    )]
+    pub buffer: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault.key() == pool.vault @ ErrorCode::InvalidVault
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), pool_authority.key().as_ref()],
+        bump,
+        seeds::program = pangi_vault::ID
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub unbonding_entry: UncheckedAccount<'info>,
+    /// CHECK: Validated by pangi-vault
+    #[account(mut)]
+    pub vault_token_account: UncheckedAccount<'info>,
+    pub vault_program: Program<'info, PangiVault>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetPoolConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"pool", pool.vault.as_ref()],
+        bump = pool.bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        constraint = fee_recipient.mint == pool.st_mint @ ErrorCode::InvalidMint
+    )]
+    pub fee_recipient: Account<'info, TokenAccount>,
+    pub admin: Signer<'info>,
+}
+
+/// Pool - one liquid staking pool per designated vault
+#[account]
+#[derive(InitSpace, Default)]
+pub struct Pool {
+    pub admin: Pubkey,                 // May update fees and config
+    pub vault: Pubkey,                 // pangi-vault vault the pool stakes into
+    pub token_mint: Pubkey,            // PANGI
+    pub st_mint: Pubkey,               // stPANGI receipt mint
+    pub buffer: Pubkey,                // PANGI token account for deposits and withdrawals
+    pub fee_recipient: Pubkey,         // stPANGI account receiving reward fees
+    pub reward_fee_bps: u16,           // Share of compounded rewards taken as fee
+    pub instant_withdraw_fee_bps: u16, // Fee on instant withdrawals (stays in the pool)
+    pub buffer_target_bps: u16,        // Share of pooled PANGI kept liquid in the buffer
+    pub withdrawal_delay: i64,         // Minimum wait for withdrawal tickets
+    pub staked_amount: u64,            // PANGI staked in the vault by the pool
+    pub pending_withdrawals: u64,      // PANGI owed to open tickets (held in the buffer)
+    pub next_ticket_id: u64,           // Seed id of the next withdrawal ticket
+    pub total_rewards: u64,            // Rewards compounded over the pool's lifetime
+    pub created_at: i64,               // Pool creation timestamp
+    pub bump: u8,                      // PDA bump seed
+    pub authority_bump: u8,            // Pool authority PDA bump seed
+    pub unbonding_amount: u64,         // PANGI in the pool's vault unbonding entries
+}
+
+/// WithdrawalTicket - PANGI owed for stPANGI burned by request_withdrawal
+#[account]
+#[derive(InitSpace)]
+pub struct WithdrawalTicket {
+    pub pool: Pubkey,                  // Parent pool
+    pub owner: Pubkey,                 // Recipient
+    pub id: u64,                       // Seed id
+    pub amount: u64,                   // PANGI owed (fixed at request)
+    pub requested_at: i64,             // Request timestamp
+    pub claimable_at: i64,             // requested_at + withdrawal_delay
+    pub bump: u8,                      // PDA bump seed
+}
+
+// Events
+#[event]
+pub struct PoolInitializedEvent {
+    pub pool: Pubkey,
+    pub vault: Pubkey,
+    pub st_mint: Pubkey,
+    pub admin: Pubkey,
+    pub reward_fee_bps: u16,
+    pub instant_withdraw_fee_bps: u16,
+    pub buffer_target_bps: u16,
+    pub withdrawal_delay: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DepositedEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub total_pooled: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawnEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub shares: u64,
+    pub amount: u64,              // PANGI paid out (after fee)
+    pub fee: u64,                 // Instant withdrawal fee left in the pool
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalRequestedEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub ticket_id: u64,
+    pub shares: u64,
+    pub amount: u64,
+    pub claimable_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalClaimedEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub ticket_id: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BufferStakedEvent {
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolCompoundedEvent {
+    pub pool: Pubkey,
+    pub rewards: u64,
+    pub fee: u64,
+    pub fee_shares: u64,          // stPANGI minted to the fee recipient
+    pub total_pooled: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultUnstakedEvent {
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub pending_withdrawals: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultUnstakeRequestedEvent {
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub unbonding_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultUnstakeCompletedEvent {
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub unbonding_amount: u64,
+    pub pending_withdrawals: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolConfigUpdatedEvent {
+    pub pool: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub reward_fee_bps: u16,
+    pub instant_withdraw_fee_bps: u16,
+    pub buffer_target_bps: u16,
+    pub withdrawal_delay: i64,
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Unauthorized: caller is not the authority")]
+    Unauthorized,
+    #[msg("Arithmetic overflow detected")]
+    Overflow,
+    #[msg("Arithmetic underflow detected")]
+    Underflow,
+    #[msg("Division by zero")]
+    DivisionByZero,
+    #[msg("Amount too small")]
+    AmountTooSmall,
+    #[msg("Fee exceeds maximum")]
+    FeeTooHigh,
+    #[msg("Buffer target exceeds maximum (50%)")]
+    InvalidBufferTarget,
+    #[msg("Invalid withdrawal delay (0 to 30 days)")]
+    InvalidWithdrawalDelay,
+    #[msg("Vault must be active, compound into its stake mint and not use receipt mode")]
+    VaultNotSupported,
+    #[msg("Account is not the pool's vault")]
+    InvalidVault,
+    #[msg("Account is not the pool buffer")]
+    InvalidBuffer,
+    #[msg("Token account has the wrong mint")]
+    InvalidMint,
+    #[msg("Account is not the pool fee recipient")]
+    InvalidFeeRecipient,
+    #[msg("Buffer cannot cover this withdrawal - request a delayed withdrawal")]
+    InsufficientBuffer,
+    #[msg("More stPANGI than exists")]
+    InsufficientShares,
+    #[msg("Withdrawal ticket is not claimable yet")]
+    WithdrawalNotReady,
+    #[msg("Buffer is at or below its target")]
+    NothingToStake,
+    #[msg("Buffer already covers open withdrawal tickets")]
+    NothingToUnstake,
+    #[msg("Pool's vault stake is still locked")]
+    PoolStakeLocked,
+    #[msg("stPANGI is outstanding but the pool holds no PANGI")]
+    PoolUnbacked,
+    #[msg("Signer does not hold the vault's Master NFT")]
+    NotVaultMaster,
+}
+
+// ============================================
+// Exchange Rate Tests
+// ============================================
+
+#[cfg(test)]
+mod exchange_rate_tests {
+    use super::*;
+
+    #[test]
+    fn first_deposit_is_one_to_one() {
+        assert_eq!(shares_for_deposit(5_000_000, 0, 0).unwrap(), 5_000_000);
+    }
+
+    #[test]
+    fn unbacked_supply_refuses_deposits() {
+        // An emptied pool with stPANGI left would otherwise mint 1:1 and hand the
+        // depositor's PANGI to the old holders
+        assert!(shares_for_deposit(5_000_000, 0, 1_000_000).is_err());
+        assert_eq!(shares_for_deposit(5_000_000, 1_000_000, 1_000_000).unwrap(), 5_000_000);
+    }
+
+    #[test]
+    fn rewards_raise_redemption_value() {
+        // 100 PANGI backing 100 stPANGI, then 10 PANGI of rewards compound
+        // (the virtual offset's 1 PANGI keeps its sliver of the rewards)
+        let supply = 100_000_000_000;
+        let redeemed = tokens_for_shares(supply / 2, 110_000_000_000, supply).unwrap();
+        assert_eq!(redeemed, 54_999_950_000);
+
+        // A later depositor gets fewer shares per PANGI
+        let shares = shares_for_deposit(11_000_000_000, 110_000_000_000, supply).unwrap();
+        assert_eq!(shares, 10_000_009_090);
+    }
+
+    #[test]
+    fn buffer_donation_cannot_round_depositors_down() {
+        // Attacker deposits, withdraws all but 1 stPANGI, then donates to the buffer
+        let donation = 1_000_000_000_000u64;
+        let (total, supply) = (1 + donation, 1u64);
+
+        let amount = 2_000_000_000u64;
+        let shares = shares_for_deposit(amount, total, supply).unwrap();
+        assert!(shares > 0);
+
+        // The depositor loses at most one share's worth (0.1% here), not up to half
+        let back = tokens_for_shares(shares, total + amount, supply + shares).unwrap();
+        assert!(amount - back <= amount / 1000);
+
+        // The donation went to the virtual shares: the attacker's stPANGI is worth ~1/1,000,000 of it
+        let attacker = tokens_for_shares(supply, total + amount, supply + shares).unwrap();
+        assert!(attacker < donation / 100_000);
+    }
+
+    #[test]
+    fn round_trip_never_gains() {
+        let (total, supply) = (123_456_789_012, 98_765_432_109);
+        for amount in [1_000_000u64, 7_777_777, 1_000_000_000, 55_555_555_555] {
+            let shares = shares_for_deposit(amount, total, supply).unwrap();
+            let back = tokens_for_shares(shares, total + amount, supply + shares).unwrap();
+            assert!(back <= amount);
+        }
+    }
+
+    #[test]
+    fn pooled_tokens_covers_tickets_beyond_the_buffer() {
+        // Tickets waiting on unstake_from_vault can exceed the buffer
+        let pool = Pool {
+            staked_amount: 90_000_000_000,
+            pending_withdrawals: 15_000_000_000,
+            ..Default::default()
+        };
+        assert_eq!(pooled_tokens(&pool, 5_000_000_000, 0).unwrap(), 80_000_000_000);
+        assert_eq!(pooled_tokens(&pool, 15_000_000_000, 0).unwrap(), 90_000_000_000);
+        assert_eq!(pooled_tokens(&pool, 0, 0).unwrap(), 75_000_000_000);
+    }
+
+    #[test]
+    fn unbonding_stake_keeps_backing_shares() {
+        // Moving stake into a vault unbonding entry leaves the exchange rate unchanged
+        let staked = Pool {
+            staked_amount: 90_000_000_000,
+            pending_withdrawals: 15_000_000_000,
+            ..Default::default()
+        };
+        let unbonding = Pool {
+            staked_amount: 75_000_000_000,
+            unbonding_amount: 15_000_000_000,
+            pending_withdrawals: 15_000_000_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            pooled_tokens(&staked, 0, 0).unwrap(),
+            pooled_tokens(&unbonding, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn uncompounded_rewards_are_priced_in() {
+        let pool = Pool {
+            staked_amount: 100_000_000_000,
+            ..Default::default()
+        };
+        let supply = 100_000_000_000;
+        let (rewards, fee) = (10_000_000_000u64, 1_000_000_000u64);
+
+        // Before compound: net rewards already back the shares
+        let before = pooled_tokens(&pool, 0, rewards - fee).unwrap();
+
+        // After compound: rewards are staked and the fee is minted as stPANGI
+        let compounded = Pool {
+            staked_amount: pool.staked_amount + rewards,
+            ..Default::default()
+        };
+        let after = pooled_tokens(&compounded, 0, 0).unwrap();
+        let fee_shares = fee_shares_for(fee, after, supply).unwrap();
+
+        // A depositor right before compound is priced the same as right after
+        let amount = 5_000_000_000;
+        let shares_before = shares_for_deposit(amount, before, supply).unwrap();
+        let shares_after = shares_for_deposit(amount, after, supply + fee_shares).unwrap();
+        assert!(shares_before.abs_diff(shares_after) <= 1);
+    }
+
+    #[test]
+    fn fee_shares_dilute_by_exactly_the_fee() {
+        let (total, supply, fee) = (110_000_000_000u64, 100_000_000_000u64, 1_000_000_000u64);
+        let fee_shares = fee_shares_for(fee, total, supply).unwrap();
+        let fee_value = tokens_for_shares(fee_shares, total, supply + fee_shares).unwrap();
+        assert!(fee - fee_value <= 1);
+    }
+}
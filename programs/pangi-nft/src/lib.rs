@@ -1,5 +1,6 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, Token, TokenAccount};
+use anchor_lang::system_program;
+use anchor_spl::token::Mint;
 
 declare_id!("etpBw57TYbPLMiTVB16iRUNKoSvfux2Gi1Mf9omXnYE");
 
@@ -7,28 +8,32 @@ declare_id!("etpBw57TYbPLMiTVB16iRUNKoSvfux2Gi1Mf9omXnYE");
 const MIN_EVOLUTION_COOLDOWN: i64 = 60; // 1 minute minimum
 const MAX_EVOLUTION_COOLDOWN: i64 = 30 * 24 * 60 * 60; // 30 days maximum
 const MAX_EVOLUTION_COUNT: u32 = 100; // Prevent infinite evolution
-const MAX_GENERATION: u16 = 1000; // Maximum generation number
 const MAX_TOTAL_NFTS: u64 = 10000; // ✅ ADD: Maximum total NFTs that can be minted
+const LEGACY_HATCHLING_SPACE: usize = 8 + Hatchling::INIT_SPACE - 32; // Hatchling size before locked_by
 
 // Safe math macros for overflow protection
+#[allow(unused_macros)]
 macro_rules! safe_add {
     ($a:expr, $b:expr) => {{
         $a.checked_add($b).ok_or(ErrorCode::Overflow)?
     }};
 }
 
+#[allow(unused_macros)]
 macro_rules! safe_sub {
     ($a:expr, $b:expr) => {{
         $a.checked_sub($b).ok_or(ErrorCode::Underflow)?
     }};
 }
 
+#[allow(unused_macros)]
 macro_rules! safe_mul {
     ($a:expr, $b:expr) => {{
         $a.checked_mul($b).ok_or(ErrorCode::Overflow)?
     }};
 }
 
+#[allow(unused_macros)]
 macro_rules! safe_div {
     ($a:expr, $b:expr) => {{
         let divisor = $b;
@@ -121,7 +126,7 @@ pub mod pangi_nft {
             } else {
                 // Adult: matching_nft_id should be in hatchling range (1-1500)
                 require!(
-                    matching_nft_id >= 1 && matching_nft_id <= 1500,
+                    (1..=1500).contains(&matching_nft_id),
                     ErrorCode::InvalidMatchingId
                 );
             }
@@ -137,6 +142,7 @@ pub mod pangi_nft {
         hatchling.traits = TraitSet::default();
         hatchling.generation = 1;
         hatchling.is_locked = false;
+        hatchling.locked_by = Pubkey::default();
         hatchling.series = series;
         hatchling.matching_nft_id = matching_nft_id;
         hatchling.is_special_edition = is_special_edition;
@@ -240,6 +246,9 @@ pub mod pangi_nft {
         Ok(())
     }
 
+    /// Lock a Hatchling on behalf of an optional `locker` (e.g. a staking program PDA)
+    /// Only the locker can unlock it again; without one the owner is the locker,
+    /// so callers that pass only (hatchling, authority) keep working
     pub fn lock_hatchling(ctx: Context<LockHatchling>) -> Result<()> {
         let hatchling = &mut ctx.accounts.hatchling;
 
@@ -251,10 +260,15 @@ pub mod pangi_nft {
         require!(!hatchling.is_locked, ErrorCode::AlreadyLocked);
 
         hatchling.is_locked = true;
+        hatchling.locked_by = ctx
+            .accounts
+            .locker
+            .as_ref()
+            .map_or(hatchling.authority, |locker| locker.key());
 
         emit!(HatchlingLockedEvent {
             nft_mint: hatchling.nft_mint,
-            locked_by: ctx.accounts.authority.key(),
+            locked_by: hatchling.locked_by,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
@@ -264,14 +278,21 @@ pub mod pangi_nft {
     pub fn unlock_hatchling(ctx: Context<UnlockHatchling>) -> Result<()> {
         let hatchling = &mut ctx.accounts.hatchling;
 
+        require!(hatchling.is_locked, ErrorCode::NotLocked);
+
+        // Hatchlings locked before locked_by existed are unlocked by their owner
+        let locker = if hatchling.locked_by == Pubkey::default() {
+            hatchling.authority
+        } else {
+            hatchling.locked_by
+        };
         require!(
-            ctx.accounts.authority.key() == hatchling.authority,
+            ctx.accounts.authority.key() == locker,
             ErrorCode::Unauthorized
         );
 
-        require!(hatchling.is_locked, ErrorCode::NotLocked);
-
         hatchling.is_locked = false;
+        hatchling.locked_by = Pubkey::default();
 
         emit!(HatchlingUnlockedEvent {
             nft_mint: hatchling.nft_mint,
@@ -281,6 +302,83 @@ pub mod pangi_nft {
 
         Ok(())
     }
+
+    /// Resize a Hatchling created before `locked_by` was added (permissionless, payer funds rent)
+    /// Legacy Hatchlings cannot be loaded by any other instruction until migrated
+    pub fn migrate_hatchling(ctx: Context<MigrateHatchling>) -> Result<()> {
+        let hatchling = ctx.accounts.hatchling.to_account_info();
+        let new_space = 8 + Hatchling::INIT_SPACE;
+
+        let rent_due = Rent::get()?
+            .minimum_balance(new_space)
+            .saturating_sub(hatchling.lamports());
+        if rent_due > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: hatchling.clone(),
+                    },
+                ),
+                rent_due,
+            )?;
+        }
+
+        // New tail is zeroed: locked_by = default (legacy locks stay owner-unlockable)
+        hatchling.resize(new_space)?;
+
+        emit!(HatchlingMigratedEvent {
+            hatchling: ctx.accounts.hatchling.key(),
+            payer: ctx.accounts.payer.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_mint_paused(ctx: Context<UpdateGlobalConfig>, paused: bool) -> Result<()> {
+        let config = &mut ctx.accounts.global_config;
+
+        require!(
+            ctx.accounts.authority.key() == config.authority,
+            ErrorCode::Unauthorized
+        );
+
+        config.mint_paused = paused;
+
+        emit!(MintPausedEvent {
+            paused,
+            authority: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Hand the global config to a new authority (e.g. the governance authority PDA)
+    pub fn transfer_config_authority(
+        ctx: Context<UpdateGlobalConfig>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.global_config;
+
+        require!(
+            ctx.accounts.authority.key() == config.authority,
+            ErrorCode::Unauthorized
+        );
+
+        let old_authority = config.authority;
+        config.authority = new_authority;
+
+        emit!(ConfigAuthorityTransferredEvent {
+            old_authority,
+            new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
 }
 
 // Helper functions with deterministic randomness
@@ -404,7 +502,8 @@ pub struct LockHatchling<'info> {
         bump
     )]
     pub hatchling: Account<'info, Hatchling>,
-    pub authority: Signer<'info>,
+    pub authority: Signer<'info>,       // Hatchling owner
+    pub locker: Option<Signer<'info>>,  // Only signer allowed to unlock (default: authority)
 }
 
 #[derive(Accounts)]
@@ -415,6 +514,34 @@ pub struct UnlockHatchling<'info> {
         bump
     )]
     pub hatchling: Account<'info, Hatchling>,
+    pub authority: Signer<'info>,       // Must be the locker recorded by lock_hatchling
+}
+
+#[derive(Accounts)]
+pub struct MigrateHatchling<'info> {
+    /// CHECK: legacy-layout Hatchling (does not deserialize until resized);
+    /// owner, discriminator and legacy size are checked
+    #[account(
+        mut,
+        owner = crate::ID,
+        constraint = hatchling.data_len() == LEGACY_HATCHLING_SPACE
+            && hatchling.try_borrow_data()?.starts_with(Hatchling::DISCRIMINATOR)
+            @ ErrorCode::NotLegacyHatchling
+    )]
+    pub hatchling: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGlobalConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
     pub authority: Signer<'info>,
 }
 
@@ -443,6 +570,7 @@ pub struct Hatchling {
     pub series: u8,              // 1 = Main Collection, 2 = Special Edition
     pub matching_nft_id: u16,    // For Series 1: Hatchling #1 ↔ Adult #1501
     pub is_special_edition: bool, // True for promotional NFTs
+    pub locked_by: Pubkey,       // Signer that may unlock (default when unlocked or legacy-locked)
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
@@ -512,6 +640,27 @@ pub struct HatchlingUnlockedEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct HatchlingMigratedEvent {
+    pub hatchling: Pubkey,
+    pub payer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MintPausedEvent {
+    pub paused: bool,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConfigAuthorityTransferredEvent {
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Invalid evolution stage for this operation")]
@@ -552,4 +701,6 @@ pub enum ErrorCode {
     InvalidMatchingId,
     #[msg("Main collection NFTs cannot evolve (Hatchling and Adult are separate mints)")]
     MainCollectionNoEvolution,
+    #[msg("Account is not a legacy-layout Hatchling")]
+    NotLegacyHatchling,
 }
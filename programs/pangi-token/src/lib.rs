@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
 use anchor_spl::token::{self, Token, TokenAccount, Transfer};
 
 declare_id!("BDSjfUUwEVHxJ3WLxHgNbKddCXEFVX3thS72fg6F4EaA");
@@ -7,8 +8,11 @@ declare_id!("BDSjfUUwEVHxJ3WLxHgNbKddCXEFVX3thS72fg6F4EaA");
 const MAX_TAX_RATE: u16 = 1000; // 10% maximum
 const MIN_TRANSFER_AMOUNT: u64 = 1; // Minimum 1 lamport
 const MAX_TRANSFER_AMOUNT: u64 = 1_000_000_000_000_000; // 1M tokens with 9 decimals
+const MAX_RESERVE_BPS: u16 = 5000; // At most half of the tax goes to the reward reserve
+const LEGACY_TAX_CONFIG_SPACE: usize = 8 + TaxConfig::INIT_SPACE - 32 - 2;
 
 // Safe math macros for overflow protection
+#[allow(unused_macros)]
 macro_rules! safe_add {
     ($a:expr, $b:expr) => {{
         $a.checked_add($b).ok_or(ErrorCode::Overflow)?
@@ -125,8 +129,35 @@ pub mod pangi_token {
             net_amount,
         )?;
 
-        // Transfer tax to conservation fund if any
-        if tax_amount > 0 {
+        // Split the tax between the vault reward reserve and the conservation fund
+        // Callers that do not pass the reserve send the whole tax to the conservation fund
+        let reserve_bps = if ctx.accounts.reward_reserve.is_some() {
+            config.reserve_bps
+        } else {
+            0
+        };
+        let (reserve_amount, conservation_amount) = split_tax(tax_amount, reserve_bps)?;
+
+        if let Some(reward_reserve) = ctx.accounts.reward_reserve.as_ref().filter(|_| reserve_amount > 0) {
+            require!(
+                reward_reserve.key() == config.reward_reserve,
+                ErrorCode::RewardReserveMismatch
+            );
+
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.from.to_account_info(),
+                        to: reward_reserve.to_account_info(),
+                        authority: ctx.accounts.authority.to_account_info(),
+                    },
+                ),
+                reserve_amount,
+            )?;
+        }
+
+        if conservation_amount > 0 {
             token::transfer(
                 CpiContext::new(
                     ctx.accounts.token_program.to_account_info(),
@@ -136,7 +167,7 @@ pub mod pangi_token {
                         authority: ctx.accounts.authority.to_account_info(),
                     },
                 ),
-                tax_amount,
+                conservation_amount,
             )?;
         }
 
@@ -146,6 +177,7 @@ pub mod pangi_token {
             to: ctx.accounts.to.key(),
             amount: net_amount,
             tax_amount,
+            reserve_amount,
             tax_rate,
             transfer_type,
             conservation_fund: ctx.accounts.conservation_fund.key(),
@@ -176,6 +208,8 @@ pub mod pangi_token {
         tax_config.whale_tax_rate = whale_tax_rate;
         tax_config.whale_transfer_threshold = whale_threshold;
         tax_config.conservation_fund = ctx.accounts.conservation_fund.key();
+        tax_config.reward_reserve = Pubkey::default();
+        tax_config.reserve_bps = 0;
         tax_config.last_updated = Clock::get()?.unix_timestamp;
         
         // Calculate and set max tax per transfer (10% of max transfer amount)
@@ -243,12 +277,114 @@ pub mod pangi_token {
 
         Ok(())
     }
+
+    /// Hand the tax config to a new authority (e.g. the governance authority PDA)
+    pub fn transfer_tax_authority(
+        ctx: Context<UpdateTaxConfig>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let tax_config = &mut ctx.accounts.tax_config;
+
+        require!(
+            ctx.accounts.authority.key() == tax_config.authority,
+            ErrorCode::Unauthorized
+        );
+
+        let old_authority = tax_config.authority;
+        tax_config.authority = new_authority;
+        tax_config.last_updated = Clock::get()?.unix_timestamp;
+
+        emit!(TaxAuthorityTransferredEvent {
+            old_authority,
+            new_authority,
+            timestamp: tax_config.last_updated,
+        });
+
+        Ok(())
+    }
+
+    /// Route `reserve_bps` of every transfer tax to a vault's insurance reserve
+    /// (its `reward_reserve` token account); the rest still goes to the conservation fund
+    /// The reserve must hold the taxed mint (the conservation fund's mint)
+    pub fn set_tax_reserve(ctx: Context<SetTaxReserve>, reserve_bps: u16) -> Result<()> {
+        let tax_config = &mut ctx.accounts.tax_config;
+
+        require!(
+            ctx.accounts.authority.key() == tax_config.authority,
+            ErrorCode::Unauthorized
+        );
+        validate_tax_reserve(
+            ctx.accounts.reward_reserve.mint,
+            ctx.accounts.conservation_fund.mint,
+            reserve_bps,
+        )?;
+
+        let reward_reserve = ctx.accounts.reward_reserve.key();
+        tax_config.reward_reserve = reward_reserve;
+        tax_config.reserve_bps = reserve_bps;
+        tax_config.last_updated = Clock::get()?.unix_timestamp;
+
+        emit!(TaxReserveUpdatedEvent {
+            authority: ctx.accounts.authority.key(),
+            reward_reserve,
+            reserve_bps,
+            timestamp: tax_config.last_updated,
+        });
+
+        Ok(())
+    }
+
+    /// Grow a tax config created before the reserve fields were appended
+    pub fn migrate_tax_config(ctx: Context<MigrateTaxConfig>) -> Result<()> {
+        let tax_config = ctx.accounts.tax_config.to_account_info();
+        let new_space = 8 + TaxConfig::INIT_SPACE;
+
+        let rent_due = Rent::get()?
+            .minimum_balance(new_space)
+            .saturating_sub(tax_config.lamports());
+        if rent_due > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: tax_config.clone(),
+                    },
+                ),
+                rent_due,
+            )?;
+        }
+
+        // New tail is zeroed: no reserve, the whole tax keeps going to the conservation fund
+        tax_config.resize(new_space)?;
+
+        emit!(TaxConfigMigratedEvent {
+            tax_config: ctx.accounts.tax_config.key(),
+            payer: ctx.accounts.payer.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+// A tax reserve must hold the taxed mint, or every reserve transfer would fail
+fn validate_tax_reserve(reserve_mint: Pubkey, taxed_mint: Pubkey, reserve_bps: u16) -> Result<()> {
+    require!(reserve_bps <= MAX_RESERVE_BPS, ErrorCode::ReserveShareTooHigh);
+    require!(reserve_mint == taxed_mint, ErrorCode::RewardReserveMintMismatch);
+    Ok(())
+}
+
+// Split a tax into (reward reserve share, conservation fund share)
+fn split_tax(tax_amount: u64, reserve_bps: u16) -> Result<(u64, u64)> {
+    let reserve_amount = safe_percentage!(tax_amount, reserve_bps);
+    Ok((reserve_amount, safe_sub!(tax_amount, reserve_amount)))
 }
 
 // Helper function to determine transfer type
 fn determine_transfer_type(
-    from_owner: &Pubkey,
-    to_owner: &Pubkey,
+    _from_owner: &Pubkey,
+    _to_owner: &Pubkey,
     amount: u64,
     whale_threshold: u64,
 ) -> Result<TransferType> {
@@ -282,6 +418,9 @@ pub struct TransferWithTax<'info> {
         bump
     )]
     pub tax_config: Account<'info, TaxConfig>,
+    /// Vault reward reserve; without it the reserve share goes to the conservation fund
+    #[account(mut)]
+    pub reward_reserve: Option<Account<'info, TokenAccount>>,
 }
 
 #[derive(Accounts)]
@@ -312,6 +451,41 @@ pub struct UpdateTaxConfig<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct SetTaxReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"tax_config"],
+        bump
+    )]
+    pub tax_config: Account<'info, TaxConfig>,
+    // Holds the taxed mint
+    #[account(
+        constraint = conservation_fund.key() == tax_config.conservation_fund @ ErrorCode::ConservationFundMismatch
+    )]
+    pub conservation_fund: Account<'info, TokenAccount>,
+    pub reward_reserve: Account<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateTaxConfig<'info> {
+    /// CHECK: legacy-layout TaxConfig (does not deserialize until resized);
+    /// seeds, discriminator and legacy size are checked
+    #[account(
+        mut,
+        seeds = [b"tax_config"],
+        bump,
+        constraint = tax_config.data_len() == LEGACY_TAX_CONFIG_SPACE
+            && tax_config.try_borrow_data()?.starts_with(TaxConfig::DISCRIMINATOR)
+            @ ErrorCode::NotLegacyTaxConfig
+    )]
+    pub tax_config: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct TaxConfig {
@@ -323,6 +497,8 @@ pub struct TaxConfig {
     pub max_tax_per_transfer: u64,
     pub conservation_fund: Pubkey,
     pub last_updated: i64,
+    pub reward_reserve: Pubkey, // Vault insurance reserve fed by the tax
+    pub reserve_bps: u16,       // Share of each tax routed to reward_reserve
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
@@ -339,6 +515,7 @@ pub struct TransferWithTaxEvent {
     pub to: Pubkey,
     pub amount: u64,
     pub tax_amount: u64,
+    pub reserve_amount: u64, // Part of tax_amount sent to the reward reserve
     pub tax_rate: u16,
     pub transfer_type: TransferType,
     pub conservation_fund: Pubkey,
@@ -366,6 +543,28 @@ pub struct TaxConfigUpdatedEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct TaxAuthorityTransferredEvent {
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TaxReserveUpdatedEvent {
+    pub authority: Pubkey,
+    pub reward_reserve: Pubkey,
+    pub reserve_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TaxConfigMigratedEvent {
+    pub tax_config: Pubkey,
+    pub payer: Pubkey,
+    pub timestamp: i64,
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Tax rate exceeds maximum allowed (10%)")]
@@ -396,6 +595,16 @@ pub enum ErrorCode {
     SlippageExceeded,
     #[msg("Division by zero")]
     DivisionByZero,
+    #[msg("Reserve share exceeds maximum allowed (50%)")]
+    ReserveShareTooHigh,
+    #[msg("Reward reserve account does not match the tax config")]
+    RewardReserveMismatch,
+    #[msg("Reward reserve must hold the taxed mint")]
+    RewardReserveMintMismatch,
+    #[msg("Conservation fund does not match the tax config")]
+    ConservationFundMismatch,
+    #[msg("Account is not a legacy-layout tax config")]
+    NotLegacyTaxConfig,
 }
 
 // ============================================
@@ -602,14 +811,14 @@ mod slippage_tests {
         let amount = u64::MAX;
         let tax_rate = 200u16; // 2%
         
-        // Use u128 to prevent overflow
-        let tax_u128 = amount as u128 * tax_rate as u128 / 10000;
+        // The intermediate product would overflow in u64
+        let product = amount as u128 * tax_rate as u128;
+        assert!(product > u64::MAX as u128);
         
-        // Should not overflow in u128
+        // Use u128 to prevent overflow; the final tax fits back into u64
+        let tax_u128 = product / 10000;
         assert!(tax_u128 > 0);
-        
-        // But would overflow in u64
-        assert!(tax_u128 > u64::MAX as u128);
+        assert!(tax_u128 <= u64::MAX as u128);
     }
 
     #[test]
@@ -661,6 +870,31 @@ mod slippage_tests {
     // Integration Tests
     // ============================================
 
+    #[test]
+    fn test_split_tax_routes_reserve_share() {
+        let tax = 20_000_000;
+
+        assert_eq!(split_tax(tax, 0).unwrap(), (0, tax));
+        assert_eq!(split_tax(tax, 2500).unwrap(), (5_000_000, 15_000_000));
+        assert_eq!(split_tax(tax, MAX_RESERVE_BPS).unwrap(), (10_000_000, 10_000_000));
+
+        // Rounding dust stays with the conservation fund
+        let (reserve, conservation) = split_tax(7, 3333).unwrap();
+        assert_eq!((reserve, conservation), (2, 5));
+        assert_eq!(reserve + conservation, 7);
+    }
+
+    #[test]
+    fn test_tax_reserve_must_hold_the_taxed_mint() {
+        let pangi_mint = Pubkey::new_unique();
+        let cath_mint = Pubkey::new_unique();
+
+        assert!(validate_tax_reserve(pangi_mint, pangi_mint, 2500).is_ok());
+        // A vault reserve in its reward mint would make every reserve transfer fail
+        assert!(validate_tax_reserve(cath_mint, pangi_mint, 2500).is_err());
+        assert!(validate_tax_reserve(pangi_mint, pangi_mint, MAX_RESERVE_BPS + 1).is_err());
+    }
+
     #[test]
     fn test_complete_transfer_with_slippage() {
         // Simulate a complete transfer with slippage protection
@@ -763,7 +997,7 @@ mod slippage_tests {
     #[test]
     fn test_small_transfer_scenario() {
         // Test with very small amounts
-        let amount = 1000u64; // 0.000001 tokens
+        let amount = 49u64; // 0.000000049 tokens
         let tax_rate = 200u16; // 2%
         
         // Calculate tax
@@ -849,11 +1083,7 @@ mod slippage_tests {
             return 0;
         }
         
-        let diff = if expected > actual {
-            expected - actual
-        } else {
-            actual - expected
-        };
+        let diff = expected.abs_diff(actual);
         
         ((diff as u128 * 10000) / expected as u128) as u64
     }
@@ -865,8 +1095,6 @@ mod slippage_tests {
 
 #[cfg(test)]
 mod benchmark_tests {
-    use super::*;
-
     #[test]
     fn bench_tax_calculation() {
         let amount = 1_000_000_000u64;
@@ -879,7 +1107,6 @@ mod benchmark_tests {
         }
         
         // If this completes without timeout, performance is acceptable
-        assert!(true);
     }
 
     #[test]
@@ -894,6 +1121,5 @@ mod benchmark_tests {
         }
         
         // If this completes without timeout, performance is acceptable
-        assert!(true);
     }
 }
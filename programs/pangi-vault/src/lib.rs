@@ -1,5 +1,9 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, spl_token::instruction::AuthorityType, CloseAccount, MintTo, SetAuthority, Token, TokenAccount, Mint, Transfer};
+use pangi_nft::{Hatchling, Rarity};
+use pangi_token::TaxConfig;
+use solana_sha256_hasher::hashv;
 
 declare_id!("5ghkR1LyUMA4K8Dhit2ssqnBbWsZv3sWgvbFSoTKnhw2");
 
@@ -30,12 +34,41 @@ declare_id!("5ghkR1LyUMA4K8Dhit2ssqnBbWsZv3sWgvbFSoTKnhw2");
 // Security constants
 const MIN_STAKE_AMOUNT: u64 = 1_000_000; // 0.001 tokens (9 decimals)
 const MAX_STAKE_AMOUNT: u64 = 1_000_000_000_000_000; // 1M tokens
-const MIN_LOCK_DURATION: i64 = 60; // 1 minute
-const MAX_LOCK_DURATION: i64 = 365 * 24 * 60 * 60; // 1 year
+const MIN_LOCK_DURATION: i64 = 60; // 1 minute (registry default)
+const MAX_LOCK_DURATION: i64 = 365 * 24 * 60 * 60; // 1 year (registry default and hard ceiling)
+const MAX_REWARD_RATE: u16 = 10000; // 100% APY (registry default and hard ceiling)
+const VAULT_INDEX_PAGE_SIZE: usize = 32; // Vaults listed per registry index page
+const MAX_UNBONDING_PERIOD: i64 = 30 * 24 * 60 * 60; // 30 days (0 = instant withdraw)
+const MAX_REWARD_EPOCHS: usize = 16; // Reward schedule entries per vault (incl. the creation rate)
 const REWARD_RATE_DENOMINATOR: u64 = 10000; // For basis points
 const CLAIM_COOLDOWN: i64 = 60 * 60; // 1 hour between claims
+const VAULT_CRANK_INTERVAL: i64 = 60 * 60; // 1 hour of accrual between update_vault cranks
+const CRANK_TIP_BPS: u16 = 10; // 0.1% of base rewards is set aside for update_vault crankers (stakers get the rest)
+const REWARD_INDEX_PRECISION: u64 = 1_000_000_000_000; // Vault reward index scale (per 1e12 staked units)
 const DEPOSIT_COOLDOWN: i64 = 60; // 1 minute between deposits
-const EARLY_UNLOCK_PENALTY_BPS: u16 = 1500; // 15% penalty for early unlock
+const EARLY_UNLOCK_PENALTY_BPS: u16 = 1500; // Default 15% flat penalty for early unlock
+const MAX_PENALTY_STEPS: usize = 8; // Stepped penalty curve entries per vault
+const MAX_PRINCIPAL_PENALTY_BPS: u16 = 2500; // Max 25% of principal when penalizing principal
+const MAX_REFERRAL_BPS: u16 = 2000; // Max 20% of a referred staker's rewards paid to the referrer
+const MAX_RESERVE_BPS: u16 = 5000; // Max 50% of early-unlock penalties routed to the insurance reserve
+const SCALES_MULTIPLIER_DENOMINATOR: u64 = 10000; // Scales points tier multiplier (10000 = 1x)
+pub const GLOBAL_SHARD_COUNT: u8 = 8; // ve_supply / global cap shards; vaults spread across them by address
+const MAX_BALANCE_CHECKPOINTS: usize = 32; // Balance history entries kept per stake (oldest dropped)
+const VOTING_POWER_MAX_LOCK: i64 = 365 * 24 * 60 * 60; // vePANGI: full voting power at 1 year remaining
+const VE_WEEK: i64 = 7 * 24 * 60 * 60; // vePANGI lock ends are rounded down to week boundaries
+const VE_SLOPE_WEEKS: usize = 54; // Slope-change ring: every week a MAX_LOCK_DURATION lock can end in
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+const NFT_WEIGHT_DENOMINATOR: u64 = 100; // Rarity weight 100 = 1x base NFT reward
+const MAX_NFT_BOOST_BPS: u16 = 2000; // Max +20% APR boost from staked NFTs
+
+// Guardian permission bits (GuardianConfig.permissions)
+pub const GUARDIAN_PERMISSION_CLAIM: u8 = 1 << 0; // Claim rewards to the stake owner
+pub const GUARDIAN_PERMISSION_REPORT: u8 = 1 << 1; // Report events to the public address
+pub const GUARDIAN_PERMISSION_COMPOUND: u8 = 1 << 2; // Compound rewards into the stake
+const GUARDIAN_PERMISSIONS_ALL: u8 =
+    GUARDIAN_PERMISSION_CLAIM | GUARDIAN_PERMISSION_REPORT | GUARDIAN_PERMISSION_COMPOUND;
+const GUARDIAN_REPORT_COOLDOWN: i64 = 60 * 60; // 1 hour between reports per stake
+const GUARDIAN_CHALLENGE_WINDOW: i64 = 3 * 24 * 60 * 60; // 3 days to challenge a dispute (and bond exit delay)
 
 // Safe math macros for overflow protection
 macro_rules! safe_add {
@@ -80,20 +113,34 @@ pub mod pangi_vault {
         ctx: Context<CreateVault>,
         reward_rate: u16,
         lock_duration: i64,
+        unbonding_period: i64,
     ) -> Result<()> {
-        // Input validation
+        let registry = &ctx.accounts.registry;
+
+        // Input validation against the registry defaults
         require!(
-            reward_rate <= 10000, // Max 100% APY
+            reward_rate <= registry.max_reward_rate,
             ErrorCode::RewardRateTooHigh
         );
         require!(
-            lock_duration >= MIN_LOCK_DURATION,
+            lock_duration >= registry.min_lock_duration,
             ErrorCode::LockDurationTooShort
         );
         require!(
-            lock_duration <= MAX_LOCK_DURATION,
+            lock_duration <= registry.max_lock_duration,
             ErrorCode::LockDurationTooLong
         );
+        require!(
+            (0..=MAX_UNBONDING_PERIOD).contains(&unbonding_period),
+            ErrorCode::InvalidUnbondingPeriod
+        );
+
+        // Creator must hold the Master NFT the vault is seeded by
+        verify_master(
+            ctx.accounts.nft_mint.key(),
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
 
         let vault = &mut ctx.accounts.vault;
         let clock = Clock::get()?;
@@ -102,6 +149,8 @@ pub mod pangi_vault {
         vault.authority = ctx.accounts.authority.key();
         vault.token_mint = ctx.accounts.token_mint.key();
         vault.vault_token_account = ctx.accounts.vault_token_account.key();
+        vault.reward_mint = ctx.accounts.reward_mint.key();
+        vault.reward_token_account = ctx.accounts.reward_token_account.key();
         vault.total_staked = 0;
         vault.reward_rate = reward_rate;
         vault.lock_duration = lock_duration;
@@ -110,13 +159,83 @@ pub mod pangi_vault {
         vault.total_penalties_collected = 0;
         vault.is_active = true;
         vault.bump = ctx.bumps.vault;
+        vault.nft_reward_rate = 0;
+        vault.total_nfts_staked = 0;
+        vault.auto_compound = false;
+        vault.access_mode = VaultAccessMode::OwnerOnly;
+        vault.allowlist_root = [0u8; 32];
+        vault.max_total_staked = 0;
+        vault.max_user_stake = 0;
+        vault.allow_partial_fill = false;
+        vault.deactivated_at = 0;
+        vault.stake_count = 0;
+        vault.unbonding_period = unbonding_period;
+        vault.total_unbonding = 0;
+        vault.receipt_mode = false;
+        vault.reward_epochs = [RewardEpoch::default(); MAX_REWARD_EPOCHS];
+        vault.reward_epochs[0] = RewardEpoch {
+            start_time: clock.unix_timestamp,
+            rate: reward_rate,
+        };
+        vault.reward_epoch_count = 1;
+        vault.penalty_curve = PenaltyCurve::Flat;
+        vault.penalty_bps = EARLY_UNLOCK_PENALTY_BPS;
+        vault.penalty_steps = [PenaltyStep::default(); MAX_PENALTY_STEPS];
+        vault.penalty_step_count = 0;
+        vault.penalize_principal = false;
+        vault.total_principal_penalties = 0;
+        vault.voting_approved = false;
+        vault.ve_curve = VotingPowerCurve {
+            last_checkpoint: clock.unix_timestamp,
+            ..Default::default()
+        };
+        vault.ve_epoch = 0;
+        vault.referral_bps = 0;
+        vault.referral_pending = 0;
+        vault.reward_reserve = Pubkey::default();
+        vault.reserve_bps = 0;
+        vault.reserve_pending = 0;
+        vault.total_reserve_funded = 0;
+        vault.total_reserve_drawn = 0;
+        vault.total_rewards_accrued = 0;
+        vault.reward_index = 0;
+        vault.total_crank_tips = 0;
+        vault.crank_tips_pending = 0;
+        vault.last_crank_at = clock.unix_timestamp;
+
+        // Register the vault in the next index slot
+        let registry = &mut ctx.accounts.registry;
+        let index_page = &mut ctx.accounts.vault_index_page;
+        let registry_index = registry.vault_count;
+        let page = registry_index / VAULT_INDEX_PAGE_SIZE as u64;
+        let slot = (registry_index % VAULT_INDEX_PAGE_SIZE as u64) as usize;
+        if slot == 0 {
+            index_page.page = page;
+            index_page.count = 0;
+            index_page.bump = ctx.bumps.vault_index_page;
+        }
+        index_page.entries[slot] = VaultIndexEntry {
+            vault: vault.key(),
+            nft_mint: vault.nft_mint,
+            reward_rate,
+            status: VaultStatus::Active,
+        };
+        index_page.count = safe_add!(index_page.count, 1);
+        registry.vault_count = safe_add!(registry.vault_count, 1);
+        vault.registry_index = registry_index;
+        vault.global_shard = vault.key().to_bytes()[0] % GLOBAL_SHARD_COUNT;
         
         emit!(VaultCreatedEvent {
             nft_mint: vault.nft_mint,
-            vault: ctx.accounts.vault.key(),
+            vault: vault.key(),
             authority: vault.authority,
+            token_mint: vault.token_mint,
+            reward_mint: vault.reward_mint,
             reward_rate,
             lock_duration,
+            unbonding_period,
+            registry_index,
+            global_shard: vault.global_shard,
             timestamp: vault.created_at,
         });
         
@@ -124,11 +243,16 @@ pub mod pangi_vault {
     }
 
     /// Deposit tokens into vault (self-custody staking)
-    /// Tokens are transferred to vault but user maintains custody through Master NFT
+    /// Each depositor gets their own stake record; who may deposit depends on the vault's access mode
+    /// `proof` is the Merkle proof for Allowlist vaults (ignored otherwise)
+    /// Optional `referrer_stats` records the referrer on the stake's first deposit
+    /// While a global cap is set, remaining_accounts must hold every global cap shard
+    /// of the vault's token_mint, in shard order
     /// Master NFT holder can configure Guardian reporting for this stake
     pub fn deposit_tokens(
         ctx: Context<DepositTokens>,
         amount: u64,
+        proof: Vec<[u8; 32]>,
     ) -> Result<()> {
         let vault = &mut ctx.accounts.vault;
         let stake = &ctx.accounts.stake_record;
@@ -136,12 +260,15 @@ pub mod pangi_vault {
 
         // Validate vault is active
         require!(vault.is_active, ErrorCode::VaultInactive);
+        require!(!vault.receipt_mode, ErrorCode::ReceiptModeEnabled);
 
-        // Validate authority (Master NFT holder)
-        require!(
-            ctx.accounts.authority.key() == vault.authority,
-            ErrorCode::VaultAuthorityMismatch
-        );
+        // Validate depositor against the vault access mode
+        verify_deposit_access(
+            vault,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+            &proof,
+        )?;
         
         // ✅ DEPOSIT COOLDOWN CHECK (prevent spam)
         if stake.amount > 0 {
@@ -157,6 +284,17 @@ pub mod pangi_vault {
         require!(amount >= MIN_STAKE_AMOUNT, ErrorCode::AmountTooSmall);
         require!(amount <= MAX_STAKE_AMOUNT, ErrorCode::AmountTooLarge);
 
+        // ✅ TVL CAPS: vault, per-user and global
+        let amount = capped_deposit_amount(
+            vault,
+            &ctx.accounts.global_caps,
+            ctx.remaining_accounts,
+            stake.amount,
+            ctx.accounts.authority.key(),
+            amount,
+            clock.unix_timestamp,
+        )?;
+
         // Check user has sufficient balance
         require!(
             ctx.accounts.user_token_account.amount >= amount,
@@ -180,51 +318,220 @@ pub mod pangi_vault {
         )?;
 
         // Update vault state
+        advance_vault_rewards(vault, clock.unix_timestamp)?;
         vault.total_staked = new_total;
-        vault.last_reward_update = clock.unix_timestamp;
+
+        let global_cap_shard = &mut ctx.accounts.global_cap_shard;
+        global_cap_shard.total_staked = safe_add!(global_cap_shard.total_staked, amount);
 
         // Create or update stake record
         let stake = &mut ctx.accounts.stake_record;
+        if stake.authority == Pubkey::default() {
+            vault.stake_count = safe_add!(vault.stake_count, 1);
+
+            // Referrer is fixed on first deposit (ignored on later deposits)
+            if let Some(referrer_stats) = ctx.accounts.referrer_stats.as_mut() {
+                require!(
+                    referrer_stats.vault == vault.key()
+                        && referrer_stats.referrer != ctx.accounts.authority.key(),
+                    ErrorCode::InvalidReferrer
+                );
+                stake.referrer = referrer_stats.referrer;
+                referrer_stats.referred_count = safe_add!(referrer_stats.referred_count, 1);
+
+                emit!(ReferralRecordedEvent {
+                    vault: vault.key(),
+                    referrer: referrer_stats.referrer,
+                    staker: ctx.accounts.authority.key(),
+                    referred_count: referrer_stats.referred_count,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+        }
         if stake.amount == 0 {
-            // New stake
-            stake.vault = ctx.accounts.vault.key();
+            // New stake (unclaimed pending_rewards of an emptied stake are kept)
+            stake.vault = vault.key();
             stake.authority = ctx.accounts.authority.key();
             stake.amount = amount;
             stake.staked_at = clock.unix_timestamp;
             stake.unlock_at = safe_add!(clock.unix_timestamp, vault.lock_duration);
             stake.last_claim = clock.unix_timestamp;
+            stake.rewards_accrued_at = clock.unix_timestamp;
+            stake.reward_index_paid = vault.reward_index;
+            stake.points_accrued_at = clock.unix_timestamp;
             stake.total_claimed = 0;
+            stake.total_compounded = 0;
         } else {
-            // Add to existing stake
+            // Top-up: settle rewards on the old balance first
+            settle_stake_rewards(stake, vault, clock.unix_timestamp)?;
             stake.amount = safe_add!(stake.amount, amount);
         }
 
+        // Checkpoint vePANGI voting power for the new balance
+        checkpoint_voting_power(&mut ctx.accounts.ve_supply, vault, stake, clock.unix_timestamp)?;
+        record_balance_checkpoint(stake, &clock);
+
         emit!(TokensDepositedEvent {
-            vault: ctx.accounts.vault.key(),
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            amount,
+            total_staked: vault.total_staked,
+            unlock_at: stake.unlock_at,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Deposit into a receipt-mode vault as a new transferable position
+    /// Mints a position NFT to the depositor; whoever holds it controls the stake
+    /// (withdraw, claim, compound, unstake), so locked positions can be traded
+    /// Each deposit opens a separate position (no top-ups)
+    /// remaining_accounts as for deposit_tokens
+    pub fn deposit_position(
+        ctx: Context<DepositPosition>,
+        amount: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let clock = Clock::get()?;
+
+        require!(vault.is_active, ErrorCode::VaultInactive);
+        require!(vault.receipt_mode, ErrorCode::ReceiptModeDisabled);
+
+        verify_deposit_access(
+            vault,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+            &proof,
+        )?;
+
+        require!(amount >= MIN_STAKE_AMOUNT, ErrorCode::AmountTooSmall);
+        require!(amount <= MAX_STAKE_AMOUNT, ErrorCode::AmountTooLarge);
+
+        // Per-user cap applies per position (positions are transferable)
+        let amount = capped_deposit_amount(
+            vault,
+            &ctx.accounts.global_caps,
+            ctx.remaining_accounts,
+            0,
+            ctx.accounts.authority.key(),
+            amount,
+            clock.unix_timestamp,
+        )?;
+
+        require!(
+            ctx.accounts.user_token_account.amount >= amount,
+            ErrorCode::InsufficientBalance
+        );
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.vault_token_account.to_account_info(),
+                    authority: ctx.accounts.authority.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        advance_vault_rewards(vault, clock.unix_timestamp)?;
+        vault.total_staked = safe_add!(vault.total_staked, amount);
+        vault.stake_count = safe_add!(vault.stake_count, 1);
+
+        let global_cap_shard = &mut ctx.accounts.global_cap_shard;
+        global_cap_shard.total_staked = safe_add!(global_cap_shard.total_staked, amount);
+
+        // Position stake records are keyed by the position mint
+        let position_mint = ctx.accounts.position_mint.key();
+        let stake = &mut ctx.accounts.stake_record;
+        stake.vault = vault.key();
+        stake.authority = position_mint;
+        stake.amount = amount;
+        stake.staked_at = clock.unix_timestamp;
+        stake.unlock_at = safe_add!(clock.unix_timestamp, vault.lock_duration);
+        stake.last_claim = clock.unix_timestamp;
+        stake.rewards_accrued_at = clock.unix_timestamp;
+        stake.reward_index_paid = vault.reward_index;
+        stake.points_accrued_at = clock.unix_timestamp;
+        stake.total_claimed = 0;
+        stake.total_compounded = 0;
+        stake.position_mint = position_mint;
+
+        checkpoint_voting_power(&mut ctx.accounts.ve_supply, vault, stake, clock.unix_timestamp)?;
+        record_balance_checkpoint(stake, &clock);
+
+        // Mint the position NFT and fix its supply at 1
+        let seeds = &[
+            b"vault",
+            vault.nft_mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.position_mint.to_account_info(),
+                    to: ctx.accounts.user_position_account.to_account_info(),
+                    authority: vault.to_account_info(),
+                },
+                signer,
+            ),
+            1,
+        )?;
+        token::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                SetAuthority {
+                    current_authority: vault.to_account_info(),
+                    account_or_mint: ctx.accounts.position_mint.to_account_info(),
+                },
+                signer,
+            ),
+            AuthorityType::MintTokens,
+            None,
+        )?;
+
+        emit!(TokensDepositedEvent {
+            vault: vault.key(),
             authority: ctx.accounts.authority.key(),
             amount,
             total_staked: vault.total_staked,
             unlock_at: stake.unlock_at,
             timestamp: clock.unix_timestamp,
         });
+        emit!(PositionMintedEvent {
+            vault: vault.key(),
+            stake_record: stake.key(),
+            position_mint,
+            depositor: ctx.accounts.authority.key(),
+            amount,
+            unlock_at: stake.unlock_at,
+            timestamp: clock.unix_timestamp,
+        });
 
         Ok(())
     }
 
-    /// Withdraw staked tokens
-    /// If withdrawn before unlock_at: user gets principal but 0 rewards (early unlock penalty)
+    /// Withdraw staked tokens (vaults without an unbonding period, or deactivated vaults)
+    /// If withdrawn before unlock_at: the curve penalty is taken from the withdrawn share of rewards
     /// If withdrawn after unlock_at: user gets principal and can claim rewards separately
+    /// Deactivated vaults unlock every stake: no early-unlock penalty
     pub fn withdraw_tokens(
         ctx: Context<WithdrawTokens>,
         amount: u64,
     ) -> Result<()> {
-        let vault = &ctx.accounts.vault;
+        let vault = &mut ctx.accounts.vault;
         let stake = &mut ctx.accounts.stake_record;
         let clock = Clock::get()?;
 
-        // Validate authority
+        // Validate authority (depositor, or position NFT holder)
         require!(
-            ctx.accounts.authority.key() == stake.authority,
+            ctx.accounts.authority.key() == stake_owner(stake, &ctx.accounts.position_account)?,
             ErrorCode::Unauthorized
         );
 
@@ -233,63 +540,44 @@ pub mod pangi_vault {
         require!(amount <= stake.amount, ErrorCode::InsufficientStake);
 
         // Check if early unlock (before lock period ends)
-        let is_early_unlock = clock.unix_timestamp < stake.unlock_at;
+        let is_early_unlock = vault.is_active && clock.unix_timestamp < stake.unlock_at;
+
+        // Unbonding vaults release every stake, early or not, through request_unstake/complete_unstake
+        require!(
+            vault.unbonding_period == 0 || !vault.is_active,
+            ErrorCode::UnbondingRequired
+        );
+
+        // Settle rewards on the full balance before it changes
+        settle_stake_rewards(stake, vault, clock.unix_timestamp)?;
         
-        // Calculate rewards based on unlock timing
-        let (pending_rewards, penalty_to_pool) = if is_early_unlock {
-            // Early unlock: proportional rewards - 15% penalty
-            let (user_payout, penalty) = calculate_early_unlock_rewards(
-                stake.amount,
-                vault.reward_rate,
-                stake.staked_at,
-                stake.unlock_at,
-                clock.unix_timestamp,
-            )?;
-            
-            let days_early = safe_div!(
-                safe_sub!(stake.unlock_at, clock.unix_timestamp),
-                86400  // seconds per day
-            );
-            
-            emit!(EarlyUnlockEvent {
-                vault: ctx.accounts.vault.key(),
-                authority: ctx.accounts.authority.key(),
+        // Early unlock forfeits the curve penalty on the withdrawn share of rewards;
+        // the rest stays settled for claim_rewards
+        let (penalty_to_pool, principal_penalty) = if is_early_unlock {
+            apply_early_unlock(
+                vault,
+                stake,
+                ctx.accounts.authority.key(),
                 amount,
-                forfeited_rewards: penalty,  // 15% penalty goes to pool
-                unlock_at: stake.unlock_at,
-                unlocked_at: clock.unix_timestamp,
-                days_early,
-            });
-            
-            (user_payout, penalty)
-        } else {
-            // Normal unlock: full rewards, no penalty
-            let full_rewards = calculate_pending_rewards(
-                stake.amount,
-                vault.reward_rate,
-                stake.last_claim,
                 clock.unix_timestamp,
-            )?;
-            (full_rewards, 0)
+            )?
+        } else {
+            (0, 0)
         };
 
-        // If there's a penalty, it stays in the vault (reward pool)
-        // Track total penalties collected for transparency
-        if penalty_to_pool > 0 {
-            let vault = &mut ctx.accounts.vault;
-            vault.total_penalties_collected = safe_add!(
-                vault.total_penalties_collected,
-                penalty_to_pool
-            );
-        }
-
         // Update stake record
         stake.amount = stake
             .amount
             .checked_sub(amount)
             .ok_or(ErrorCode::Underflow)?;
 
+        // Checkpoint vePANGI voting power for the remaining balance
+        checkpoint_voting_power(&mut ctx.accounts.ve_supply, vault, stake, clock.unix_timestamp)?;
+        record_balance_checkpoint(stake, &clock);
+
         // Transfer tokens from vault to user
+        // A principal penalty moves into the reward pool (its reserve slice is already earmarked)
+        let vault = &ctx.accounts.vault;
         let seeds = &[
             b"vault",
             vault.nft_mint.as_ref(),
@@ -307,22 +595,43 @@ pub mod pangi_vault {
                 },
                 signer,
             ),
-            amount,
+            safe_sub!(amount, principal_penalty),
         )?;
+        if principal_penalty > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.vault_token_account.to_account_info(),
+                        to: ctx.accounts.reward_token_account.to_account_info(),
+                        authority: ctx.accounts.vault.to_account_info(),
+                    },
+                    signer,
+                ),
+                principal_penalty,
+            )?;
+        }
 
         // Update vault total
         let vault = &mut ctx.accounts.vault;
+        advance_vault_rewards(vault, clock.unix_timestamp)?;
         vault.total_staked = vault
             .total_staked
             .checked_sub(amount)
             .ok_or(ErrorCode::Underflow)?;
+        vault.total_principal_penalties = safe_add!(vault.total_principal_penalties, principal_penalty);
+
+        let global_cap_shard = &mut ctx.accounts.global_cap_shard;
+        global_cap_shard.total_staked = safe_sub!(global_cap_shard.total_staked, amount);
 
         emit!(TokensWithdrawnEvent {
-            vault: ctx.accounts.vault.key(),
+            vault: vault.key(),
             authority: ctx.accounts.authority.key(),
             amount,
-            pending_rewards,
+            pending_rewards: stake.pending_rewards,
             penalty_to_pool,  // Amount returned to pool (0 if normal unlock)
+            principal_penalty,
+            reward_mint: vault.reward_mint,
             remaining_stake: stake.amount,
             is_early_unlock,
             timestamp: clock.unix_timestamp,
@@ -331,28 +640,38 @@ pub mod pangi_vault {
         Ok(())
     }
 
-    /// Claim staking rewards
+    /// Claim staking rewards (paid in the vault's reward mint, e.g. $CATH)
     /// Guardian can report this claim event OUT to configured public address
     /// Guardian receives NO data from PANGI (one-way reporting)
     /// NOTE: Can only claim rewards if lock period has ended (unlock_at reached)
+    /// or the vault was deactivated (rewards stop accruing at deactivation)
     /// Early unlock = 0 rewards (penalty enforced in withdraw_tokens)
     pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
         let vault = &ctx.accounts.vault;
         let stake = &mut ctx.accounts.stake_record;
         let clock = Clock::get()?;
 
-        // Validate authority (Master NFT holder or authorized Guardian)
+        // Validate authority (stake owner, or Guardian with claim-to-owner permission)
+        // Rewards always go to the stake owner's reward account
+        let owner = stake_owner(stake, &ctx.accounts.position_account)?;
         require!(
-            ctx.accounts.authority.key() == stake.authority,
+            ctx.accounts.user_reward_account.owner == owner,
             ErrorCode::Unauthorized
         );
+        if ctx.accounts.authority.key() != owner {
+            verify_optional_guardian(
+                &ctx.accounts.guardian_config,
+                &ctx.accounts.guardian_nft_account,
+                stake.key(),
+                ctx.accounts.authority.key(),
+                GUARDIAN_PERMISSION_CLAIM,
+            )?;
+        }
 
-        // Validate vault is active
-        require!(vault.is_active, ErrorCode::VaultInactive);
-        
         // Validate lock period has ended (no rewards before unlock_at)
+        // Deactivation unlocks all stakes
         require!(
-            clock.unix_timestamp >= stake.unlock_at,
+            clock.unix_timestamp >= stake.unlock_at || !vault.is_active,
             ErrorCode::StillLocked
         );
         
@@ -367,19 +686,26 @@ pub mod pangi_vault {
             ErrorCode::ClaimCooldownActive
         );
 
-        // Calculate pending rewards (only if lock period completed)
-        let pending_rewards = calculate_pending_rewards(
-            stake.amount,
-            vault.reward_rate,
-            stake.last_claim,
-            clock.unix_timestamp,
-        )?;
+        // Settle accrued rewards (only if lock period completed)
+        settle_stake_rewards(stake, vault, clock.unix_timestamp)?;
+        let pending_rewards = stake.pending_rewards;
 
         require!(pending_rewards > 0, ErrorCode::NoRewardsToClaim);
 
-        // Check vault has sufficient balance for rewards
+        // Top up a short reward pool from the insurance reserve (if any)
+        draw_reserve_shortfall(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_token_account,
+            &ctx.accounts.reward_reserve,
+            &ctx.accounts.token_program,
+            pending_rewards,
+            clock.unix_timestamp,
+        )?;
+        let vault = &ctx.accounts.vault;
+
+        // Check reward pool has sufficient balance for rewards
         require!(
-            ctx.accounts.vault_token_account.amount >= pending_rewards,
+            spendable_rewards(vault, &ctx.accounts.reward_token_account) >= pending_rewards,
             ErrorCode::InsufficientVaultBalance
         );
 
@@ -395,8 +721,8 @@ pub mod pangi_vault {
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
                 Transfer {
-                    from: ctx.accounts.vault_token_account.to_account_info(),
-                    to: ctx.accounts.user_token_account.to_account_info(),
+                    from: ctx.accounts.reward_token_account.to_account_info(),
+                    to: ctx.accounts.user_reward_account.to_account_info(),
                     authority: ctx.accounts.vault.to_account_info(),
                 },
                 signer,
@@ -405,16 +731,21 @@ pub mod pangi_vault {
         )?;
 
         // Update stake record
+        stake.pending_rewards = 0;
         stake.last_claim = clock.unix_timestamp;
         stake.total_claimed = stake
             .total_claimed
             .checked_add(pending_rewards)
             .ok_or(ErrorCode::Overflow)?;
+        let vault = &mut ctx.accounts.vault;
+        credit_referral(stake, vault, pending_rewards)?;
 
         emit!(RewardsClaimedEvent {
-            vault: ctx.accounts.vault.key(),
-            authority: ctx.accounts.authority.key(),
+            vault: vault.key(),
+            authority: stake.authority,
+            claimed_by: ctx.accounts.authority.key(),
             amount: pending_rewards,
+            reward_mint: vault.reward_mint,
             total_claimed: stake.total_claimed,
             timestamp: clock.unix_timestamp,
         });
@@ -422,369 +753,7256 @@ pub mod pangi_vault {
         Ok(())
     }
 
-    pub fn deactivate_vault(ctx: Context<DeactivateVault>) -> Result<()> {
-        let vault = &mut ctx.accounts.vault;
+    /// Compound pending rewards into the same position (reward mint must equal stake mint)
+    /// Allowed during the lock; keeps staked_at and unlock_at unchanged and is exempt from deposit caps
+    /// Callable by the stake owner or a Guardian with compound permission
+    pub fn compound_rewards(ctx: Context<CompoundRewards>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        let owner = stake_owner(&ctx.accounts.stake_record, &ctx.accounts.position_account)?;
+        if ctx.accounts.caller.key() != owner {
+            verify_optional_guardian(
+                &ctx.accounts.guardian_config,
+                &ctx.accounts.guardian_nft_account,
+                ctx.accounts.stake_record.key(),
+                ctx.accounts.caller.key(),
+                GUARDIAN_PERMISSION_COMPOUND,
+            )?;
+        }
+
+        let amount = compound_stake_rewards(ctx.accounts, clock.unix_timestamp)?;
+        emit_compounded(ctx.accounts, amount, false, clock.unix_timestamp);
+
+        Ok(())
+    }
+
+    /// Permissionless auto-compound crank for vaults with auto_compound enabled
+    pub fn crank_compound(ctx: Context<CompoundRewards>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        require!(ctx.accounts.vault.auto_compound, ErrorCode::AutoCompoundDisabled);
+
+        let amount = compound_stake_rewards(ctx.accounts, clock.unix_timestamp)?;
+        emit_compounded(ctx.accounts, amount, true, clock.unix_timestamp);
+
+        Ok(())
+    }
+
+    /// Permissionless crank: advance vault-level reward accounting to the current time
+    /// Stakes settle base rewards against the reward_index this advances
+    /// Pays the caller the CRANK_TIP_BPS share of rewards accrued since the last crank, which the
+    /// reward_index leaves out for crankers
+    pub fn update_vault(ctx: Context<UpdateVault>) -> Result<()> {
+        let clock = Clock::get()?;
 
+        let vault = &mut ctx.accounts.vault;
+        let accrual_end = reward_accrual_time(vault, clock.unix_timestamp);
+        // Deposits, withdrawals and claims also advance last_reward_update; only cranks reset this
         require!(
-            ctx.accounts.authority.key() == vault.authority,
-            ErrorCode::Unauthorized
+            safe_sub!(accrual_end, vault.last_crank_at) >= VAULT_CRANK_INTERVAL,
+            ErrorCode::VaultUpdateTooSoon
         );
 
-        require!(vault.is_active, ErrorCode::VaultAlreadyInactive);
+        let rewards_accrued = advance_vault_rewards(vault, clock.unix_timestamp)?;
+        vault.last_crank_at = accrual_end;
 
-        vault.is_active = false;
+        // Pay out the set-aside share; whatever the pool cannot cover is dropped
+        let payable = ctx
+            .accounts
+            .reward_token_account
+            .amount
+            .saturating_sub(vault.reserve_pending)
+            .saturating_sub(vault.referral_pending);
+        let tip = vault.crank_tips_pending.min(payable);
+        vault.crank_tips_pending = 0;
+        vault.total_crank_tips = safe_add!(vault.total_crank_tips, tip);
 
-        emit!(VaultDeactivatedEvent {
-            vault: ctx.accounts.vault.key(),
+        let vault = &ctx.accounts.vault;
+        if tip > 0 {
+            let seeds = &[
+                b"vault",
+                vault.nft_mint.as_ref(),
+                &[vault.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.reward_token_account.to_account_info(),
+                        to: ctx.accounts.cranker_reward_account.to_account_info(),
+                        authority: vault.to_account_info(),
+                    },
+                    signer,
+                ),
+                tip,
+            )?;
+        }
+
+        emit!(VaultUpdatedEvent {
+            vault: vault.key(),
+            cranker: ctx.accounts.cranker.key(),
+            rewards_accrued,
+            total_rewards_accrued: vault.total_rewards_accrued,
+            reward_index: vault.reward_index,
+            tip,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Enable or disable the permissionless auto-compound crank for a vault
+    pub fn set_auto_compound(ctx: Context<SetAutoCompound>, enabled: bool) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+        if enabled {
+            require!(
+                vault.reward_mint == vault.token_mint,
+                ErrorCode::RewardMintMismatch
+            );
+        }
+
+        vault.auto_compound = enabled;
+
+        emit!(AutoCompoundUpdatedEvent {
+            vault: vault.key(),
             authority: ctx.accounts.authority.key(),
+            enabled,
             timestamp: Clock::get()?.unix_timestamp,
         });
 
         Ok(())
     }
-}
 
-// Helper function to calculate pending rewards
-fn calculate_pending_rewards(
-    staked_amount: u64,
-    reward_rate: u16,
-    last_claim: i64,
-    current_time: i64,
-) -> Result<u64> {
-    // Calculate time elapsed with overflow protection
-    let time_elapsed = current_time
-        .checked_sub(last_claim)
-        .ok_or(ErrorCode::Overflow)?;
+    /// Set who may deposit: Master NFT holder only, anyone, or an allowlist
+    /// `allowlist_root` is the Merkle root of eligible wallets (required for Allowlist)
+    pub fn set_access_mode(
+        ctx: Context<SetAccessMode>,
+        access_mode: VaultAccessMode,
+        allowlist_root: [u8; 32],
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
 
-    if time_elapsed <= 0 {
-        return Ok(0);
-    }
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+        if access_mode == VaultAccessMode::Allowlist {
+            require!(allowlist_root != [0u8; 32], ErrorCode::InvalidAllowlistRoot);
+        }
 
-    // Calculate rewards: (amount * rate * time) / (denominator * seconds_per_year)
-    // Using u128 to prevent overflow
-    let seconds_per_year: u128 = 365 * 24 * 60 * 60;
-    
-    let rewards = (staked_amount as u128)
-        .checked_mul(reward_rate as u128)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_mul(time_elapsed as u128)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_div(REWARD_RATE_DENOMINATOR as u128)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_div(seconds_per_year)
-        .ok_or(ErrorCode::Overflow)?;
+        vault.access_mode = access_mode;
+        vault.allowlist_root = if access_mode == VaultAccessMode::Allowlist {
+            allowlist_root
+        } else {
+            [0u8; 32]
+        };
 
-    // Ensure result fits in u64
-    require!(rewards <= u64::MAX as u128, ErrorCode::Overflow);
+        emit!(AccessModeUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            access_mode,
+            allowlist_root: vault.allowlist_root,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-    Ok(rewards as u64)
-}
+        Ok(())
+    }
 
-// Calculate early unlock rewards with 15% penalty
-// Returns (user_payout, penalty_amount)
-// penalty_amount goes back to reward pool
-fn calculate_early_unlock_rewards(
-    staked_amount: u64,
-    reward_rate: u16,
-    staked_at: i64,
-    unlock_at: i64,
-    current_time: i64,
-) -> Result<(u64, u64)> {
-    // Calculate time actually staked
-    let time_staked = safe_sub!(current_time, staked_at);
-    
-    // Calculate total lock duration
-    let lock_duration = safe_sub!(unlock_at, staked_at);
-    
-    // Calculate total potential rewards for full duration
-    let total_potential_rewards = calculate_pending_rewards(
-        staked_amount,
-        reward_rate,
-        staked_at,
-        unlock_at,
-    )?;
-    
-    // Calculate proportional rewards based on time actually staked
-    // proportional_rewards = (time_staked / lock_duration) × total_potential_rewards
-    let proportional_rewards = (total_potential_rewards as u128)
-        .checked_mul(time_staked as u128)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_div(lock_duration as u128)
-        .ok_or(ErrorCode::DivisionByZero)?;
-    
-    require!(proportional_rewards <= u64::MAX as u128, ErrorCode::Overflow);
-    let proportional_rewards = proportional_rewards as u64;
-    
-    // Apply 15% penalty
-    let penalty_amount = safe_percentage!(proportional_rewards, EARLY_UNLOCK_PENALTY_BPS);
-    let user_payout = safe_sub!(proportional_rewards, penalty_amount);
-    
-    // Return (user_payout, penalty_amount)
-    // penalty_amount will be returned to reward pool
-    Ok((user_payout, penalty_amount))
-}
+    /// Append a future reward epoch: `rate` applies from `start_time` until the next epoch
+    /// Epochs can only be scheduled ahead, so accrued rewards never change
+    pub fn append_reward_epoch(
+        ctx: Context<AppendRewardEpoch>,
+        start_time: i64,
+        rate: u16,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let clock = Clock::get()?;
 
-#[derive(Accounts)]
-pub struct CreateVault<'info> {
-    #[account(
-        init,
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+        require!(vault.is_active, ErrorCode::VaultInactive);
+        require!(
+            rate <= ctx.accounts.registry.max_reward_rate,
+            ErrorCode::RewardRateTooHigh
+        );
+
+        let count = vault.reward_epoch_count as usize;
+        require!(count < MAX_REWARD_EPOCHS, ErrorCode::RewardScheduleFull);
+        require!(
+            start_time > clock.unix_timestamp
+                && start_time > vault.reward_epochs[count - 1].start_time,
+            ErrorCode::RetroactiveRewardEpoch
+        );
+
+        vault.reward_epochs[count] = RewardEpoch { start_time, rate };
+        vault.reward_epoch_count = safe_add!(vault.reward_epoch_count, 1);
+
+        emit!(RewardEpochAddedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            epoch_index: count as u8,
+            start_time,
+            rate,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Set the share of referred stakers' rewards paid to their referrer (0 disables)
+    /// Paid from the reward pool on top of the staker's rewards, at the rate in force when they are paid
+    pub fn set_referral_bps(ctx: Context<SetReferralBps>, referral_bps: u16) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+        require!(referral_bps <= MAX_REFERRAL_BPS, ErrorCode::ReferralShareTooHigh);
+
+        vault.referral_bps = referral_bps;
+
+        emit!(ReferralShareUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            referral_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Register the signer as a referrer for a vault (creates their ReferrerStats)
+    pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+        let referrer_stats = &mut ctx.accounts.referrer_stats;
+
+        referrer_stats.vault = ctx.accounts.vault.key();
+        referrer_stats.referrer = ctx.accounts.referrer.key();
+        referrer_stats.referred_count = 0;
+        referrer_stats.total_claimed = 0;
+        referrer_stats.last_claim_at = 0;
+        referrer_stats.bump = ctx.bumps.referrer_stats;
+
+        Ok(())
+    }
+
+    /// Claim the referral rewards credited on one referred stake
+    /// The share is credited as the staker's rewards are paid; paid from the reward pool
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let stake = &mut ctx.accounts.stake_record;
+        let clock = Clock::get()?;
+
+        require!(
+            stake.referrer == ctx.accounts.referrer.key(),
+            ErrorCode::InvalidReferrer
+        );
+
+        require!(stake.referral_pending > 0, ErrorCode::NoRewardsToClaim);
+
+        pay_referral_pending(
+            vault,
+            stake,
+            &mut ctx.accounts.referrer_stats,
+            &ctx.accounts.referrer_reward_account,
+            &ctx.accounts.reward_token_account,
+            &ctx.accounts.token_program,
+            clock.unix_timestamp,
+        )?;
+
+        Ok(())
+    }
+
+    /// Configure the early-unlock penalty curve
+    /// Flat / LinearDecay use `penalty_bps` (LinearDecay reaches zero at unlock);
+    /// Stepped uses `steps`, ordered by ascending min_remaining
+    /// With `penalize_principal` the same bps is also taken from withdrawn principal and
+    /// moved into the reward pool, so the vault must pay rewards in its stake mint
+    pub fn set_penalty_curve(
+        ctx: Context<SetPenaltyCurve>,
+        curve: PenaltyCurve,
+        penalty_bps: u16,
+        steps: Vec<PenaltyStep>,
+        penalize_principal: bool,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+
+        let max_bps = match curve {
+            PenaltyCurve::Flat | PenaltyCurve::LinearDecay => penalty_bps,
+            PenaltyCurve::Stepped => {
+                require!(
+                    !steps.is_empty() && steps.len() <= MAX_PENALTY_STEPS,
+                    ErrorCode::InvalidPenaltyCurve
+                );
+                require!(
+                    steps.windows(2).all(|w| w[0].min_remaining < w[1].min_remaining),
+                    ErrorCode::InvalidPenaltyCurve
+                );
+                steps.iter().map(|step| step.penalty_bps).max().unwrap_or(0)
+            }
+        };
+        require!(max_bps <= 10000, ErrorCode::InvalidPenaltyCurve);
+        if penalize_principal {
+            require!(
+                max_bps <= MAX_PRINCIPAL_PENALTY_BPS,
+                ErrorCode::InvalidPenaltyCurve
+            );
+            require!(
+                vault.reward_mint == vault.token_mint,
+                ErrorCode::PrincipalPenaltyMintMismatch
+            );
+        }
+
+        vault.penalty_curve = curve;
+        vault.penalty_bps = penalty_bps;
+        vault.penalty_steps = [PenaltyStep::default(); MAX_PENALTY_STEPS];
+        vault.penalty_step_count = 0;
+        if curve == PenaltyCurve::Stepped {
+            vault.penalty_steps[..steps.len()].copy_from_slice(&steps);
+            vault.penalty_step_count = steps.len() as u8;
+        }
+        vault.penalize_principal = penalize_principal;
+        // Governance-approved vaults must keep charging principal on every early exit
+        require!(
+            !vault.voting_approved || principal_penalty_always_applies(vault),
+            ErrorCode::VotingRequiresPrincipalPenalty
+        );
+
+        emit!(PenaltyCurveUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            curve,
+            penalty_bps,
+            steps,
+            penalize_principal,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Switch the vault between wallet-bound stakes and transferable positions
+    /// In receipt mode deposits go through deposit_position; existing stakes are unaffected
+    pub fn set_receipt_mode(ctx: Context<SetReceiptMode>, enabled: bool) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+
+        vault.receipt_mode = enabled;
+
+        emit!(ReceiptModeUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            enabled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Set the vault TVL cap and per-user stake cap (0 = uncapped)
+    /// With `allow_partial_fill`, deposits over a cap are filled up to it instead of rejected
+    /// Caps bound new deposits only: compounded rewards grow existing positions past them
+    pub fn set_deposit_caps(
+        ctx: Context<SetDepositCaps>,
+        max_total_staked: u64,
+        max_user_stake: u64,
+        allow_partial_fill: bool,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+
+        vault.max_total_staked = max_total_staked;
+        vault.max_user_stake = max_user_stake;
+        vault.allow_partial_fill = allow_partial_fill;
+
+        emit!(DepositCapsUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            max_total_staked,
+            max_user_stake,
+            allow_partial_fill,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Master NFT holder installs a Guardian for a stake
+    /// Names the Guardian NFT, its permission bitmask and the public reporting address
+    pub fn install_guardian(
+        ctx: Context<InstallGuardian>,
+        permissions: u8,
+        report_address: Pubkey,
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let clock = Clock::get()?;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.master.key(),
+        )?;
+        require!(
+            permissions & !GUARDIAN_PERMISSIONS_ALL == 0,
+            ErrorCode::InvalidGuardianPermissions
+        );
+
+        let guardian = &mut ctx.accounts.guardian_config;
+        guardian.vault = vault.key();
+        guardian.stake_record = ctx.accounts.stake_record.key();
+        guardian.master = ctx.accounts.master.key();
+        guardian.guardian_nft_mint = ctx.accounts.guardian_nft_mint.key();
+        guardian.permissions = permissions;
+        guardian.report_address = report_address;
+        guardian.installed_at = clock.unix_timestamp;
+        guardian.updated_at = clock.unix_timestamp;
+        guardian.last_report_at = 0;
+        guardian.report_count = 0;
+        guardian.reported_unlock_at = 0;
+        guardian.reported_total_claimed = ctx.accounts.stake_record.total_claimed;
+        guardian.bump = ctx.bumps.guardian_config;
+
+        emit!(GuardianInstalledEvent {
+            vault: guardian.vault,
+            stake_record: guardian.stake_record,
+            guardian_nft_mint: guardian.guardian_nft_mint,
+            permissions,
+            report_address,
+            master: guardian.master,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Master NFT holder updates a Guardian's NFT, permissions or reporting address
+    pub fn update_guardian(
+        ctx: Context<UpdateGuardian>,
+        guardian_nft_mint: Option<Pubkey>,
+        permissions: Option<u8>,
+        report_address: Option<Pubkey>,
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let guardian = &mut ctx.accounts.guardian_config;
+        let clock = Clock::get()?;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.master.key(),
+        )?;
+
+        if let Some(mint) = guardian_nft_mint {
+            guardian.guardian_nft_mint = mint;
+        }
+        if let Some(bits) = permissions {
+            require!(
+                bits & !GUARDIAN_PERMISSIONS_ALL == 0,
+                ErrorCode::InvalidGuardianPermissions
+            );
+            guardian.permissions = bits;
+        }
+        if let Some(address) = report_address {
+            guardian.report_address = address;
+        }
+        guardian.master = ctx.accounts.master.key();
+        guardian.updated_at = clock.unix_timestamp;
+
+        emit!(GuardianUpdatedEvent {
+            vault: guardian.vault,
+            stake_record: guardian.stake_record,
+            guardian_nft_mint: guardian.guardian_nft_mint,
+            permissions: guardian.permissions,
+            report_address: guardian.report_address,
+            master: guardian.master,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Master NFT holder revokes a Guardian (closes the config, rent to Master)
+    pub fn revoke_guardian(ctx: Context<RevokeGuardian>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let guardian = &ctx.accounts.guardian_config;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.master.key(),
+        )?;
+
+        emit!(GuardianRevokedEvent {
+            vault: guardian.vault,
+            stake_record: guardian.stake_record,
+            guardian_nft_mint: guardian.guardian_nft_mint,
+            master: ctx.accounts.master.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Guardian reports a stake event OUT to the configured public address
+    /// Permissionless for the owner (no owner signature); the Guardian NFT holder signs
+    /// Unlock: reportable once unlock_at has passed (once per lock)
+    /// Claim: reportable when rewards were claimed since the last claim report
+    pub fn guardian_report(ctx: Context<GuardianReport>, kind: GuardianReportKind) -> Result<()> {
+        let stake = &ctx.accounts.stake_record;
+        let guardian = &mut ctx.accounts.guardian_config;
+        let clock = Clock::get()?;
+
+        verify_guardian(
+            guardian,
+            &ctx.accounts.guardian_nft_account,
+            stake.key(),
+            ctx.accounts.guardian.key(),
+            GUARDIAN_PERMISSION_REPORT,
+        )?;
+
+        // Rate limit reports per stake
+        if guardian.report_count > 0 {
+            let time_since_last_report = safe_sub!(clock.unix_timestamp, guardian.last_report_at);
+            require!(
+                time_since_last_report >= GUARDIAN_REPORT_COOLDOWN,
+                ErrorCode::ReportCooldownActive
+            );
+        }
+
+        let claimed_since_last_report = match kind {
+            GuardianReportKind::Unlock => {
+                require!(
+                    stake.amount > 0 && clock.unix_timestamp >= stake.unlock_at,
+                    ErrorCode::StillLocked
+                );
+                require!(
+                    guardian.reported_unlock_at != stake.unlock_at,
+                    ErrorCode::NothingToReport
+                );
+                guardian.reported_unlock_at = stake.unlock_at;
+                0
+            }
+            GuardianReportKind::Claim => {
+                let delta = safe_sub!(stake.total_claimed, guardian.reported_total_claimed);
+                require!(delta > 0, ErrorCode::NothingToReport);
+                guardian.reported_total_claimed = stake.total_claimed;
+                delta
+            }
+        };
+
+        guardian.last_report_at = clock.unix_timestamp;
+        guardian.report_count = safe_add!(guardian.report_count, 1);
+
+        emit!(GuardianReportEvent {
+            report_address: guardian.report_address,
+            vault: stake.vault,
+            stake_record: stake.key(),
+            owner: stake.authority,
+            guardian_nft_mint: guardian.guardian_nft_mint,
+            kind,
+            amount_staked: stake.amount,
+            unlock_at: stake.unlock_at,
+            total_claimed: stake.total_claimed,
+            claimed_since_last_report,
+            report_index: guardian.report_count,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Guardian NFT holder posts (or tops up) a PANGI bond for the stake they guard
+    /// The bond follows the Guardian NFT and can be slashed through a dispute
+    pub fn post_guardian_bond(ctx: Context<PostGuardianBond>, amount: u64) -> Result<()> {
+        let guardian = &ctx.accounts.guardian_config;
+        let clock = Clock::get()?;
+
+        verify_guardian_holder(
+            guardian.guardian_nft_mint,
+            &ctx.accounts.guardian_nft_account,
+            ctx.accounts.guardian.key(),
+        )?;
+        require!(amount > 0, ErrorCode::AmountTooSmall);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.guardian_token_account.to_account_info(),
+                    to: ctx.accounts.bond_token_account.to_account_info(),
+                    authority: ctx.accounts.guardian.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let bond = &mut ctx.accounts.guardian_bond;
+        if bond.stake_record == Pubkey::default() {
+            bond.vault = ctx.accounts.vault.key();
+            bond.stake_record = guardian.stake_record;
+            bond.guardian_nft_mint = guardian.guardian_nft_mint;
+            bond.bond_token_account = ctx.accounts.bond_token_account.key();
+            bond.dispute_state = GuardianDisputeState::None;
+            bond.bump = ctx.bumps.guardian_bond;
+        }
+        bond.amount = safe_add!(bond.amount, amount);
+        bond.exit_requested_at = 0; // Topping up cancels a pending exit
+
+        emit!(GuardianBondPostedEvent {
+            vault: bond.vault,
+            stake_record: bond.stake_record,
+            guardian_nft_mint: bond.guardian_nft_mint,
+            guardian: ctx.accounts.guardian.key(),
+            amount,
+            bond_amount: bond.amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Open a dispute against a Guardian bond (Master NFT holder or registry authority)
+    /// Slashes after GUARDIAN_CHALLENGE_WINDOW unless the Guardian challenges it first;
+    /// challenged disputes are decided by the registry authority (governance)
+    pub fn open_guardian_dispute(
+        ctx: Context<OpenGuardianDispute>,
+        slash_amount: u64,
+        evidence_hash: [u8; 32],
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let clock = Clock::get()?;
+
+        if ctx.accounts.disputer.key() != ctx.accounts.registry.authority {
+            let Some(master_nft_account) = ctx.accounts.master_nft_account.as_ref() else {
+                return Err(ErrorCode::NotMasterHolder.into());
+            };
+            verify_master(vault.nft_mint, master_nft_account, ctx.accounts.disputer.key())?;
+        }
+
+        let bond = &mut ctx.accounts.guardian_bond;
+        open_dispute(
+            bond,
+            ctx.accounts.disputer.key(),
+            slash_amount,
+            evidence_hash,
+            clock.unix_timestamp,
+        )?;
+
+        emit!(GuardianDisputeOpenedEvent {
+            vault: bond.vault,
+            stake_record: bond.stake_record,
+            guardian_nft_mint: bond.guardian_nft_mint,
+            disputed_by: bond.disputed_by,
+            slash_amount,
+            evidence_hash,
+            challenge_ends_at: bond.challenge_ends_at,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Guardian NFT holder contests an open dispute within the challenge window
+    pub fn challenge_guardian_dispute(ctx: Context<ChallengeGuardianDispute>) -> Result<()> {
+        let bond = &mut ctx.accounts.guardian_bond;
+        let clock = Clock::get()?;
+
+        verify_guardian_holder(
+            bond.guardian_nft_mint,
+            &ctx.accounts.guardian_nft_account,
+            ctx.accounts.guardian.key(),
+        )?;
+        challenge_dispute(bond, clock.unix_timestamp)?;
+
+        emit!(GuardianDisputeChallengedEvent {
+            vault: bond.vault,
+            stake_record: bond.stake_record,
+            guardian_nft_mint: bond.guardian_nft_mint,
+            guardian: ctx.accounts.guardian.key(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Slash an unchallenged dispute once its challenge window has passed (permissionless)
+    pub fn execute_guardian_slash(ctx: Context<SlashGuardianBond>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        require_unchallenged_slash(&ctx.accounts.guardian_bond, clock.unix_timestamp)?;
+
+        slash_guardian_bond(ctx.accounts, clock.unix_timestamp)
+    }
+
+    /// Registry authority (governance) decides a challenged dispute
+    /// `uphold` slashes the disputed amount; otherwise the dispute is dismissed
+    pub fn resolve_guardian_dispute(ctx: Context<SlashGuardianBond>, uphold: bool) -> Result<()> {
+        let clock = Clock::get()?;
+
+        let Some(authority) = ctx.accounts.authority.as_ref() else {
+            return Err(ErrorCode::Unauthorized.into());
+        };
+        require!(
+            authority.key() == ctx.accounts.registry.authority,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            ctx.accounts.guardian_bond.dispute_state == GuardianDisputeState::Challenged,
+            ErrorCode::NoOpenDispute
+        );
+
+        if uphold {
+            return slash_guardian_bond(ctx.accounts, clock.unix_timestamp);
+        }
+
+        let bond = &mut ctx.accounts.guardian_bond;
+        let dismissed_amount = bond.dispute_amount;
+        clear_dispute(bond);
+
+        emit!(GuardianDisputeDismissedEvent {
+            vault: bond.vault,
+            stake_record: bond.stake_record,
+            guardian_nft_mint: bond.guardian_nft_mint,
+            dismissed_amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Guardian NFT holder starts withdrawing the bond
+    /// Withdrawable after GUARDIAN_CHALLENGE_WINDOW, so recent behaviour can still be disputed
+    pub fn request_guardian_bond_exit(ctx: Context<RequestGuardianBondExit>) -> Result<()> {
+        let bond = &mut ctx.accounts.guardian_bond;
+        let clock = Clock::get()?;
+
+        verify_guardian_holder(
+            bond.guardian_nft_mint,
+            &ctx.accounts.guardian_nft_account,
+            ctx.accounts.guardian.key(),
+        )?;
+        require!(bond.amount > 0, ErrorCode::InsufficientBalance);
+
+        bond.exit_requested_at = clock.unix_timestamp;
+
+        emit!(GuardianBondExitRequestedEvent {
+            vault: bond.vault,
+            stake_record: bond.stake_record,
+            guardian_nft_mint: bond.guardian_nft_mint,
+            guardian: ctx.accounts.guardian.key(),
+            withdrawable_at: safe_add!(clock.unix_timestamp, GUARDIAN_CHALLENGE_WINDOW),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw the full bond once the exit delay has passed and no dispute is open
+    pub fn withdraw_guardian_bond(ctx: Context<WithdrawGuardianBond>) -> Result<()> {
+        let bond = &ctx.accounts.guardian_bond;
+        let clock = Clock::get()?;
+
+        verify_guardian_holder(
+            bond.guardian_nft_mint,
+            &ctx.accounts.guardian_nft_account,
+            ctx.accounts.guardian.key(),
+        )?;
+        require!(
+            bond.dispute_state == GuardianDisputeState::None,
+            ErrorCode::DisputeAlreadyOpen
+        );
+        require!(
+            bond.exit_requested_at > 0
+                && clock.unix_timestamp >= safe_add!(bond.exit_requested_at, GUARDIAN_CHALLENGE_WINDOW),
+            ErrorCode::BondExitNotReady
+        );
+
+        let amount = bond.amount;
+        let seeds = &[
+            b"guardian_bond",
+            bond.stake_record.as_ref(),
+            bond.guardian_nft_mint.as_ref(),
+            &[bond.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.bond_token_account.to_account_info(),
+                    to: ctx.accounts.guardian_token_account.to_account_info(),
+                    authority: ctx.accounts.guardian_bond.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+        )?;
+
+        let bond = &mut ctx.accounts.guardian_bond;
+        bond.amount = 0;
+        bond.exit_requested_at = 0;
+
+        emit!(GuardianBondWithdrawnEvent {
+            vault: bond.vault,
+            stake_record: bond.stake_record,
+            guardian_nft_mint: bond.guardian_nft_mint,
+            guardian: ctx.accounts.guardian.key(),
+            amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn deactivate_vault(ctx: Context<DeactivateVault>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+
+        require!(vault.is_active, ErrorCode::VaultAlreadyInactive);
+
+        // Wind-down: stakes unlock penalty-free, rewards stop accruing and voting power is dropped
+        vault.is_active = false;
+        let deactivated_at = Clock::get()?.unix_timestamp;
+        vault.deactivated_at = deactivated_at;
+        remove_vault_voting_power(&mut ctx.accounts.ve_supply, vault, deactivated_at)?;
+        set_index_status(&mut ctx.accounts.vault_index_page, vault, VaultStatus::Inactive)?;
+
+        emit!(VaultDeactivatedEvent {
+            vault: ctx.accounts.vault.key(),
+            authority: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Start unbonding a stake (vaults with an unbonding period)
+    /// Moves `amount` into a new unbonding entry; it stops earning rewards and voting power
+    /// Before unlock_at the early-unlock penalty applies, as in withdraw_tokens
+    pub fn request_unstake(ctx: Context<RequestUnstake>, amount: u64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let stake = &mut ctx.accounts.stake_record;
+        let clock = Clock::get()?;
+
+        require!(
+            ctx.accounts.authority.key() == stake_owner(stake, &ctx.accounts.position_account)?,
+            ErrorCode::Unauthorized
+        );
+        require!(vault.unbonding_period > 0, ErrorCode::UnbondingDisabled);
+        require!(amount > 0, ErrorCode::AmountTooSmall);
+        require!(amount <= stake.amount, ErrorCode::InsufficientStake);
+
+        let is_early_unlock = vault.is_active && clock.unix_timestamp < stake.unlock_at;
+
+        // Settle before the amount stops accruing
+        settle_stake_rewards(stake, vault, clock.unix_timestamp)?;
+
+        // A principal penalty moves into the reward pool; only the rest unbonds
+        let principal_penalty = if is_early_unlock {
+            let (_, principal_penalty) = apply_early_unlock(
+                vault,
+                stake,
+                ctx.accounts.authority.key(),
+                amount,
+                clock.unix_timestamp,
+            )?;
+            principal_penalty
+        } else {
+            0
+        };
+        let unbonding_amount = safe_sub!(amount, principal_penalty);
+
+        if principal_penalty > 0 {
+            let seeds = &[
+                b"vault",
+                vault.nft_mint.as_ref(),
+                &[vault.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.vault_token_account.to_account_info(),
+                        to: ctx.accounts.reward_token_account.to_account_info(),
+                        authority: vault.to_account_info(),
+                    },
+                    signer,
+                ),
+                principal_penalty,
+            )?;
+        }
+
+        let release_at = safe_add!(clock.unix_timestamp, vault.unbonding_period);
+
+        let entry = &mut ctx.accounts.unbonding_entry;
+        entry.vault = vault.key();
+        entry.stake_record = stake.key();
+        entry.owner = stake.authority;
+        entry.id = stake.next_unbonding_id;
+        entry.amount = unbonding_amount;
+        entry.requested_at = clock.unix_timestamp;
+        entry.release_at = release_at;
+        entry.bump = ctx.bumps.unbonding_entry;
+
+        stake.amount = safe_sub!(stake.amount, amount);
+        stake.unbonding_amount = safe_add!(stake.unbonding_amount, unbonding_amount);
+        stake.next_unbonding_id = safe_add!(stake.next_unbonding_id, 1);
+
+        checkpoint_voting_power(&mut ctx.accounts.ve_supply, vault, stake, clock.unix_timestamp)?;
+        record_balance_checkpoint(stake, &clock);
+
+        // Unbonding tokens stay in vault_token_account but no longer count as staked
+        advance_vault_rewards(vault, clock.unix_timestamp)?;
+        vault.total_staked = safe_sub!(vault.total_staked, amount);
+        vault.total_unbonding = safe_add!(vault.total_unbonding, unbonding_amount);
+        vault.total_principal_penalties = safe_add!(vault.total_principal_penalties, principal_penalty);
+
+        let global_cap_shard = &mut ctx.accounts.global_cap_shard;
+        global_cap_shard.total_staked = safe_sub!(global_cap_shard.total_staked, amount);
+
+        emit!(UnstakeRequestedEvent {
+            vault: vault.key(),
+            authority: stake.authority,
+            unbonding_entry: entry.key(),
+            id: entry.id,
+            amount: unbonding_amount,
+            release_at,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Release an unbonding entry once its period has passed (immediately if the vault is deactivated)
+    pub fn complete_unstake(ctx: Context<CompleteUnstake>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let entry = &ctx.accounts.unbonding_entry;
+        let clock = Clock::get()?;
+
+        require!(
+            ctx.accounts.authority.key()
+                == stake_owner(&ctx.accounts.stake_record, &ctx.accounts.position_account)?,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            unbonding_released(entry, vault.is_active, clock.unix_timestamp),
+            ErrorCode::UnbondingNotComplete
+        );
+
+        let amount = entry.amount;
+
+        let seeds = &[
+            b"vault",
+            vault.nft_mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_token_account.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+        )?;
+
+        let stake = &mut ctx.accounts.stake_record;
+        stake.unbonding_amount = safe_sub!(stake.unbonding_amount, amount);
+
+        let vault = &mut ctx.accounts.vault;
+        vault.total_unbonding = safe_sub!(vault.total_unbonding, amount);
+
+        emit!(UnstakeCompletedEvent {
+            vault: vault.key(),
+            authority: stake.authority,
+            unbonding_entry: ctx.accounts.unbonding_entry.key(),
+            id: ctx.accounts.unbonding_entry.id,
+            amount,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Set the unbonding period for unlocked withdrawals (0 = instant withdraw)
+    /// Entries already unbonding keep their release time
+    pub fn set_unbonding_period(ctx: Context<SetUnbondingPeriod>, unbonding_period: i64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+        require!(
+            (0..=MAX_UNBONDING_PERIOD).contains(&unbonding_period),
+            ErrorCode::InvalidUnbondingPeriod
+        );
+
+        vault.unbonding_period = unbonding_period;
+
+        emit!(UnbondingPeriodUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            unbonding_period,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw the full principal without rewards, bypassing the lock
+    /// Only once the vault is deactivated; pending rewards are forfeited and stay in the reward pool
+    pub fn emergency_withdraw(ctx: Context<WithdrawTokens>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let stake = &mut ctx.accounts.stake_record;
+        let clock = Clock::get()?;
+
+        // While active, early exits go through withdraw_tokens / request_unstake and pay the penalty
+        require!(!vault.is_active, ErrorCode::VaultStillActive);
+
+        require!(
+            ctx.accounts.authority.key() == stake_owner(stake, &ctx.accounts.position_account)?,
+            ErrorCode::Unauthorized
+        );
+
+        let amount = stake.amount;
+        require!(amount > 0, ErrorCode::InsufficientStake);
+
+        settle_stake_rewards(stake, vault, clock.unix_timestamp)?;
+        let forfeited_rewards = stake.pending_rewards;
+
+        stake.amount = 0;
+        stake.pending_rewards = 0;
+        stake.last_claim = clock.unix_timestamp;
+
+        checkpoint_voting_power(
+            &mut ctx.accounts.ve_supply,
+            &mut ctx.accounts.vault,
+            stake,
+            clock.unix_timestamp,
+        )?;
+        record_balance_checkpoint(stake, &clock);
+
+        let vault = &ctx.accounts.vault;
+        let seeds = &[
+            b"vault",
+            vault.nft_mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_token_account.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+        )?;
+
+        let vault = &mut ctx.accounts.vault;
+        advance_vault_rewards(vault, clock.unix_timestamp)?;
+        vault.total_staked = vault
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(ErrorCode::Underflow)?;
+
+        let global_cap_shard = &mut ctx.accounts.global_cap_shard;
+        global_cap_shard.total_staked = safe_sub!(global_cap_shard.total_staked, amount);
+
+        emit!(EmergencyWithdrawEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            amount,
+            forfeited_rewards,
+            reward_mint: vault.reward_mint,
+            total_staked: vault.total_staked,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Sweep leftover rewards (and any principal-account dust) to the Master NFT holder
+    /// Only once the vault is deactivated, every token and NFT position is out and every
+    /// stake record is closed (so no settled rewards or referral shares are still owed)
+    /// Pass `reward_reserve` to sweep the insurance reserve as well
+    pub fn sweep_rewards(ctx: Context<SweepRewards>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+        require!(!vault.is_active, ErrorCode::VaultStillActive);
+        require!(
+            vault.total_staked == 0
+                && vault.total_unbonding == 0
+                && vault.total_nfts_staked == 0
+                && vault.stake_count == 0,
+            ErrorCode::PositionsStillOpen
+        );
+
+        let rewards_swept = ctx.accounts.reward_token_account.amount;
+        let principal_swept = ctx.accounts.vault_token_account.amount;
+        let reserve_swept = ctx.accounts.reward_reserve.as_ref().map_or(0, |reserve| reserve.amount);
+        require!(
+            rewards_swept > 0 || principal_swept > 0 || reserve_swept > 0,
+            ErrorCode::NothingToSweep
+        );
+
+        let seeds = &[
+            b"vault",
+            vault.nft_mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        if rewards_swept > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.reward_token_account.to_account_info(),
+                        to: ctx.accounts.authority_reward_account.to_account_info(),
+                        authority: ctx.accounts.vault.to_account_info(),
+                    },
+                    signer,
+                ),
+                rewards_swept,
+            )?;
+        }
+        if principal_swept > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.vault_token_account.to_account_info(),
+                        to: ctx.accounts.authority_token_account.to_account_info(),
+                        authority: ctx.accounts.vault.to_account_info(),
+                    },
+                    signer,
+                ),
+                principal_swept,
+            )?;
+        }
+        if let Some(reserve) = &ctx.accounts.reward_reserve {
+            if reserve_swept > 0 {
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: reserve.to_account_info(),
+                            to: ctx.accounts.authority_reward_account.to_account_info(),
+                            authority: ctx.accounts.vault.to_account_info(),
+                        },
+                        signer,
+                    ),
+                    reserve_swept,
+                )?;
+            }
+        }
+
+        emit!(RewardsSweptEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            reward_mint: vault.reward_mint,
+            rewards_swept,
+            principal_swept,
+            reserve_swept,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create the vault's reward insurance reserve (Master NFT holder only)
+    /// `reserve_bps` of every early-unlock penalty is routed to it; claims draw on it when the pool is short
+    pub fn initialize_reward_reserve(
+        ctx: Context<InitializeRewardReserve>,
+        reserve_bps: u16,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+        require!(reserve_bps <= MAX_RESERVE_BPS, ErrorCode::ReserveShareTooHigh);
+
+        vault.reward_reserve = ctx.accounts.reward_reserve.key();
+        vault.reserve_bps = reserve_bps;
+
+        emit!(ReserveConfigUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            reward_reserve: vault.reward_reserve,
+            reserve_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Set the share of early-unlock penalties routed to the insurance reserve (Master NFT holder only)
+    /// Applies to penalties taken from now on; already earmarked amounts are kept
+    pub fn set_reserve_bps(ctx: Context<SetReserveBps>, reserve_bps: u16) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+        require!(
+            vault.reward_reserve != Pubkey::default(),
+            ErrorCode::RewardReserveNotInitialized
+        );
+        require!(reserve_bps <= MAX_RESERVE_BPS, ErrorCode::ReserveShareTooHigh);
+
+        vault.reserve_bps = reserve_bps;
+
+        emit!(ReserveConfigUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            reward_reserve: vault.reward_reserve,
+            reserve_bps,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Deposit reward tokens into the insurance reserve (permissionless)
+    /// Tops up the reserve by hand; pangi-token's `set_tax_reserve` routes a tax slice in directly
+    pub fn fund_reward_reserve(ctx: Context<FundRewardReserve>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::AmountTooSmall);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.funder_token_account.to_account_info(),
+                    to: ctx.accounts.reward_reserve.to_account_info(),
+                    authority: ctx.accounts.funder.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        ctx.accounts.reward_reserve.reload()?;
+
+        let clock = Clock::get()?;
+        let vault = &mut ctx.accounts.vault;
+        vault.total_reserve_funded = safe_add!(vault.total_reserve_funded, amount);
+
+        emit!(ReserveFundedEvent {
+            vault: vault.key(),
+            funder: ctx.accounts.funder.key(),
+            amount,
+            from_penalties: false,
+            timestamp: clock.unix_timestamp,
+        });
+        emit_reserve_health(&reserve_health(
+            vault,
+            ctx.accounts.reward_reserve.amount,
+            ctx.accounts.reward_token_account.amount,
+            clock.unix_timestamp,
+        )?);
+
+        Ok(())
+    }
+
+    /// Move earmarked early-unlock penalties from the reward pool into the reserve (permissionless crank)
+    /// Moves what the pool can spare; the rest stays earmarked
+    pub fn sweep_penalties_to_reserve(ctx: Context<SweepPenaltiesToReserve>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let amount = vault
+            .reserve_pending
+            .min(ctx.accounts.reward_token_account.amount);
+        require!(amount > 0, ErrorCode::NothingToSweep);
+
+        let seeds = &[
+            b"vault",
+            vault.nft_mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.reward_token_account.to_account_info(),
+                    to: ctx.accounts.reward_reserve.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer,
+            ),
+            amount,
+        )?;
+        ctx.accounts.reward_token_account.reload()?;
+        ctx.accounts.reward_reserve.reload()?;
+
+        let clock = Clock::get()?;
+        let vault = &mut ctx.accounts.vault;
+        vault.reserve_pending = safe_sub!(vault.reserve_pending, amount);
+        vault.total_reserve_funded = safe_add!(vault.total_reserve_funded, amount);
+
+        emit!(ReserveFundedEvent {
+            vault: vault.key(),
+            funder: ctx.accounts.caller.key(),
+            amount,
+            from_penalties: true,
+            timestamp: clock.unix_timestamp,
+        });
+        emit_reserve_health(&reserve_health(
+            vault,
+            ctx.accounts.reward_reserve.amount,
+            ctx.accounts.reward_token_account.amount,
+            clock.unix_timestamp,
+        )?);
+
+        Ok(())
+    }
+
+    /// Close an empty stake record and return its rent to the stake owner
+    /// Owner may close any time; anyone may close once the vault is deactivated
+    /// An unclaimed referral share is paid to the referrer as part of the close
+    pub fn close_stake_record(ctx: Context<CloseStakeRecord>) -> Result<()> {
+        let accounts = &mut *ctx.accounts;
+        let stake = &mut accounts.stake_record;
+        let vault = &mut accounts.vault;
+
+        let owner = stake_owner(stake, &accounts.position_account)?;
+        require!(accounts.owner.key() == owner, ErrorCode::Unauthorized);
+        require!(
+            accounts.caller.key() == owner || !vault.is_active,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            stake.amount == 0
+                && stake.unbonding_amount == 0
+                && stake.pending_rewards == 0
+                && stake.nft_boost_bps == 0,
+            ErrorCode::StakeNotEmpty
+        );
+        // The referrer's unclaimed share is paid out here so an idle referrer cannot block the close
+        if stake.referral_pending > 0 {
+            match (
+                &mut accounts.referrer_stats,
+                &accounts.referrer_reward_account,
+                &accounts.reward_token_account,
+                &accounts.token_program,
+            ) {
+                (Some(stats), Some(referrer_account), Some(pool), Some(token_program)) => {
+                    pay_referral_pending(
+                        vault,
+                        stake,
+                        stats,
+                        referrer_account,
+                        pool,
+                        token_program,
+                        Clock::get()?.unix_timestamp,
+                    )?;
+                }
+                _ => return err!(ErrorCode::ReferralRewardsPending),
+            }
+        }
+        // Points not yet credited would be lost with the record (sync_scales_points is permissionless)
+        require!(
+            stake.points_synced == stake.scales_points,
+            ErrorCode::ScalesPointsUnsynced
+        );
+
+        vault.stake_count = safe_sub!(vault.stake_count, 1);
+
+        emit!(StakeRecordClosedEvent {
+            vault: vault.key(),
+            authority: owner,
+            closed_by: accounts.caller.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Close a wound-down vault and its token accounts, returning rent to the Master NFT holder
+    /// Requires every stake record closed and both token accounts swept
+    pub fn close_vault(ctx: Context<CloseVault>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+        require!(!vault.is_active, ErrorCode::VaultStillActive);
+        require!(
+            vault.total_staked == 0
+                && vault.total_unbonding == 0
+                && vault.total_nfts_staked == 0
+                && vault.stake_count == 0,
+            ErrorCode::PositionsStillOpen
+        );
+        require!(
+            ctx.accounts.vault_token_account.amount == 0
+                && ctx.accounts.reward_token_account.amount == 0,
+            ErrorCode::VaultNotSwept
+        );
+        // A vault with an insurance reserve must close it as well
+        let reserve = match &ctx.accounts.reward_reserve {
+            Some(reserve) => {
+                require!(reserve.amount == 0, ErrorCode::VaultNotSwept);
+                Some(reserve.to_account_info())
+            }
+            None => {
+                require!(
+                    vault.reward_reserve == Pubkey::default(),
+                    ErrorCode::InvalidRewardReserve
+                );
+                None
+            }
+        };
+
+        let seeds = &[
+            b"vault",
+            vault.nft_mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        for token_account in [
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.reward_token_account.to_account_info(),
+        ]
+        .into_iter()
+        .chain(reserve)
+        {
+            token::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: token_account,
+                    destination: ctx.accounts.authority.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                signer,
+            ))?;
+        }
+
+        set_index_status(&mut ctx.accounts.vault_index_page, vault, VaultStatus::Closed)?;
+
+        emit!(VaultClosedEvent {
+            vault: vault.key(),
+            nft_mint: vault.nft_mint,
+            authority: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Set the per-day reward for a staked Common Hatchling (0 disables NFT rewards)
+    /// Rarer Hatchlings earn a multiple of this rate
+    pub fn set_nft_reward_rate(ctx: Context<SetNftRewardRate>, nft_reward_rate: u64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        verify_master(
+            vault.nft_mint,
+            &ctx.accounts.master_nft_account,
+            ctx.accounts.authority.key(),
+        )?;
+
+        vault.nft_reward_rate = nft_reward_rate;
+
+        emit!(NftRewardRateUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            nft_reward_rate,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Stake a Hatchling NFT into vault escrow
+    /// Locks the Hatchling in pangi-nft, starts its rarity-weighted reward stream
+    /// and boosts the owner's token stake APR while staked
+    pub fn stake_nft(ctx: Context<StakeNft>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        require!(ctx.accounts.vault.is_active, ErrorCode::VaultInactive);
+
+        // Move the NFT into vault escrow
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.owner_nft_account.to_account_info(),
+                    to: ctx.accounts.nft_escrow.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        // Lock the Hatchling so it cannot evolve while staked
+        // The vault PDA is the locker: only unstake_nft can unlock it
+        let vault = &ctx.accounts.vault;
+        let seeds = &[
+            b"vault",
+            vault.nft_mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        pangi_nft::cpi::lock_hatchling(CpiContext::new_with_signer(
+            ctx.accounts.nft_program.to_account_info(),
+            pangi_nft::cpi::accounts::LockHatchling {
+                hatchling: ctx.accounts.hatchling.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+                locker: Some(vault.to_account_info()),
+            },
+            signer,
+        ))?;
+
+        let rarity = ctx.accounts.hatchling.rarity;
+        let boost_bps = nft_boost_bps(rarity);
+
+        let nft_stake = &mut ctx.accounts.nft_stake;
+        nft_stake.vault = ctx.accounts.vault.key();
+        nft_stake.owner = ctx.accounts.owner.key();
+        nft_stake.nft_mint = ctx.accounts.nft_mint.key();
+        nft_stake.rarity_weight = nft_rarity_weight(rarity);
+        nft_stake.boost_bps = boost_bps;
+        nft_stake.staked_at = clock.unix_timestamp;
+        nft_stake.last_claim = clock.unix_timestamp;
+        nft_stake.total_claimed = 0;
+        nft_stake.bump = ctx.bumps.nft_stake;
+
+        // Boost the owner's token stake in this vault (created empty if needed)
+        let stake = &mut ctx.accounts.stake_record;
+        let vault = &mut ctx.accounts.vault;
+        if stake.authority == Pubkey::default() {
+            stake.vault = vault.key();
+            stake.authority = ctx.accounts.owner.key();
+            vault.stake_count = safe_add!(vault.stake_count, 1);
+        }
+        // The boost changes the effective rate: settle at the old rate first
+        settle_stake_rewards(stake, vault, clock.unix_timestamp)?;
+        stake.nft_boost_bps = safe_add!(stake.nft_boost_bps, boost_bps);
+
+        vault.total_nfts_staked = safe_add!(vault.total_nfts_staked, 1);
+
+        emit!(NftStakedEvent {
+            vault: vault.key(),
+            owner: nft_stake.owner,
+            nft_mint: nft_stake.nft_mint,
+            rarity_weight: nft_stake.rarity_weight,
+            boost_bps,
+            total_nfts_staked: vault.total_nfts_staked,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Claim a staked Hatchling's accrued rewards
+    pub fn claim_nft_rewards(ctx: Context<ClaimNftRewards>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let nft_stake = &mut ctx.accounts.nft_stake;
+        let clock = Clock::get()?;
+
+        require!(vault.is_active, ErrorCode::VaultInactive);
+
+        let time_since_last_claim = safe_sub!(clock.unix_timestamp, nft_stake.last_claim);
+        require!(
+            time_since_last_claim >= CLAIM_COOLDOWN,
+            ErrorCode::ClaimCooldownActive
+        );
+
+        let rewards = calculate_nft_rewards(
+            vault.nft_reward_rate,
+            nft_stake.rarity_weight,
+            nft_stake.last_claim,
+            reward_accrual_time(vault, clock.unix_timestamp),
+        )?;
+        require!(rewards > 0, ErrorCode::NoRewardsToClaim);
+
+        require!(
+            spendable_rewards(vault, &ctx.accounts.reward_token_account) >= rewards,
+            ErrorCode::InsufficientVaultBalance
+        );
+
+        let seeds = &[
+            b"vault",
+            vault.nft_mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.reward_token_account.to_account_info(),
+                    to: ctx.accounts.owner_reward_account.to_account_info(),
+                    authority: vault.to_account_info(),
+                },
+                signer,
+            ),
+            rewards,
+        )?;
+
+        nft_stake.last_claim = clock.unix_timestamp;
+        nft_stake.total_claimed = safe_add!(nft_stake.total_claimed, rewards);
+
+        emit!(NftRewardsClaimedEvent {
+            vault: vault.key(),
+            owner: nft_stake.owner,
+            nft_mint: nft_stake.nft_mint,
+            amount: rewards,
+            reward_mint: vault.reward_mint,
+            total_claimed: nft_stake.total_claimed,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Unstake a Hatchling: pay its accrued rewards, return the NFT,
+    /// unlock it in pangi-nft and remove the APR boost
+    /// Fails if the reward pool cannot cover the rewards, unless `forfeit_unpaid` is set:
+    /// then the NFT is returned with whatever the pool can pay and the rest is given up
+    pub fn unstake_nft(ctx: Context<UnstakeNft>, forfeit_unpaid: bool) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let nft_stake = &ctx.accounts.nft_stake;
+        let clock = Clock::get()?;
+
+        let rewards = calculate_nft_rewards(
+            vault.nft_reward_rate,
+            nft_stake.rarity_weight,
+            nft_stake.last_claim,
+            reward_accrual_time(vault, clock.unix_timestamp),
+        )?;
+
+        let rewards_paid = nft_rewards_payable(
+            rewards,
+            spendable_rewards(vault, &ctx.accounts.reward_token_account),
+            forfeit_unpaid,
+        )?;
+
+        let seeds = &[
+            b"vault",
+            vault.nft_mint.as_ref(),
+            &[vault.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        if rewards_paid > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.reward_token_account.to_account_info(),
+                        to: ctx.accounts.owner_reward_account.to_account_info(),
+                        authority: vault.to_account_info(),
+                    },
+                    signer,
+                ),
+                rewards_paid,
+            )?;
+        }
+
+        // Return the NFT and close the escrow (rent back to owner)
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.nft_escrow.to_account_info(),
+                    to: ctx.accounts.owner_nft_account.to_account_info(),
+                    authority: vault.to_account_info(),
+                },
+                signer,
+            ),
+            1,
+        )?;
+
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.nft_escrow.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: vault.to_account_info(),
+            },
+            signer,
+        ))?;
+
+        pangi_nft::cpi::unlock_hatchling(CpiContext::new_with_signer(
+            ctx.accounts.nft_program.to_account_info(),
+            pangi_nft::cpi::accounts::UnlockHatchling {
+                hatchling: ctx.accounts.hatchling.to_account_info(),
+                authority: vault.to_account_info(),
+            },
+            signer,
+        ))?;
+
+        let boost_bps = nft_stake.boost_bps;
+        let nft_mint = nft_stake.nft_mint;
+        let total_claimed = safe_add!(nft_stake.total_claimed, rewards_paid);
+
+        let stake = &mut ctx.accounts.stake_record;
+        settle_stake_rewards(stake, &ctx.accounts.vault, clock.unix_timestamp)?;
+        stake.nft_boost_bps = safe_sub!(stake.nft_boost_bps, boost_bps);
+
+        let vault = &mut ctx.accounts.vault;
+        vault.total_nfts_staked = safe_sub!(vault.total_nfts_staked, 1);
+
+        emit!(NftUnstakedEvent {
+            vault: vault.key(),
+            owner: ctx.accounts.owner.key(),
+            nft_mint,
+            rewards_paid,
+            rewards_forfeited: safe_sub!(rewards, rewards_paid),
+            reward_mint: vault.reward_mint,
+            total_claimed,
+            total_nfts_staked: vault.total_nfts_staked,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create one shard of the voting power supply of a staked mint (GLOBAL_SHARD_COUNT per mint)
+    /// Locks only count towards the supply of their vault's token_mint
+    pub fn initialize_voting_power(ctx: Context<InitializeVotingPower>, shard: u8) -> Result<()> {
+        require!(shard < GLOBAL_SHARD_COUNT, ErrorCode::InvalidShard);
+
+        let ve_supply = &mut ctx.accounts.ve_supply;
+        let clock = Clock::get()?;
+
+        ve_supply.shard = shard;
+        ve_supply.mint = ctx.accounts.token_mint.key();
+        ve_supply.curve = VotingPowerCurve {
+            last_checkpoint: clock.unix_timestamp,
+            ..Default::default()
+        };
+        ve_supply.bump = ctx.bumps.ve_supply;
+
+        emit!(VotingPowerInitializedEvent {
+            ve_supply: ve_supply.key(),
+            mint: ve_supply.mint,
+            shard,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Initialize the global TVL cap registry shared by all vaults (once)
+    /// The initializer becomes the registry authority
+    pub fn initialize_global_caps(
+        ctx: Context<InitializeGlobalCaps>,
+        max_total_staked: u64,
+    ) -> Result<()> {
+        let global_caps = &mut ctx.accounts.global_caps;
+
+        global_caps.authority = ctx.accounts.authority.key();
+        global_caps.max_total_staked = max_total_staked;
+        global_caps.bump = ctx.bumps.global_caps;
+
+        emit!(GlobalCapUpdatedEvent {
+            authority: global_caps.authority,
+            max_total_staked,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create one shard of a staked mint's global total (GLOBAL_SHARD_COUNT per mint)
+    pub fn initialize_global_cap_shard(ctx: Context<InitializeGlobalCapShard>, shard: u8) -> Result<()> {
+        require!(shard < GLOBAL_SHARD_COUNT, ErrorCode::InvalidShard);
+
+        let global_cap_shard = &mut ctx.accounts.global_cap_shard;
+
+        global_cap_shard.mint = ctx.accounts.token_mint.key();
+        global_cap_shard.shard = shard;
+        global_cap_shard.total_staked = 0;
+        global_cap_shard.bump = ctx.bumps.global_cap_shard;
+
+        Ok(())
+    }
+
+    /// Update the global TVL cap (0 = uncapped)
+    /// Like the vault caps it bounds deposits only; compounded rewards still count towards the total
+    pub fn set_global_cap(ctx: Context<SetGlobalCap>, max_total_staked: u64) -> Result<()> {
+        let global_caps = &mut ctx.accounts.global_caps;
+
+        require!(
+            ctx.accounts.authority.key() == global_caps.authority,
+            ErrorCode::Unauthorized
+        );
+
+        global_caps.max_total_staked = max_total_staked;
+
+        emit!(GlobalCapUpdatedEvent {
+            authority: global_caps.authority,
+            max_total_staked,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Initialize the vault registry (once)
+    /// The initializer becomes the registry authority; defaults start at the built-in limits
+    pub fn initialize_vault_registry(ctx: Context<InitializeVaultRegistry>) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+
+        registry.authority = ctx.accounts.authority.key();
+        registry.vault_count = 0;
+        registry.min_lock_duration = MIN_LOCK_DURATION;
+        registry.max_lock_duration = MAX_LOCK_DURATION;
+        registry.max_reward_rate = MAX_REWARD_RATE;
+        registry.bump = ctx.bumps.registry;
+
+        emit!(VaultDefaultsUpdatedEvent {
+            authority: registry.authority,
+            min_lock_duration: registry.min_lock_duration,
+            max_lock_duration: registry.max_lock_duration,
+            max_reward_rate: registry.max_reward_rate,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Update the defaults new vaults are validated against
+    /// Existing vaults keep their lock duration and schedule
+    pub fn set_vault_defaults(
+        ctx: Context<SetVaultDefaults>,
+        min_lock_duration: i64,
+        max_lock_duration: i64,
+        max_reward_rate: u16,
+    ) -> Result<()> {
+        let registry = &mut ctx.accounts.registry;
+
+        require!(
+            ctx.accounts.authority.key() == registry.authority,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            min_lock_duration > 0 && min_lock_duration <= max_lock_duration,
+            ErrorCode::InvalidVaultDefaults
+        );
+        // vePANGI schedules slope changes at most VE_SLOPE_WEEKS ahead
+        require!(
+            max_lock_duration <= MAX_LOCK_DURATION,
+            ErrorCode::InvalidVaultDefaults
+        );
+        require!(
+            max_reward_rate <= MAX_REWARD_RATE,
+            ErrorCode::InvalidVaultDefaults
+        );
+
+        registry.min_lock_duration = min_lock_duration;
+        registry.max_lock_duration = max_lock_duration;
+        registry.max_reward_rate = max_reward_rate;
+
+        emit!(VaultDefaultsUpdatedEvent {
+            authority: registry.authority,
+            min_lock_duration,
+            max_lock_duration,
+            max_reward_rate,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Approve or revoke a vault's stakes as governance voting power (registry authority)
+    /// Approval requires a principal penalty on every early exit, so voting power cannot be
+    /// borrowed by locking and unlocking for free
+    /// Revoking takes the vault's stakes out of the ve_supply at once; after approval existing
+    /// stakes join it as they are checkpointed (see checkpoint_stake)
+    pub fn set_vault_voting(ctx: Context<SetVaultVoting>, approved: bool) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        require!(
+            ctx.accounts.authority.key() == ctx.accounts.registry.authority,
+            ErrorCode::Unauthorized
+        );
+        if approved {
+            require!(
+                principal_penalty_always_applies(vault),
+                ErrorCode::VotingRequiresPrincipalPenalty
+            );
+        }
+
+        vault.voting_approved = approved;
+        if !approved {
+            remove_vault_voting_power(&mut ctx.accounts.ve_supply, vault, Clock::get()?.unix_timestamp)?;
+        }
+
+        emit!(VaultVotingUpdatedEvent {
+            vault: vault.key(),
+            authority: ctx.accounts.authority.key(),
+            approved,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Permanently leave a stake out of the voting power supply
+    /// For owners that cannot vote (e.g. the stPANGI pool PDA), so their locks do not weigh on quorum
+    /// The owner proves it is a program address by its `owner_seeds` under `owner_program`
+    /// One-way: opting back in after a quorum snapshot would let a holder vote against a shrunken quorum
+    pub fn opt_out_of_voting(
+        ctx: Context<OptOutOfVoting>,
+        owner_program: Pubkey,
+        owner_seeds: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        let stake = &mut ctx.accounts.stake_record;
+        let clock = Clock::get()?;
+
+        require!(
+            ctx.accounts.authority.key() == stake_owner(stake, &ctx.accounts.position_account)?,
+            ErrorCode::Unauthorized
+        );
+        // Program addresses are off the ed25519 curve: no wallet key can sign for one
+        let seeds: Vec<&[u8]> = owner_seeds.iter().map(|seed| seed.as_slice()).collect();
+        require!(
+            Pubkey::create_program_address(&seeds, &owner_program)
+                .is_ok_and(|address| address == ctx.accounts.authority.key()),
+            ErrorCode::OwnerNotProgramAddress
+        );
+        require!(!stake.ve_opt_out, ErrorCode::AlreadyOptedOut);
+
+        stake.ve_opt_out = true;
+        checkpoint_voting_power(
+            &mut ctx.accounts.ve_supply,
+            &mut ctx.accounts.vault,
+            stake,
+            clock.unix_timestamp,
+        )?;
+        record_balance_checkpoint(stake, &clock);
+
+        emit!(VotingOptedOutEvent {
+            stake_record: stake.key(),
+            authority: ctx.accounts.authority.key(),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless checkpoint of a stake's voting power into its vault's ve_supply shard
+    /// Brings existing stakes into the supply after their vault is approved
+    pub fn checkpoint_stake(ctx: Context<CheckpointStake>) -> Result<()> {
+        let stake = &mut ctx.accounts.stake_record;
+        let clock = Clock::get()?;
+
+        let counted = (stake.ve_amount, stake.ve_unlock_at, stake.ve_epoch);
+        checkpoint_voting_power(
+            &mut ctx.accounts.ve_supply,
+            &mut ctx.accounts.vault,
+            stake,
+            clock.unix_timestamp,
+        )?;
+        // Only record real changes: anyone can call this, and must not flush the snapshot history
+        if (stake.ve_amount, stake.ve_unlock_at, stake.ve_epoch) != counted {
+            record_balance_checkpoint(stake, &clock);
+        }
+
+        emit!(VotingPowerCheckpointEvent {
+            stake_record: stake.key(),
+            authority: stake.authority,
+            voting_power: voting_power_at(stake.ve_amount, stake.ve_unlock_at, clock.unix_timestamp)?,
+            shard_voting_power: shard_voting_power_at(&ctx.accounts.ve_supply, clock.unix_timestamp)?,
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Credit a stake's scales points to its owner's aggregate (permissionless)
+    /// Settles the stake first; points already credited are never counted twice
+    pub fn sync_scales_points(ctx: Context<SyncScalesPoints>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let stake = &mut ctx.accounts.stake_record;
+        let clock = Clock::get()?;
+
+        let owner = stake_owner(stake, &ctx.accounts.position_account)?;
+        require!(ctx.accounts.owner.key() == owner, ErrorCode::Unauthorized);
+
+        settle_stake_rewards(stake, vault, clock.unix_timestamp)?;
+        let credited = safe_sub!(stake.scales_points, stake.points_synced);
+        stake.points_synced = stake.scales_points;
+
+        let user_points = &mut ctx.accounts.user_points;
+        if user_points.owner == Pubkey::default() {
+            user_points.owner = owner;
+            user_points.bump = ctx.bumps.user_points;
+        }
+        user_points.total_earned = safe_add!(user_points.total_earned, credited);
+        user_points.updated_at = clock.unix_timestamp;
+
+        emit!(ScalesPointsSyncedEvent {
+            vault: vault.key(),
+            stake_record: stake.key(),
+            owner,
+            credited,
+            balance: safe_sub!(user_points.total_earned, user_points.total_spent),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Spend scales points from the signer's aggregate
+    /// Intended for CPI from other programs (e.g. pangi-nft evolution discounts); the owner's
+    /// signature is forwarded with the CPI
+    pub fn spend_scales_points(ctx: Context<SpendScalesPoints>, amount: u128) -> Result<()> {
+        let user_points = &mut ctx.accounts.user_points;
+        let clock = Clock::get()?;
+
+        require!(amount > 0, ErrorCode::AmountTooSmall);
+        let balance = safe_sub!(user_points.total_earned, user_points.total_spent);
+        require!(amount <= balance, ErrorCode::InsufficientScalesPoints);
+
+        user_points.total_spent = safe_add!(user_points.total_spent, amount);
+        user_points.updated_at = clock.unix_timestamp;
+
+        emit!(ScalesPointsSpentEvent {
+            owner: user_points.owner,
+            amount,
+            balance: safe_sub!(balance, amount),
+            timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Read-only view of a user's scales points, returned via return data
+    /// Counts synced points only; call sync_scales_points first for an up-to-date balance
+    pub fn get_scales_points(ctx: Context<GetScalesPoints>) -> Result<ScalesPointsBalance> {
+        let user_points = &ctx.accounts.user_points;
+
+        Ok(ScalesPointsBalance {
+            owner: user_points.owner,
+            total_earned: user_points.total_earned,
+            total_spent: user_points.total_spent,
+            balance: safe_sub!(user_points.total_earned, user_points.total_spent),
+            timestamp: Clock::get()?.unix_timestamp,
+        })
+    }
+
+    /// Read-only view of a stake's balance at a past timestamp, returned via return data
+    /// For snapshot-based distributions (special-distribution, airdrops)
+    pub fn get_stake_at(ctx: Context<GetStakeAt>, timestamp: i64) -> Result<StakeSnapshot> {
+        let stake = &ctx.accounts.stake_record;
+
+        require!(
+            timestamp <= Clock::get()?.unix_timestamp,
+            ErrorCode::SnapshotInFuture
+        );
+
+        Ok(StakeSnapshot {
+            stake_record: stake.key(),
+            vault: stake.vault,
+            authority: stake.authority,
+            timestamp,
+            amount: stake_at(stake, timestamp)?,
+        })
+    }
+
+    /// Read-only view of the vault's insurance reserve health, returned via return data
+    pub fn get_reserve_health(ctx: Context<GetReserveHealth>) -> Result<ReserveHealth> {
+        reserve_health(
+            &ctx.accounts.vault,
+            ctx.accounts.reward_reserve.amount,
+            ctx.accounts.reward_token_account.amount,
+            Clock::get()?.unix_timestamp,
+        )
+    }
+
+    /// Read-only view of a stake's vePANGI voting power
+    /// Returned via return data so governance programs can read it over CPI
+    /// remaining_accounts must hold every ve_supply shard of the vault's token_mint, in shard order
+    pub fn get_voting_power(ctx: Context<GetVotingPower>) -> Result<VotingPower> {
+        let vault = &ctx.accounts.vault;
+        let stake = &ctx.accounts.stake_record;
+        let now = Clock::get()?.unix_timestamp;
+
+        Ok(VotingPower {
+            stake_record: stake.key(),
+            authority: stake.authority,
+            voting_power: if stake.ve_opt_out {
+                0
+            } else {
+                voting_power_at(stake.amount, stake.unlock_at, now)?
+            },
+            total_voting_power: total_voting_power_at(ctx.remaining_accounts, &vault.token_mint, now)?,
+            timestamp: now,
+        })
+    }
+
+    /// Read-only view of a stake position, returned via return data
+    /// Uses the same reward, early-unlock and schedule math as the state-changing instructions
+    pub fn preview_position(ctx: Context<PreviewPosition>) -> Result<PositionPreview> {
+        let vault = &ctx.accounts.vault;
+        let stake = &ctx.accounts.stake_record;
+        let now = Clock::get()?.unix_timestamp;
+        let schedule = reward_schedule(vault);
+        let pending_rewards = pending_stake_rewards(vault, stake, now)?;
+
+        // Withdrawing the full stake now
+        let is_early_unlock = vault.is_active && now < stake.unlock_at && stake.amount > 0;
+        let penalty_bps = if is_early_unlock {
+            early_unlock_penalty_bps(vault, stake.staked_at, stake.unlock_at, now)?
+        } else {
+            0
+        };
+        // A full early withdrawal forfeits the curve penalty; the rest stays claimable
+        let early_unlock_penalty = if is_early_unlock {
+            calculate_early_unlock_penalty(pending_rewards, stake.amount, stake.amount, penalty_bps)?
+        } else {
+            0
+        };
+        let early_unlock_forfeited_rewards = early_unlock_penalty;
+        let early_unlock_principal_penalty = if is_early_unlock && vault.penalize_principal {
+            safe_percentage!(stake.amount, penalty_bps)
+        } else {
+            0
+        };
+
+        let effective_apr_bps = if vault.is_active {
+            effective_reward_rate(current_reward_rate(schedule, now), stake.nft_boost_bps)
+        } else {
+            0
+        };
+
+        Ok(PositionPreview {
+            vault: vault.key(),
+            stake_record: stake.key(),
+            amount: stake.amount,
+            pending_rewards,
+            is_early_unlock,
+            early_unlock_forfeited_rewards,
+            early_unlock_penalty,
+            early_unlock_principal_penalty,
+            seconds_to_unlock: if vault.is_active {
+                safe_sub!(stake.unlock_at, now).max(0)
+            } else {
+                0
+            },
+            effective_apr_bps,
+            timestamp: now,
+        })
+    }
+}
+
+/// Rewards a stake holds at `current_time`: settled plus accrued since the last settlement
+/// Matches what settle_stake_rewards would leave in stake.pending_rewards
+pub fn pending_stake_rewards(vault: &Vault, stake: &StakeRecord, current_time: i64) -> Result<u64> {
+    let accrued = accrued_stake_rewards(
+        stake,
+        reward_index_at(vault, current_time)?,
+        reward_accrual_time(vault, current_time),
+    )?;
+    Ok(safe_add!(stake.pending_rewards, accrued))
+}
+
+/// Staked amount at `timestamp` from the stake's balance checkpoints
+/// Zero before the first checkpoint; errors once that history has been overwritten
+pub fn stake_at(stake: &StakeRecord, timestamp: i64) -> Result<u64> {
+    Ok(balance_checkpoint_at(stake, timestamp)?.map_or(0, |checkpoint| checkpoint.amount))
+}
+
+/// vePANGI voting power of a stake at `timestamp`, from the lock counted in the supply then
+/// Later withdrawals and re-locks do not change it. Zero if the vault's share has since been
+/// taken out of the supply as a whole (older ve_epoch)
+pub fn stake_voting_power_at(stake: &StakeRecord, vault: &Vault, timestamp: i64) -> Result<u64> {
+    match balance_checkpoint_at(stake, timestamp)? {
+        Some(checkpoint) if checkpoint.ve_epoch == vault.ve_epoch => {
+            voting_power_at(checkpoint.amount, checkpoint.ve_unlock_at, timestamp)
+        }
+        _ => Ok(0),
+    }
+}
+
+// Newest balance checkpoint at or before `timestamp` (None before the first one)
+fn balance_checkpoint_at(stake: &StakeRecord, timestamp: i64) -> Result<Option<BalanceCheckpoint>> {
+    let count = stake.checkpoint_count as usize;
+    let oldest = if count < MAX_BALANCE_CHECKPOINTS {
+        0
+    } else {
+        stake.checkpoint_head as usize
+    };
+
+    // Newest to oldest: last balance recorded at or before `timestamp`
+    for i in (0..count).rev() {
+        let checkpoint = &stake.balance_checkpoints[(oldest + i) % MAX_BALANCE_CHECKPOINTS];
+        if checkpoint.timestamp <= timestamp {
+            return Ok(Some(*checkpoint));
+        }
+    }
+
+    require!(
+        count < MAX_BALANCE_CHECKPOINTS,
+        ErrorCode::SnapshotHistoryUnavailable
+    );
+    Ok(None)
+}
+
+/// vePANGI voting power of a lock at `current_time`
+/// voting_power = amount × remaining_lock / VOTING_POWER_MAX_LOCK (zero once unlocked)
+pub fn voting_power_at(amount: u64, unlock_at: i64, current_time: i64) -> Result<u64> {
+    let lock_end = ve_lock_end(unlock_at);
+    if lock_end <= current_time {
+        return Ok(0);
+    }
+
+    let remaining = safe_sub!(lock_end, current_time);
+    let power = (amount as u128)
+        .checked_mul(remaining as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(VOTING_POWER_MAX_LOCK as u128)
+        .ok_or(ErrorCode::DivisionByZero)?;
+
+    require!(power <= u64::MAX as u128, ErrorCode::Overflow);
+    Ok(power as u64)
+}
+
+/// Lock end counted for vePANGI: `unlock_at` rounded down to a week boundary
+pub fn ve_lock_end(unlock_at: i64) -> i64 {
+    unlock_at.div_euclid(VE_WEEK) * VE_WEEK
+}
+
+/// vePANGI voting power of one ve_supply shard at `current_time`, extrapolated from the last checkpoint
+/// Applies the scheduled slope changes, so expired locks drop out without being checkpointed
+pub fn shard_voting_power_at(supply: &VotingPowerSupply, current_time: i64) -> Result<u64> {
+    let mut curve = supply.curve;
+    advance_voting_curve(&mut curve, current_time)?;
+
+    let power = curve.bias.max(0) / VOTING_POWER_MAX_LOCK as i128;
+    require!(power <= u64::MAX as i128, ErrorCode::Overflow);
+    Ok(power as u64)
+}
+
+/// Total voting power of `mint` stakers at `current_time`, summed over the mint's ve_supply shards
+/// `shards` must be the mint's GLOBAL_SHARD_COUNT shard accounts, in shard order
+pub fn total_voting_power_at(shards: &[AccountInfo], mint: &Pubkey, current_time: i64) -> Result<u64> {
+    require!(shards.len() == GLOBAL_SHARD_COUNT as usize, ErrorCode::InvalidShard);
+
+    let mut total: u64 = 0;
+    for (i, info) in shards.iter().enumerate() {
+        require!(info.owner == &ID, ErrorCode::InvalidShard);
+        let supply = VotingPowerSupply::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        // Supplies only exist at their (mint, shard) PDA, so distinct indexes mean distinct shards
+        require!(supply.mint == *mint && supply.shard as usize == i, ErrorCode::InvalidShard);
+        total = safe_add!(total, shard_voting_power_at(&supply, current_time)?);
+    }
+
+    Ok(total)
+}
+
+// Ring slot holding the slope change scheduled at `week_start`
+fn slope_change_slot(week_start: i64) -> usize {
+    week_start.div_euclid(VE_WEEK).rem_euclid(VE_SLOPE_WEEKS as i64) as usize
+}
+
+// Move the curve to `current_time` week by week, dropping the slope of locks ending at each boundary
+// Lock ends are week-aligned, so bias reaches exactly zero as the last lock expires
+fn advance_voting_curve(curve: &mut VotingPowerCurve, current_time: i64) -> Result<()> {
+    let mut t = curve.last_checkpoint;
+
+    // Every scheduled change lies within VE_SLOPE_WEEKS of the last checkpoint
+    for _ in 0..=VE_SLOPE_WEEKS {
+        if t >= current_time {
+            break;
+        }
+        let next_week = safe_add!(ve_lock_end(t), VE_WEEK);
+        let step_end = next_week.min(current_time);
+        curve.bias = safe_sub!(
+            curve.bias,
+            safe_mul!(curve.slope, safe_sub!(step_end, t) as i128)
+        );
+        if step_end == next_week {
+            let slot = slope_change_slot(next_week);
+            curve.slope = safe_sub!(curve.slope, curve.slope_changes[slot]);
+            curve.slope_changes[slot] = 0;
+        }
+        t = step_end;
+    }
+    // Past the horizon every lock has ended and slope is zero
+    if t < current_time {
+        curve.bias = safe_sub!(
+            curve.bias,
+            safe_mul!(curve.slope, safe_sub!(current_time, t) as i128)
+        );
+    }
+
+    curve.last_checkpoint = curve.last_checkpoint.max(current_time);
+    Ok(())
+}
+
+// Add a lock of `slope` (its amount) ending at week-aligned `lock_end` to a curve already
+// advanced to `current_time`; a negative slope removes it
+fn apply_lock(
+    curve: &mut VotingPowerCurve,
+    slope: i128,
+    lock_end: i64,
+    current_time: i64,
+) -> Result<()> {
+    let remaining = safe_sub!(lock_end, current_time) as i128;
+    let slot = slope_change_slot(lock_end);
+    curve.bias = safe_add!(curve.bias, safe_mul!(slope, remaining));
+    curve.slope = safe_add!(curve.slope, slope);
+    curve.slope_changes[slot] = safe_add!(curve.slope_changes[slot], slope);
+    Ok(())
+}
+
+/// Whether stakes in `vault` count as governance voting power
+/// Deactivated vaults unlock penalty-free, so their stakes carry none
+pub fn vault_carries_voting_power(vault: &Vault) -> bool {
+    vault.is_active && vault.voting_approved && principal_penalty_always_applies(vault)
+}
+
+// Every early exit pays a principal penalty: Flat with non-zero bps, or Stepped with a
+// non-zero step covering any remaining time. LinearDecay rounds down to zero near unlock
+fn principal_penalty_always_applies(vault: &Vault) -> bool {
+    let steps = &vault.penalty_steps[..vault.penalty_step_count as usize];
+    vault.penalize_principal
+        && match vault.penalty_curve {
+            PenaltyCurve::Flat => vault.penalty_bps > 0,
+            PenaltyCurve::LinearDecay => false,
+            PenaltyCurve::Stepped => {
+                steps.first().is_some_and(|step| step.min_remaining <= 0)
+                    && steps.iter().all(|step| step.penalty_bps > 0)
+            }
+        }
+}
+
+// Move the shard supply and the vault's share to `current_time`, then swap the stake's previously
+// counted lock (ve_amount, ve_unlock_at) for its current one (amount, week-aligned unlock_at).
+// A lock that already ended left the supply through its scheduled slope change, and one counted
+// under an older vault.ve_epoch left it with the vault's whole share.
+fn checkpoint_voting_power(
+    supply: &mut VotingPowerSupply,
+    vault: &mut Vault,
+    stake: &mut StakeRecord,
+    current_time: i64,
+) -> Result<()> {
+    advance_voting_curve(&mut supply.curve, current_time)?;
+    advance_voting_curve(&mut vault.ve_curve, current_time)?;
+
+    // Remove the old contribution and its scheduled slope change
+    if stake.ve_epoch == vault.ve_epoch && stake.ve_amount > 0 && stake.ve_unlock_at > current_time {
+        let old_slope = -(stake.ve_amount as i128);
+        apply_lock(&mut supply.curve, old_slope, stake.ve_unlock_at, current_time)?;
+        apply_lock(&mut vault.ve_curve, old_slope, stake.ve_unlock_at, current_time)?;
+    }
+
+    // Add the new contribution only while the lock is still running (and the stake and vault vote)
+    let lock_end = ve_lock_end(stake.unlock_at);
+    if vault_carries_voting_power(vault)
+        && stake.amount > 0
+        && !stake.ve_opt_out
+        && lock_end > current_time
+    {
+        let new_slope = stake.amount as i128;
+        apply_lock(&mut supply.curve, new_slope, lock_end, current_time)?;
+        apply_lock(&mut vault.ve_curve, new_slope, lock_end, current_time)?;
+        stake.ve_amount = stake.amount;
+        stake.ve_unlock_at = lock_end;
+    } else {
+        stake.ve_amount = 0;
+        stake.ve_unlock_at = 0;
+    }
+    stake.ve_epoch = vault.ve_epoch;
+
+    Ok(())
+}
+
+// Take the vault's whole share out of its shard supply (deactivation / loss of approval)
+// Bumping ve_epoch keeps stakes counted so far from being removed again at their next checkpoint
+fn remove_vault_voting_power(
+    supply: &mut VotingPowerSupply,
+    vault: &mut Vault,
+    current_time: i64,
+) -> Result<()> {
+    advance_voting_curve(&mut supply.curve, current_time)?;
+    advance_voting_curve(&mut vault.ve_curve, current_time)?;
+
+    let share = &vault.ve_curve;
+    supply.curve.bias = safe_sub!(supply.curve.bias, share.bias);
+    supply.curve.slope = safe_sub!(supply.curve.slope, share.slope);
+    for (change, vault_change) in supply.curve.slope_changes.iter_mut().zip(share.slope_changes) {
+        *change = safe_sub!(*change, vault_change);
+    }
+
+    vault.ve_curve = VotingPowerCurve {
+        last_checkpoint: current_time,
+        ..Default::default()
+    };
+    vault.ve_epoch = safe_add!(vault.ve_epoch, 1);
+    Ok(())
+}
+
+// Verify `signer` currently holds the Master NFT (`nft_mint`) in `master_nft_account`
+// Vault control follows the NFT: selling it hands over the vault
+fn verify_master(
+    nft_mint: Pubkey,
+    master_nft_account: &TokenAccount,
+    signer: Pubkey,
+) -> Result<()> {
+    require!(
+        master_nft_account.mint == nft_mint
+            && master_nft_account.owner == signer
+            && master_nft_account.amount == 1,
+        ErrorCode::NotMasterHolder
+    );
+
+    Ok(())
+}
+
+/// Current owner of a stake: the depositor, or the holder of its position NFT
+pub fn stake_owner(
+    stake: &StakeRecord,
+    position_account: &Option<Account<TokenAccount>>,
+) -> Result<Pubkey> {
+    if stake.position_mint == Pubkey::default() {
+        return Ok(stake.authority);
+    }
+
+    let Some(position_account) = position_account else {
+        return Err(ErrorCode::InvalidPositionAccount.into());
+    };
+    require!(
+        position_account.mint == stake.position_mint && position_account.amount == 1,
+        ErrorCode::InvalidPositionAccount
+    );
+
+    Ok(position_account.owner)
+}
+
+// Validate `depositor` against the vault access mode
+fn verify_deposit_access(
+    vault: &Vault,
+    master_nft_account: &Option<Account<TokenAccount>>,
+    depositor: Pubkey,
+    proof: &[[u8; 32]],
+) -> Result<()> {
+    match vault.access_mode {
+        VaultAccessMode::OwnerOnly => {
+            let Some(master_nft_account) = master_nft_account else {
+                return Err(ErrorCode::NotMasterHolder.into());
+            };
+            verify_master(vault.nft_mint, master_nft_account, depositor)
+        }
+        VaultAccessMode::Open => Ok(()),
+        VaultAccessMode::Allowlist => {
+            require!(
+                verify_allowlist_proof(&vault.allowlist_root, depositor, proof),
+                ErrorCode::DepositNotAllowed
+            );
+            Ok(())
+        }
+    }
+}
+
+// Clamp a deposit to the vault, per-user and global caps
+// The global cap bounds the vault token_mint's total across all its shards (`cap_shards`)
+// Over a cap: reject, or fill up to the cap if the vault allows it
+fn capped_deposit_amount(
+    vault: &Account<Vault>,
+    global_caps: &GlobalCapRegistry,
+    cap_shards: &[AccountInfo],
+    user_stake: u64,
+    depositor: Pubkey,
+    requested: u64,
+    current_time: i64,
+) -> Result<u64> {
+    let vault_room = cap_room(vault.max_total_staked, vault.total_staked);
+    let user_room = cap_room(vault.max_user_stake, user_stake);
+    let global_room = if global_caps.max_total_staked == 0 {
+        u64::MAX
+    } else {
+        cap_room(
+            global_caps.max_total_staked,
+            total_staked_for_mint(cap_shards, &vault.token_mint)?,
+        )
+    };
+    let room = vault_room.min(user_room).min(global_room);
+
+    let filled = deposit_fill(requested, room, vault.allow_partial_fill)?;
+    if filled == requested {
+        return Ok(requested);
+    }
+
+    emit!(DepositClampedEvent {
+        vault: vault.key(),
+        authority: depositor,
+        requested,
+        filled,
+        vault_room,
+        user_room,
+        global_room,
+        timestamp: current_time,
+    });
+
+    Ok(filled)
+}
+
+// Part of `requested` a deposit may take with `room` left under the tightest cap
+// Over the cap: reject, or fill `room` if partial fills are on and it is not dust
+fn deposit_fill(requested: u64, room: u64, allow_partial_fill: bool) -> Result<u64> {
+    if requested <= room {
+        return Ok(requested);
+    }
+
+    require!(
+        allow_partial_fill && room >= MIN_STAKE_AMOUNT,
+        ErrorCode::DepositCapExceeded
+    );
+
+    Ok(room)
+}
+
+// Verify `wallet` is a leaf of the allowlist Merkle tree rooted at `root`
+// Leaf = sha256(wallet); parents hash the sorted pair so proofs carry no direction bits
+fn verify_allowlist_proof(root: &[u8; 32], wallet: Pubkey, proof: &[[u8; 32]]) -> bool {
+    let mut node = hashv(&[wallet.as_ref()]).to_bytes();
+    for sibling in proof {
+        node = if node <= *sibling {
+            hashv(&[&node, sibling]).to_bytes()
+        } else {
+            hashv(&[sibling, &node]).to_bytes()
+        };
+    }
+    node == *root
+}
+
+// Verify `signer` holds the Guardian NFT of `stake_record`'s GuardianConfig
+// and that the config grants `permission`
+fn verify_guardian(
+    config: &GuardianConfig,
+    nft_account: &TokenAccount,
+    stake_record: Pubkey,
+    signer: Pubkey,
+    permission: u8,
+) -> Result<()> {
+    require!(
+        config.stake_record == stake_record,
+        ErrorCode::InvalidGuardianConfig
+    );
+    require!(
+        config.permissions & permission == permission,
+        ErrorCode::GuardianPermissionDenied
+    );
+    require!(
+        nft_account.mint == config.guardian_nft_mint
+            && nft_account.owner == signer
+            && nft_account.amount == 1,
+        ErrorCode::NotGuardianHolder
+    );
+
+    Ok(())
+}
+
+// Signer must hold the Guardian NFT (permission-independent: bond actions)
+fn verify_guardian_holder(
+    guardian_nft_mint: Pubkey,
+    nft_account: &TokenAccount,
+    signer: Pubkey,
+) -> Result<()> {
+    require!(
+        nft_account.mint == guardian_nft_mint
+            && nft_account.owner == signer
+            && nft_account.amount == 1,
+        ErrorCode::NotGuardianHolder
+    );
+
+    Ok(())
+}
+
+// Move the disputed amount from a Guardian bond to the conservation fund and close the dispute
+fn slash_guardian_bond(accounts: &mut SlashGuardianBond, current_time: i64) -> Result<()> {
+    let bond = &accounts.guardian_bond;
+    let slashed = bond.dispute_amount.min(bond.amount);
+
+    let seeds = &[
+        b"guardian_bond",
+        bond.stake_record.as_ref(),
+        bond.guardian_nft_mint.as_ref(),
+        &[bond.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            accounts.token_program.to_account_info(),
+            Transfer {
+                from: accounts.bond_token_account.to_account_info(),
+                to: accounts.conservation_fund.to_account_info(),
+                authority: accounts.guardian_bond.to_account_info(),
+            },
+            signer,
+        ),
+        slashed,
+    )?;
+
+    let bond = &mut accounts.guardian_bond;
+    bond.amount = safe_sub!(bond.amount, slashed);
+    bond.total_slashed = safe_add!(bond.total_slashed, slashed);
+    let disputed_by = bond.disputed_by;
+    let evidence_hash = bond.evidence_hash;
+    clear_dispute(bond);
+
+    emit!(GuardianSlashedEvent {
+        vault: bond.vault,
+        stake_record: bond.stake_record,
+        guardian_nft_mint: bond.guardian_nft_mint,
+        disputed_by,
+        evidence_hash,
+        slashed,
+        bond_amount: bond.amount,
+        conservation_fund: accounts.conservation_fund.key(),
+        timestamp: current_time,
+    });
+
+    Ok(())
+}
+
+// Open a dispute over `slash_amount` of the bond; the challenge window starts now
+fn open_dispute(
+    bond: &mut GuardianBond,
+    disputer: Pubkey,
+    slash_amount: u64,
+    evidence_hash: [u8; 32],
+    current_time: i64,
+) -> Result<()> {
+    require!(
+        bond.dispute_state == GuardianDisputeState::None,
+        ErrorCode::DisputeAlreadyOpen
+    );
+    require!(
+        slash_amount > 0 && slash_amount <= bond.amount,
+        ErrorCode::InvalidSlashAmount
+    );
+
+    bond.dispute_state = GuardianDisputeState::Open;
+    bond.dispute_amount = slash_amount;
+    bond.disputed_by = disputer;
+    bond.evidence_hash = evidence_hash;
+    bond.challenge_ends_at = safe_add!(current_time, GUARDIAN_CHALLENGE_WINDOW);
+
+    Ok(())
+}
+
+// Contest an open dispute; only inside its challenge window
+fn challenge_dispute(bond: &mut GuardianBond, current_time: i64) -> Result<()> {
+    require!(
+        bond.dispute_state == GuardianDisputeState::Open,
+        ErrorCode::NoOpenDispute
+    );
+    require!(
+        current_time < bond.challenge_ends_at,
+        ErrorCode::ChallengeWindowClosed
+    );
+
+    bond.dispute_state = GuardianDisputeState::Challenged;
+
+    Ok(())
+}
+
+// An unchallenged dispute is slashable once its challenge window has passed
+fn require_unchallenged_slash(bond: &GuardianBond, current_time: i64) -> Result<()> {
+    require!(
+        bond.dispute_state == GuardianDisputeState::Open,
+        ErrorCode::NoOpenDispute
+    );
+    require!(
+        current_time >= bond.challenge_ends_at,
+        ErrorCode::ChallengeWindowActive
+    );
+
+    Ok(())
+}
+
+fn clear_dispute(bond: &mut GuardianBond) {
+    bond.dispute_state = GuardianDisputeState::None;
+    bond.dispute_amount = 0;
+    bond.disputed_by = Pubkey::default();
+    bond.evidence_hash = [0u8; 32];
+    bond.challenge_ends_at = 0;
+}
+
+// Guardian path of instructions that also accept the stake owner
+// (Guardian accounts are optional there and must both be present)
+fn verify_optional_guardian(
+    guardian_config: &Option<Account<GuardianConfig>>,
+    guardian_nft_account: &Option<Account<TokenAccount>>,
+    stake_record: Pubkey,
+    signer: Pubkey,
+    permission: u8,
+) -> Result<()> {
+    let (Some(config), Some(nft_account)) = (guardian_config, guardian_nft_account) else {
+        return Err(ErrorCode::Unauthorized.into());
+    };
+
+    verify_guardian(config, nft_account, stake_record, signer, permission)
+}
+
+// Move a stake's pending rewards from the reward pool into staked principal
+// Returns the compounded amount; staked_at and unlock_at are left untouched
+fn compound_stake_rewards(accounts: &mut CompoundRewards, current_time: i64) -> Result<u64> {
+    let vault = &accounts.vault;
+    let stake = &accounts.stake_record;
+
+    require!(vault.is_active, ErrorCode::VaultInactive);
+    require!(
+        vault.reward_mint == vault.token_mint,
+        ErrorCode::RewardMintMismatch
+    );
+
+    let time_since_last_claim = safe_sub!(current_time, stake.last_claim);
+    require!(
+        time_since_last_claim >= CLAIM_COOLDOWN,
+        ErrorCode::ClaimCooldownActive
+    );
+
+    settle_stake_rewards(&mut accounts.stake_record, vault, current_time)?;
+    let pending_rewards = accounts.stake_record.pending_rewards;
+    require!(pending_rewards > 0, ErrorCode::NoRewardsToClaim);
+
+    draw_reserve_shortfall(
+        &mut accounts.vault,
+        &mut accounts.reward_token_account,
+        &accounts.reward_reserve,
+        &accounts.token_program,
+        pending_rewards,
+        current_time,
+    )?;
+    let vault = &accounts.vault;
+    require!(
+        spendable_rewards(vault, &accounts.reward_token_account) >= pending_rewards,
+        ErrorCode::InsufficientVaultBalance
+    );
+
+    let seeds = &[
+        b"vault",
+        vault.nft_mint.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            accounts.token_program.to_account_info(),
+            Transfer {
+                from: accounts.reward_token_account.to_account_info(),
+                to: accounts.vault_token_account.to_account_info(),
+                authority: vault.to_account_info(),
+            },
+            signer,
+        ),
+        pending_rewards,
+    )?;
+
+    let stake = &mut accounts.stake_record;
+    stake.amount = safe_add!(stake.amount, pending_rewards);
+    stake.pending_rewards = 0;
+    stake.last_claim = current_time;
+    stake.total_claimed = safe_add!(stake.total_claimed, pending_rewards);
+    stake.total_compounded = safe_add!(stake.total_compounded, pending_rewards);
+    credit_referral(stake, &mut accounts.vault, pending_rewards)?;
+
+    checkpoint_voting_power(&mut accounts.ve_supply, &mut accounts.vault, stake, current_time)?;
+    record_balance_checkpoint(stake, &Clock::get()?);
+
+    let vault = &mut accounts.vault;
+    advance_vault_rewards(vault, current_time)?;
+    vault.total_staked = safe_add!(vault.total_staked, pending_rewards);
+
+    // Exempt from deposit caps (see set_deposit_caps), but still counted in the global total
+    let global_cap_shard = &mut accounts.global_cap_shard;
+    global_cap_shard.total_staked = safe_add!(global_cap_shard.total_staked, pending_rewards);
+
+    Ok(pending_rewards)
+}
+
+// Record stake.amount and its counted lock after a balance or voting change in the stake's checkpoint ring
+// Changes within the same second overwrite the newest entry
+fn record_balance_checkpoint(stake: &mut StakeRecord, clock: &Clock) {
+    let checkpoint = BalanceCheckpoint {
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+        amount: stake.amount,
+        ve_unlock_at: stake.ve_unlock_at,
+        ve_epoch: stake.ve_epoch,
+    };
+
+    let count = stake.checkpoint_count as usize;
+    let head = stake.checkpoint_head as usize;
+    if count > 0 {
+        let newest = (head + MAX_BALANCE_CHECKPOINTS - 1) % MAX_BALANCE_CHECKPOINTS;
+        if stake.balance_checkpoints[newest].timestamp == clock.unix_timestamp {
+            stake.balance_checkpoints[newest] = checkpoint;
+            return;
+        }
+    }
+
+    stake.balance_checkpoints[head] = checkpoint;
+    stake.checkpoint_head = ((head + 1) % MAX_BALANCE_CHECKPOINTS) as u8;
+    stake.checkpoint_count = (count + 1).min(MAX_BALANCE_CHECKPOINTS) as u8;
+}
+
+// Settle rewards accrued on the current balance and rate into stake.pending_rewards
+// and scales points
+// Must run before any change to stake.amount or stake.nft_boost_bps
+fn settle_stake_rewards(stake: &mut StakeRecord, vault: &Vault, current_time: i64) -> Result<()> {
+    let accrual_time = reward_accrual_time(vault, current_time);
+    settle_rewards(stake, reward_index_at(vault, current_time)?, accrual_time)?;
+    settle_points(stake, vault.lock_duration, accrual_time)?;
+    Ok(())
+}
+
+// Credit the referrer's share of rewards paid to the staker (claimed or compounded)
+// Rewards forfeited on early unlock earn the referrer nothing
+// The vault tracks the total owed so other payouts leave it in the pool
+fn credit_referral(stake: &mut StakeRecord, vault: &mut Vault, paid: u64) -> Result<()> {
+    if stake.referrer != Pubkey::default() {
+        let share = safe_percentage!(paid, vault.referral_bps);
+        stake.referral_pending = safe_add!(stake.referral_pending, share);
+        vault.referral_pending = safe_add!(vault.referral_pending, share);
+    }
+    Ok(())
+}
+
+// Settle against the vault reward index at `accrual_time`; neither the index paid
+// nor the accrual time ever rewinds
+fn settle_rewards(stake: &mut StakeRecord, reward_index: u128, accrual_time: i64) -> Result<()> {
+    let accrued = accrued_stake_rewards(stake, reward_index, accrual_time)?;
+    stake.pending_rewards = safe_add!(stake.pending_rewards, accrued);
+    stake.reward_index_paid = stake.reward_index_paid.max(reward_index);
+    stake.rewards_accrued_at = stake.rewards_accrued_at.max(accrual_time);
+    Ok(())
+}
+
+// Rewards accrued since the stake's last settlement: base rewards from the vault
+// reward index, plus the NFT boost on top over the elapsed time
+fn accrued_stake_rewards(stake: &StakeRecord, reward_index: u128, accrual_time: i64) -> Result<u64> {
+    let index_delta = reward_index.saturating_sub(stake.reward_index_paid);
+    let base = safe_div!(
+        safe_mul!(stake.amount as u128, index_delta),
+        REWARD_INDEX_PRECISION as u128
+    );
+    require!(base <= u64::MAX as u128, ErrorCode::Overflow);
+    let boost = calculate_boost_rewards(
+        stake.amount,
+        stake.nft_boost_bps,
+        stake.rewards_accrued_at,
+        accrual_time,
+    )?;
+    Ok(safe_add!(base as u64, boost))
+}
+
+// Accrue scales points on the current balance up to `accrual_time`
+// Points only grow: withdrawals lower future accrual but never remove points
+fn settle_points(stake: &mut StakeRecord, lock_duration: i64, accrual_time: i64) -> Result<()> {
+    if accrual_time > stake.points_accrued_at {
+        let accrued = scales_points_for(
+            stake.amount,
+            lock_duration,
+            safe_sub!(accrual_time, stake.points_accrued_at),
+        )?;
+        stake.scales_points = safe_add!(stake.scales_points, accrued);
+        stake.points_accrued_at = accrual_time;
+    }
+    Ok(())
+}
+
+/// Scales points for holding `amount` for `seconds` in a vault with `lock_duration`
+/// points = amount × days × tier multiplier (amount in raw token units)
+pub fn scales_points_for(amount: u64, lock_duration: i64, seconds: i64) -> Result<u128> {
+    let numerator = safe_mul!(
+        safe_mul!(amount as u128, seconds.max(0) as u128),
+        scales_multiplier_bps(lock_duration) as u128
+    );
+    Ok(safe_div!(
+        numerator,
+        SCALES_MULTIPLIER_DENOMINATOR as u128 * SECONDS_PER_DAY as u128
+    ))
+}
+
+/// Tier multiplier (bps) by vault lock duration: longer locks earn points faster
+pub fn scales_multiplier_bps(lock_duration: i64) -> u64 {
+    match lock_duration {
+        d if d >= 180 * SECONDS_PER_DAY => 20000, // 6+ months: 2x
+        d if d >= 90 * SECONDS_PER_DAY => 15000,  // 3+ months: 1.5x
+        d if d >= 30 * SECONDS_PER_DAY => 12500,  // 1+ month: 1.25x
+        _ => 10000,                               // 1x
+    }
+}
+
+// Update a vault's status in its registry index entry
+fn set_index_status(
+    index_page: &mut VaultIndexPage,
+    vault: &Account<Vault>,
+    status: VaultStatus,
+) -> Result<()> {
+    let slot = (vault.registry_index % VAULT_INDEX_PAGE_SIZE as u64) as usize;
+    let entry = &mut index_page.entries[slot];
+    require!(entry.vault == vault.key(), ErrorCode::InvalidVaultIndex);
+    entry.status = status;
+    Ok(())
+}
+
+// Early-unlock penalty (bps) for exiting at `current_time` under the vault's curve
+// Flat: penalty_bps; LinearDecay: penalty_bps scaled by remaining / total lock;
+// Stepped: the step with the largest min_remaining <= remaining lock time
+fn early_unlock_penalty_bps(
+    vault: &Vault,
+    staked_at: i64,
+    unlock_at: i64,
+    current_time: i64,
+) -> Result<u16> {
+    let remaining = safe_sub!(unlock_at, current_time);
+    if remaining <= 0 {
+        return Ok(0);
+    }
+
+    match vault.penalty_curve {
+        PenaltyCurve::Flat => Ok(vault.penalty_bps),
+        PenaltyCurve::LinearDecay => {
+            let lock_duration = safe_sub!(unlock_at, staked_at);
+            if lock_duration <= 0 {
+                return Ok(0);
+            }
+            let bps = safe_div!(
+                safe_mul!(vault.penalty_bps as u128, remaining.min(lock_duration) as u128),
+                lock_duration as u128
+            );
+            Ok(bps as u16)
+        }
+        PenaltyCurve::Stepped => Ok(vault.penalty_steps[..vault.penalty_step_count as usize]
+            .iter()
+            .rev()
+            .find(|step| step.min_remaining <= remaining)
+            .map_or(0, |step| step.penalty_bps)),
+    }
+}
+
+// Rate of the epoch in force at `current_time` (0 before the schedule starts)
+fn current_reward_rate(schedule: &[RewardEpoch], current_time: i64) -> u16 {
+    schedule
+        .iter()
+        .rev()
+        .find(|epoch| epoch.start_time <= current_time)
+        .map_or(0, |epoch| epoch.rate)
+}
+
+// Active epochs of the vault's reward schedule, ordered by start_time
+fn reward_schedule(vault: &Vault) -> &[RewardEpoch] {
+    &vault.reward_epochs[..vault.reward_epoch_count as usize]
+}
+
+// Advance vault-level reward accounting (base schedule, no NFT boosts) to `current_time`
+// Runs before every total_staked change; returns the rewards accrued since the last update
+fn advance_vault_rewards(vault: &mut Vault, current_time: i64) -> Result<u64> {
+    let accrual_end = reward_accrual_time(vault, current_time);
+    if accrual_end <= vault.last_reward_update {
+        return Ok(0);
+    }
+
+    let accrued = calculate_pending_rewards(
+        vault.total_staked,
+        reward_schedule(vault),
+        vault.last_reward_update,
+        accrual_end,
+    )?;
+
+    vault.total_rewards_accrued = safe_add!(vault.total_rewards_accrued, accrued);
+    vault.crank_tips_pending = safe_add!(
+        vault.crank_tips_pending,
+        safe_percentage!(accrued, CRANK_TIP_BPS)
+    );
+    vault.reward_index = reward_index_at(vault, current_time)?;
+    vault.last_reward_update = accrual_end;
+
+    Ok(accrued)
+}
+
+// Vault reward index as of `current_time`: the stored index plus base accrual per
+// REWARD_INDEX_PRECISION staked units since last_reward_update, less the crank tip share
+fn reward_index_at(vault: &Vault, current_time: i64) -> Result<u128> {
+    let index_delta = calculate_pending_rewards(
+        REWARD_INDEX_PRECISION,
+        reward_schedule(vault),
+        vault.last_reward_update,
+        reward_accrual_time(vault, current_time),
+    )?;
+    let staker_delta = safe_div!(
+        safe_mul!(index_delta as u128, (10000 - CRANK_TIP_BPS) as u128),
+        10000u128
+    );
+    Ok(safe_add!(vault.reward_index, staker_delta))
+}
+
+// Time stake rewards accrue up to: rewards stop at deactivation
+fn reward_accrual_time(vault: &Vault, current_time: i64) -> i64 {
+    if vault.is_active {
+        current_time
+    } else {
+        current_time.min(vault.deactivated_at)
+    }
+}
+
+// An unbonding entry pays out once its period has passed, or at once if the vault is deactivated
+fn unbonding_released(entry: &UnbondingEntry, vault_active: bool, current_time: i64) -> bool {
+    current_time >= entry.release_at || !vault_active
+}
+
+// Tokens of `mint` staked across every vault, summed over the mint's global cap shards
+// `shards` must be the mint's GLOBAL_SHARD_COUNT shard accounts, in shard order
+fn total_staked_for_mint(shards: &[AccountInfo], mint: &Pubkey) -> Result<u64> {
+    require!(shards.len() == GLOBAL_SHARD_COUNT as usize, ErrorCode::InvalidShard);
+
+    let mut total: u64 = 0;
+    for (i, info) in shards.iter().enumerate() {
+        require!(info.owner == &ID, ErrorCode::InvalidShard);
+        let shard = GlobalCapShard::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        // Shards only exist at their (mint, shard) PDA, so distinct indexes mean distinct shards
+        require!(shard.mint == *mint && shard.shard as usize == i, ErrorCode::InvalidShard);
+        total = safe_add!(total, shard.total_staked);
+    }
+
+    Ok(total)
+}
+
+// Remaining room under `cap` (0 = uncapped)
+fn cap_room(cap: u64, current: u64) -> u64 {
+    if cap == 0 {
+        u64::MAX
+    } else {
+        cap.saturating_sub(current)
+    }
+}
+
+// Pay a stake's accrued referral share from the reward pool to the referrer
+fn pay_referral_pending<'info>(
+    vault: &mut Account<'info, Vault>,
+    stake: &mut Account<'info, StakeRecord>,
+    referrer_stats: &mut Account<'info, ReferrerStats>,
+    referrer_reward_account: &Account<'info, TokenAccount>,
+    reward_token_account: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    now: i64,
+) -> Result<()> {
+    let amount = stake.referral_pending;
+    // The share is held back from spendable_rewards, so only the reserve earmark is excluded here
+    require!(
+        reward_token_account.amount.saturating_sub(vault.reserve_pending) >= amount,
+        ErrorCode::InsufficientVaultBalance
+    );
+
+    let seeds = &[
+        b"vault",
+        vault.nft_mint.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: reward_token_account.to_account_info(),
+                to: referrer_reward_account.to_account_info(),
+                authority: vault.to_account_info(),
+            },
+            signer,
+        ),
+        amount,
+    )?;
+
+    stake.referral_pending = 0;
+    vault.referral_pending = safe_sub!(vault.referral_pending, amount);
+    referrer_stats.total_claimed = safe_add!(referrer_stats.total_claimed, amount);
+    referrer_stats.last_claim_at = now;
+
+    emit!(ReferralRewardsClaimedEvent {
+        vault: vault.key(),
+        referrer: referrer_stats.referrer,
+        stake_record: stake.key(),
+        amount,
+        reward_mint: vault.reward_mint,
+        total_claimed: referrer_stats.total_claimed,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+/// Reward pool balance payouts may spend: penalties earmarked for the reserve,
+/// referral shares owed to referrers and tips set aside for crankers stay put
+pub fn spendable_rewards(vault: &Vault, reward_token_account: &TokenAccount) -> u64 {
+    reward_token_account
+        .amount
+        .saturating_sub(vault.reserve_pending)
+        .saturating_sub(vault.referral_pending)
+        .saturating_sub(vault.crank_tips_pending)
+}
+
+// NFT rewards an unstake pays out of `spendable`: all of them, or with `forfeit_unpaid` as much
+// as the pool covers, so an underfunded pool never silently drops rewards nor holds the NFT hostage
+fn nft_rewards_payable(rewards: u64, spendable: u64, forfeit_unpaid: bool) -> Result<u64> {
+    require!(
+        rewards <= spendable || forfeit_unpaid,
+        ErrorCode::InsufficientVaultBalance
+    );
+    Ok(rewards.min(spendable))
+}
+
+// Top up the reward pool from the insurance reserve when it can't cover `amount`
+// Draws at most the shortfall; the caller still checks the pool balance afterwards
+fn draw_reserve_shortfall<'info>(
+    vault: &mut Account<'info, Vault>,
+    reward_token_account: &mut Account<'info, TokenAccount>,
+    reward_reserve: &Option<Account<'info, TokenAccount>>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+    current_time: i64,
+) -> Result<()> {
+    let pool_balance = spendable_rewards(vault, reward_token_account);
+    let Some(reserve) = reward_reserve else {
+        return Ok(());
+    };
+    if pool_balance >= amount {
+        return Ok(());
+    }
+    let shortfall = safe_sub!(amount, pool_balance);
+    let drawn = shortfall.min(reserve.amount);
+    if drawn == 0 {
+        return Ok(());
+    }
+
+    let seeds = &[
+        b"vault",
+        vault.nft_mint.as_ref(),
+        &[vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: reserve.to_account_info(),
+                to: reward_token_account.to_account_info(),
+                authority: vault.to_account_info(),
+            },
+            signer,
+        ),
+        drawn,
+    )?;
+    reward_token_account.reload()?;
+
+    vault.total_reserve_drawn = safe_add!(vault.total_reserve_drawn, drawn);
+
+    emit!(ReserveDrawnEvent {
+        vault: vault.key(),
+        amount: drawn,
+        shortfall,
+        timestamp: current_time,
+    });
+    emit_reserve_health(&reserve_health(
+        vault,
+        safe_sub!(reserve.amount, drawn),
+        reward_token_account.amount,
+        current_time,
+    )?);
+
+    Ok(())
+}
+
+// Reserve balances against one day of stake rewards at the current schedule
+fn reserve_health(
+    vault: &Account<Vault>,
+    reserve_balance: u64,
+    reward_pool_balance: u64,
+    current_time: i64,
+) -> Result<ReserveHealth> {
+    let daily_emission = if vault.is_active {
+        calculate_pending_rewards(
+            vault.total_staked,
+            reward_schedule(vault),
+            current_time,
+            safe_add!(current_time, SECONDS_PER_DAY),
+        )?
+    } else {
+        0
+    };
+
+    Ok(ReserveHealth {
+        vault: vault.key(),
+        reserve_balance,
+        reward_pool_balance,
+        reserve_pending: vault.reserve_pending,
+        total_funded: vault.total_reserve_funded,
+        total_drawn: vault.total_reserve_drawn,
+        daily_emission,
+        coverage_days: reserve_coverage_days(
+            reward_pool_balance.saturating_add(reserve_balance),
+            daily_emission,
+        ),
+        timestamp: current_time,
+    })
+}
+
+// Whole days of emissions `funds` can pay (u64::MAX when nothing accrues)
+fn reserve_coverage_days(funds: u64, daily_emission: u64) -> u64 {
+    funds.checked_div(daily_emission).unwrap_or(u64::MAX)
+}
+
+fn emit_reserve_health(health: &ReserveHealth) {
+    emit!(ReserveHealthEvent {
+        vault: health.vault,
+        reserve_balance: health.reserve_balance,
+        reward_pool_balance: health.reward_pool_balance,
+        reserve_pending: health.reserve_pending,
+        daily_emission: health.daily_emission,
+        coverage_days: health.coverage_days,
+        timestamp: health.timestamp,
+    });
+}
+
+fn emit_compounded(accounts: &CompoundRewards, amount: u64, is_auto: bool, timestamp: i64) {
+    emit!(RewardsCompoundedEvent {
+        vault: accounts.vault.key(),
+        authority: accounts.stake_record.authority,
+        compounded_by: accounts.caller.key(),
+        amount,
+        reward_mint: accounts.vault.reward_mint,
+        new_stake: accounts.stake_record.amount,
+        total_staked: accounts.vault.total_staked,
+        is_auto,
+        timestamp,
+    });
+}
+
+// Token stake APR including the NFT boost (capped)
+fn effective_reward_rate(reward_rate: u16, nft_boost_bps: u16) -> u16 {
+    reward_rate.saturating_add(nft_boost_bps.min(MAX_NFT_BOOST_BPS))
+}
+
+// Reward weight of a staked Hatchling (100 = 1x base NFT reward rate)
+fn nft_rarity_weight(rarity: Rarity) -> u16 {
+    match rarity {
+        Rarity::Common => 100,
+        Rarity::Uncommon => 150,
+        Rarity::Rare => 200,
+        Rarity::Epic => 300,
+        Rarity::Legendary => 500,
+    }
+}
+
+// APR boost (basis points) a staked Hatchling gives its owner's token stake
+fn nft_boost_bps(rarity: Rarity) -> u16 {
+    match rarity {
+        Rarity::Common => 100,     // +1%
+        Rarity::Uncommon => 200,   // +2%
+        Rarity::Rare => 300,       // +3%
+        Rarity::Epic => 500,       // +5%
+        Rarity::Legendary => 1000, // +10%
+    }
+}
+
+// Calculate NFT rewards: nft_reward_rate × weight / 100 per day staked
+fn calculate_nft_rewards(
+    nft_reward_rate: u64,
+    rarity_weight: u16,
+    last_claim: i64,
+    current_time: i64,
+) -> Result<u64> {
+    let time_elapsed = safe_sub!(current_time, last_claim);
+    if time_elapsed <= 0 {
+        return Ok(0);
+    }
+
+    let rewards = (nft_reward_rate as u128)
+        .checked_mul(rarity_weight as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_mul(time_elapsed as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(NFT_WEIGHT_DENOMINATOR as u128 * SECONDS_PER_DAY as u128)
+        .ok_or(ErrorCode::DivisionByZero)?;
+
+    require!(rewards <= u64::MAX as u128, ErrorCode::Overflow);
+    Ok(rewards as u64)
+}
+
+// NFT boost rewards (capped boost APR) on `staked_amount` over [from, to)
+// The boost is additive to the schedule rate, so it accrues independently of the epochs
+fn calculate_boost_rewards(staked_amount: u64, nft_boost_bps: u16, from: i64, to: i64) -> Result<u64> {
+    let time_elapsed = safe_sub!(to, from);
+    if time_elapsed <= 0 || nft_boost_bps == 0 {
+        return Ok(0);
+    }
+
+    let seconds_per_year: u128 = 365 * 24 * 60 * 60;
+    let rewards = safe_div!(
+        safe_mul!(
+            safe_mul!(staked_amount as u128, nft_boost_bps.min(MAX_NFT_BOOST_BPS) as u128),
+            time_elapsed as u128
+        ),
+        REWARD_RATE_DENOMINATOR as u128 * seconds_per_year
+    );
+    require!(rewards <= u64::MAX as u128, ErrorCode::Overflow);
+    Ok(rewards as u64)
+}
+
+// Helper function to calculate pending rewards
+// Integrates the vault's reward schedule: each epoch's rate applies from
+// its start_time until the next epoch starts
+fn calculate_pending_rewards(
+    staked_amount: u64,
+    schedule: &[RewardEpoch],
+    last_claim: i64,
+    current_time: i64,
+) -> Result<u64> {
+    // Calculate time elapsed with overflow protection
+    let time_elapsed = current_time
+        .checked_sub(last_claim)
+        .ok_or(ErrorCode::Overflow)?;
+
+    if time_elapsed <= 0 {
+        return Ok(0);
+    }
+
+    // Σ rate × seconds over the epochs overlapping [last_claim, current_time)
+    let mut rate_time: u128 = 0;
+    for (i, epoch) in schedule.iter().enumerate() {
+        let epoch_end = schedule
+            .get(i + 1)
+            .map_or(current_time, |next| next.start_time.min(current_time));
+        let epoch_start = epoch.start_time.max(last_claim);
+        if epoch_end > epoch_start {
+            rate_time = rate_time
+                .checked_add(
+                    (epoch.rate as u128)
+                        .checked_mul(safe_sub!(epoch_end, epoch_start) as u128)
+                        .ok_or(ErrorCode::Overflow)?,
+                )
+                .ok_or(ErrorCode::Overflow)?;
+        }
+    }
+
+    // Calculate rewards: (amount * Σ rate * time) / (denominator * seconds_per_year)
+    // Using u128 to prevent overflow
+    let seconds_per_year: u128 = 365 * 24 * 60 * 60;
+    
+    let rewards = (staked_amount as u128)
+        .checked_mul(rate_time)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(REWARD_RATE_DENOMINATOR as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(seconds_per_year)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // Ensure result fits in u64
+    require!(rewards <= u64::MAX as u128, ErrorCode::Overflow);
+
+    Ok(rewards as u64)
+}
+
+// Apply the early-unlock penalty for taking `amount` out of a locked stake (after settle_stake_rewards)
+// The curve penalty is forfeited from settled rewards and booked to the pool;
+// the rest of the withdrawn share stays in pending_rewards for claim_rewards
+// Returns (penalty_to_pool, principal_penalty)
+fn apply_early_unlock(
+    vault: &mut Account<Vault>,
+    stake: &mut StakeRecord,
+    authority: Pubkey,
+    amount: u64,
+    current_time: i64,
+) -> Result<(u64, u64)> {
+    let penalty_bps = early_unlock_penalty_bps(vault, stake.staked_at, stake.unlock_at, current_time)?;
+    let penalty = calculate_early_unlock_penalty(
+        stake.pending_rewards,
+        amount,
+        stake.amount,
+        penalty_bps,
+    )?;
+    stake.pending_rewards = safe_sub!(stake.pending_rewards, penalty);
+    let principal_penalty = if vault.penalize_principal {
+        safe_percentage!(amount, penalty_bps)
+    } else {
+        0
+    };
+
+    // The penalty stays in the reward pool and the caller moves a principal penalty there;
+    // the reserve slice of both is earmarked here and moved by sweep_penalties_to_reserve
+    if penalty > 0 {
+        vault.total_penalties_collected = safe_add!(vault.total_penalties_collected, penalty);
+    }
+    let reserve_earmarked = if vault.reward_reserve != Pubkey::default() {
+        safe_percentage!(safe_add!(penalty, principal_penalty), vault.reserve_bps)
+    } else {
+        0
+    };
+    vault.reserve_pending = safe_add!(vault.reserve_pending, reserve_earmarked);
+
+    emit!(EarlyUnlockEvent {
+        vault: vault.key(),
+        authority,
+        amount,
+        forfeited_rewards: penalty,
+        reserve_earmarked,
+        reward_mint: vault.reward_mint,
+        unlock_at: stake.unlock_at,
+        unlocked_at: current_time,
+        days_early: safe_div!(safe_sub!(stake.unlock_at, current_time), SECONDS_PER_DAY),
+        penalty_curve: vault.penalty_curve,
+        penalty_bps,
+        principal_penalty,
+    });
+
+    Ok((penalty, principal_penalty))
+}
+
+// Calculate the early unlock penalty: the vault's curve penalty (`penalty_bps`)
+// on the withdrawn share (`amount` of `staked_amount`) of settled rewards
+// The penalty is booked to the reward pool (and its reserve slice)
+fn calculate_early_unlock_penalty(
+    pending_rewards: u64,
+    amount: u64,
+    staked_amount: u64,
+    penalty_bps: u16,
+) -> Result<u64> {
+    let withdrawn_share = safe_div!(
+        safe_mul!(pending_rewards as u128, amount as u128),
+        staked_amount as u128
+    ) as u64;
+
+    Ok(safe_percentage!(withdrawn_share, penalty_bps))
+}
+
+#[derive(Accounts)]
+pub struct CreateVault<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Vault::INIT_SPACE,
+        seeds = [b"vault", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub nft_mint: Account<'info, Mint>,
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        token::mint = token_mint,
+        token::authority = vault,
+        seeds = [b"vault_tokens", vault.key().as_ref()],
+        bump
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    pub reward_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        token::mint = reward_mint,
+        token::authority = vault,
+        seeds = [b"vault_rewards", vault.key().as_ref()],
+        bump
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"vault_registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, VaultRegistry>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + VaultIndexPage::INIT_SPACE,
+        seeds = [
+            b"vault_index".as_ref(),
+            &(registry.vault_count / VAULT_INDEX_PAGE_SIZE as u64).to_le_bytes()
+        ],
+        bump
+    )]
+    pub vault_index_page: Account<'info, VaultIndexPage>,  // Page receiving this vault's entry
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct DepositTokens<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + StakeRecord::INIT_SPACE,
+        seeds = [b"stake", vault.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        constraint = user_token_account.owner == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"ve_supply", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = ve_supply.bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+    #[account(
+        seeds = [b"global_caps"],
+        bump = global_caps.bump
+    )]
+    pub global_caps: Account<'info, GlobalCapRegistry>,
+    #[account(
+        mut,
+        seeds = [b"global_cap_shard", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = global_cap_shard.bump
+    )]
+    pub global_cap_shard: Account<'info, GlobalCapShard>,
+    pub master_nft_account: Option<Account<'info, TokenAccount>>,  // Required for OwnerOnly vaults
+    #[account(mut)]
+    pub referrer_stats: Option<Account<'info, ReferrerStats>>,  // Referrer (first deposit only)
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositPosition<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = 0,
+        mint::authority = vault
+    )]
+    pub position_mint: Account<'info, Mint>,      // Fresh keypair, signs the transaction
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + StakeRecord::INIT_SPACE,
+        seeds = [b"stake", vault.key().as_ref(), position_mint.key().as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = position_mint,
+        associated_token::authority = authority
+    )]
+    pub user_position_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_token_account.owner == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"ve_supply", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = ve_supply.bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+    #[account(
+        seeds = [b"global_caps"],
+        bump = global_caps.bump
+    )]
+    pub global_caps: Account<'info, GlobalCapRegistry>,
+    #[account(
+        mut,
+        seeds = [b"global_cap_shard", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = global_cap_shard.bump
+    )]
+    pub global_cap_shard: Account<'info, GlobalCapShard>,
+    pub master_nft_account: Option<Account<'info, TokenAccount>>,  // Required for OwnerOnly vaults
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTokens<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        constraint = user_token_account.owner == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    // Receives an early-unlock principal penalty
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"ve_supply", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = ve_supply.bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+    #[account(
+        mut,
+        seeds = [b"global_cap_shard", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = global_cap_shard.bump
+    )]
+    pub global_cap_shard: Account<'info, GlobalCapShard>,
+    // Required for receipt positions (holder of the position NFT signs)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        constraint = user_reward_account.mint == vault.reward_mint @ ErrorCode::InvalidRewardMint
+    )]
+    pub user_reward_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    // Insurance reserve, drawn on when the reward pool is short
+    #[account(
+        mut,
+        constraint = reward_reserve.key() == vault.reward_reserve @ ErrorCode::InvalidRewardReserve
+    )]
+    pub reward_reserve: Option<Account<'info, TokenAccount>>,
+    // Required for receipt positions (holder of the position NFT signs)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    // Only required when a Guardian claims on the owner's behalf
+    pub guardian_config: Option<Account<'info, GuardianConfig>>,
+    pub guardian_nft_account: Option<Account<'info, TokenAccount>>,
+    pub authority: Signer<'info>,               // Stake owner or Guardian NFT holder
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetReferralBps<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        init,
+        payer = referrer,
+        space = 8 + ReferrerStats::INIT_SPACE,
+        seeds = [b"referrer", vault.key().as_ref(), referrer.key().as_ref()],
+        bump
+    )]
+    pub referrer_stats: Account<'info, ReferrerStats>,
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        seeds = [b"referrer", vault.key().as_ref(), referrer.key().as_ref()],
+        bump = referrer_stats.bump
+    )]
+    pub referrer_stats: Account<'info, ReferrerStats>,
+    #[account(
+        mut,
+        constraint = referrer_reward_account.mint == vault.reward_mint @ ErrorCode::InvalidRewardMint
+    )]
+    pub referrer_reward_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    pub referrer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    // Insurance reserve, drawn on when the reward pool is short
+    #[account(
+        mut,
+        constraint = reward_reserve.key() == vault.reward_reserve @ ErrorCode::InvalidRewardReserve
+    )]
+    pub reward_reserve: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"ve_supply", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = ve_supply.bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+    #[account(
+        mut,
+        seeds = [b"global_cap_shard", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = global_cap_shard.bump
+    )]
+    pub global_cap_shard: Account<'info, GlobalCapShard>,
+    // Required for receipt positions (holder of the position NFT signs)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    // Only required when a Guardian compounds on the owner's behalf
+    pub guardian_config: Option<Account<'info, GuardianConfig>>,
+    pub guardian_nft_account: Option<Account<'info, TokenAccount>>,
+    pub caller: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = cranker_reward_account.mint == vault.reward_mint @ ErrorCode::InvalidRewardMint
+    )]
+    pub cranker_reward_account: Account<'info, TokenAccount>,  // Receives the crank tip
+    pub cranker: Signer<'info>,                 // Anyone (permissionless crank)
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetAutoCompound<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAccessMode<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AppendRewardEpoch<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        seeds = [b"vault_registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, VaultRegistry>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPenaltyCurve<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetReceiptMode<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetDepositCaps<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InstallGuardian<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        init,
+        payer = master,
+        space = 8 + GuardianConfig::INIT_SPACE,
+        seeds = [b"guardian", stake_record.key().as_ref()],
+        bump
+    )]
+    pub guardian_config: Account<'info, GuardianConfig>,
+    #[account(
+        constraint = guardian_nft_mint.supply == 1 && guardian_nft_mint.decimals == 0 @ ErrorCode::InvalidNftMint,
+        constraint = guardian_nft_mint.key() != vault.nft_mint @ ErrorCode::InvalidNftMint
+    )]
+    pub guardian_nft_mint: Account<'info, Mint>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    #[account(mut)]
+    pub master: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGuardian<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"guardian", guardian_config.stake_record.as_ref()],
+        bump = guardian_config.bump,
+        constraint = guardian_config.vault == vault.key() @ ErrorCode::InvalidGuardianConfig
+    )]
+    pub guardian_config: Account<'info, GuardianConfig>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub master: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeGuardian<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        close = master,
+        seeds = [b"guardian", guardian_config.stake_record.as_ref()],
+        bump = guardian_config.bump,
+        constraint = guardian_config.vault == vault.key() @ ErrorCode::InvalidGuardianConfig
+    )]
+    pub guardian_config: Account<'info, GuardianConfig>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    #[account(mut)]
+    pub master: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GuardianReport<'info> {
+    #[account(
+        seeds = [b"stake", stake_record.vault.as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        seeds = [b"guardian", stake_record.key().as_ref()],
+        bump = guardian_config.bump
+    )]
+    pub guardian_config: Account<'info, GuardianConfig>,
+    pub guardian_nft_account: Account<'info, TokenAccount>,
+    pub guardian: Signer<'info>,                // Guardian NFT holder
+}
+
+#[derive(Accounts)]
+pub struct PostGuardianBond<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        seeds = [b"guardian", guardian_config.stake_record.as_ref()],
+        bump = guardian_config.bump,
+        constraint = guardian_config.vault == vault.key() @ ErrorCode::InvalidGuardianConfig
+    )]
+    pub guardian_config: Account<'info, GuardianConfig>,
+    #[account(
+        init_if_needed,
+        payer = guardian,
+        space = 8 + GuardianBond::INIT_SPACE,
+        seeds = [
+            b"guardian_bond",
+            guardian_config.stake_record.as_ref(),
+            guardian_config.guardian_nft_mint.as_ref()
+        ],
+        bump
+    )]
+    pub guardian_bond: Account<'info, GuardianBond>,
+    #[account(
+        init_if_needed,
+        payer = guardian,
+        token::mint = token_mint,
+        token::authority = guardian_bond,
+        seeds = [b"guardian_bond_tokens", guardian_bond.key().as_ref()],
+        bump
+    )]
+    pub bond_token_account: Account<'info, TokenAccount>,
+    #[account(
+        constraint = token_mint.key() == vault.token_mint @ ErrorCode::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,       // PANGI (vault stake mint)
+    #[account(
+        mut,
+        constraint = guardian_token_account.owner == guardian.key() @ ErrorCode::Unauthorized
+    )]
+    pub guardian_token_account: Account<'info, TokenAccount>,
+    pub guardian_nft_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub guardian: Signer<'info>,                // Guardian NFT holder
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenGuardianDispute<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [
+            b"guardian_bond",
+            guardian_bond.stake_record.as_ref(),
+            guardian_bond.guardian_nft_mint.as_ref()
+        ],
+        bump = guardian_bond.bump,
+        constraint = guardian_bond.vault == vault.key() @ ErrorCode::InvalidGuardianConfig
+    )]
+    pub guardian_bond: Account<'info, GuardianBond>,
+    #[account(
+        seeds = [b"vault_registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, VaultRegistry>,
+    pub master_nft_account: Option<Account<'info, TokenAccount>>,  // Required unless the registry authority disputes
+    pub disputer: Signer<'info>,                // Master NFT holder or registry authority
+}
+
+#[derive(Accounts)]
+pub struct ChallengeGuardianDispute<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"guardian_bond",
+            guardian_bond.stake_record.as_ref(),
+            guardian_bond.guardian_nft_mint.as_ref()
+        ],
+        bump = guardian_bond.bump
+    )]
+    pub guardian_bond: Account<'info, GuardianBond>,
+    pub guardian_nft_account: Account<'info, TokenAccount>,
+    pub guardian: Signer<'info>,                // Guardian NFT holder
+}
+
+#[derive(Accounts)]
+pub struct SlashGuardianBond<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"guardian_bond",
+            guardian_bond.stake_record.as_ref(),
+            guardian_bond.guardian_nft_mint.as_ref()
+        ],
+        bump = guardian_bond.bump
+    )]
+    pub guardian_bond: Account<'info, GuardianBond>,
+    #[account(
+        mut,
+        constraint = bond_token_account.key() == guardian_bond.bond_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub bond_token_account: Account<'info, TokenAccount>,
+    #[account(
+        seeds = [b"vault_registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, VaultRegistry>,
+    #[account(
+        seeds = [b"tax_config"],
+        bump,
+        seeds::program = pangi_token::ID
+    )]
+    pub tax_config: Account<'info, TaxConfig>,
+    #[account(
+        mut,
+        constraint = conservation_fund.key() == tax_config.conservation_fund @ ErrorCode::InvalidConservationFund
+    )]
+    pub conservation_fund: Account<'info, TokenAccount>,
+    pub authority: Option<Signer<'info>>,       // Registry authority (resolve_guardian_dispute only)
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RequestGuardianBondExit<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"guardian_bond",
+            guardian_bond.stake_record.as_ref(),
+            guardian_bond.guardian_nft_mint.as_ref()
+        ],
+        bump = guardian_bond.bump
+    )]
+    pub guardian_bond: Account<'info, GuardianBond>,
+    pub guardian_nft_account: Account<'info, TokenAccount>,
+    pub guardian: Signer<'info>,                // Guardian NFT holder
+}
+
+#[derive(Accounts)]
+pub struct WithdrawGuardianBond<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"guardian_bond",
+            guardian_bond.stake_record.as_ref(),
+            guardian_bond.guardian_nft_mint.as_ref()
+        ],
+        bump = guardian_bond.bump
+    )]
+    pub guardian_bond: Account<'info, GuardianBond>,
+    #[account(
+        mut,
+        constraint = bond_token_account.key() == guardian_bond.bond_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub bond_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = guardian_token_account.owner == guardian.key() @ ErrorCode::Unauthorized
+    )]
+    pub guardian_token_account: Account<'info, TokenAccount>,
+    pub guardian_nft_account: Account<'info, TokenAccount>,
+    pub guardian: Signer<'info>,                // Guardian NFT holder
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct DeactivateVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [
+            b"vault_index".as_ref(),
+            &(vault.registry_index / VAULT_INDEX_PAGE_SIZE as u64).to_le_bytes()
+        ],
+        bump = vault_index_page.bump
+    )]
+    pub vault_index_page: Account<'info, VaultIndexPage>,
+    #[account(
+        mut,
+        seeds = [b"ve_supply", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = ve_supply.bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RequestUnstake<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + UnbondingEntry::INIT_SPACE,
+        seeds = [
+            b"unbonding",
+            stake_record.key().as_ref(),
+            &stake_record.next_unbonding_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub unbonding_entry: Account<'info, UnbondingEntry>,
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    // Receives an early-unlock principal penalty
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"ve_supply", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = ve_supply.bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+    #[account(
+        mut,
+        seeds = [b"global_cap_shard", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = global_cap_shard.bump
+    )]
+    pub global_cap_shard: Account<'info, GlobalCapShard>,
+    // Required for receipt positions (holder of the position NFT signs)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CompleteUnstake<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [
+            b"unbonding",
+            stake_record.key().as_ref(),
+            &unbonding_entry.id.to_le_bytes()
+        ],
+        bump = unbonding_entry.bump
+    )]
+    pub unbonding_entry: Account<'info, UnbondingEntry>,
+    #[account(
+        mut,
+        constraint = user_token_account.owner == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    // Required for receipt positions (holder of the position NFT signs)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetUnbondingPeriod<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SweepRewards<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = reward_reserve.key() == vault.reward_reserve @ ErrorCode::InvalidRewardReserve
+    )]
+    pub reward_reserve: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = authority_reward_account.mint == vault.reward_mint @ ErrorCode::InvalidRewardMint,
+        constraint = authority_reward_account.owner == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub authority_reward_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = authority_token_account.mint == vault.token_mint @ ErrorCode::InvalidVaultAccount,
+        constraint = authority_token_account.owner == authority.key() @ ErrorCode::Unauthorized
+    )]
+    pub authority_token_account: Account<'info, TokenAccount>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeRewardReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump,
+        constraint = vault.reward_reserve == Pubkey::default() @ ErrorCode::RewardReserveAlreadyInitialized
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        init,
+        payer = authority,
+        token::mint = reward_mint,
+        token::authority = vault,
+        seeds = [b"reward_reserve", vault.key().as_ref()],
+        bump
+    )]
+    pub reward_reserve: Account<'info, TokenAccount>,
+    #[account(
+        constraint = reward_mint.key() == vault.reward_mint @ ErrorCode::InvalidRewardMint
+    )]
+    pub reward_mint: Account<'info, Mint>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetReserveBps<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundRewardReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"reward_reserve", vault.key().as_ref()],
+        bump
+    )]
+    pub reward_reserve: Account<'info, TokenAccount>,
+    #[account(
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = funder_token_account.mint == vault.reward_mint @ ErrorCode::InvalidRewardMint,
+        constraint = funder_token_account.owner == funder.key() @ ErrorCode::Unauthorized
+    )]
+    pub funder_token_account: Account<'info, TokenAccount>,
+    pub funder: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SweepPenaltiesToReserve<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"reward_reserve", vault.key().as_ref()],
+        bump
+    )]
+    pub reward_reserve: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    pub caller: Signer<'info>,                  // Anyone (permissionless crank)
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseStakeRecord<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    // Required for receipt positions (identifies the holder)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,            // Stake owner (rent recipient)
+    pub caller: Signer<'info>,
+    // Required while referral_pending > 0: the referrer's share is paid out on close
+    #[account(
+        mut,
+        seeds = [b"referrer", vault.key().as_ref(), stake_record.referrer.as_ref()],
+        bump = referrer_stats.bump
+    )]
+    pub referrer_stats: Option<Account<'info, ReferrerStats>>,
+    #[account(
+        mut,
+        constraint = referrer_reward_account.mint == vault.reward_mint @ ErrorCode::InvalidRewardMint,
+        constraint = referrer_reward_account.owner == stake_record.referrer @ ErrorCode::InvalidReferrer
+    )]
+    pub referrer_reward_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Option<Account<'info, TokenAccount>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseVault<'info> {
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    // Required when the vault has an insurance reserve
+    #[account(
+        mut,
+        constraint = reward_reserve.key() == vault.reward_reserve @ ErrorCode::InvalidRewardReserve
+    )]
+    pub reward_reserve: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            b"vault_index".as_ref(),
+            &(vault.registry_index / VAULT_INDEX_PAGE_SIZE as u64).to_le_bytes()
+        ],
+        bump = vault_index_page.bump
+    )]
+    pub vault_index_page: Account<'info, VaultIndexPage>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetNftRewardRate<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub master_nft_account: Account<'info, TokenAccount>,  // Signer's Master NFT token account
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct StakeNft<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + StakeRecord::INIT_SPACE,
+        seeds = [b"stake", vault.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + NftStakeRecord::INIT_SPACE,
+        seeds = [b"nft_stake", vault.key().as_ref(), nft_mint.key().as_ref()],
+        bump
+    )]
+    pub nft_stake: Account<'info, NftStakeRecord>,
+    #[account(
+        constraint = nft_mint.supply == 1 && nft_mint.decimals == 0 @ ErrorCode::InvalidNftMint
+    )]
+    pub nft_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"hatchling", nft_mint.key().as_ref()],
+        bump,
+        seeds::program = pangi_nft::ID,
+        constraint = hatchling.authority == owner.key() @ ErrorCode::Unauthorized
+    )]
+    pub hatchling: Account<'info, Hatchling>,
+    #[account(
+        mut,
+        constraint = owner_nft_account.mint == nft_mint.key() @ ErrorCode::InvalidNftMint,
+        constraint = owner_nft_account.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = owner_nft_account.amount == 1 @ ErrorCode::InvalidNftMint
+    )]
+    pub owner_nft_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = owner,
+        token::mint = nft_mint,
+        token::authority = vault,
+        seeds = [b"nft_escrow", vault.key().as_ref(), nft_mint.key().as_ref()],
+        bump
+    )]
+    pub nft_escrow: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub nft_program: Program<'info, pangi_nft::program::PangiNft>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimNftRewards<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"nft_stake", vault.key().as_ref(), nft_stake.nft_mint.as_ref()],
+        bump = nft_stake.bump,
+        constraint = nft_stake.owner == owner.key() @ ErrorCode::Unauthorized
+    )]
+    pub nft_stake: Account<'info, NftStakeRecord>,
+    #[account(
+        mut,
+        constraint = owner_reward_account.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = owner_reward_account.mint == vault.reward_mint @ ErrorCode::InvalidRewardMint
+    )]
+    pub owner_reward_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeNft<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"nft_stake", vault.key().as_ref(), nft_stake.nft_mint.as_ref()],
+        bump = nft_stake.bump,
+        constraint = nft_stake.owner == owner.key() @ ErrorCode::Unauthorized
+    )]
+    pub nft_stake: Account<'info, NftStakeRecord>,
+    #[account(
+        mut,
+        seeds = [b"hatchling", nft_stake.nft_mint.as_ref()],
+        bump,
+        seeds::program = pangi_nft::ID
+    )]
+    pub hatchling: Account<'info, Hatchling>,
+    #[account(
+        mut,
+        seeds = [b"nft_escrow", vault.key().as_ref(), nft_stake.nft_mint.as_ref()],
+        bump
+    )]
+    pub nft_escrow: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = owner_nft_account.mint == nft_stake.nft_mint @ ErrorCode::InvalidNftMint,
+        constraint = owner_nft_account.owner == owner.key() @ ErrorCode::Unauthorized
+    )]
+    pub owner_nft_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = owner_reward_account.owner == owner.key() @ ErrorCode::Unauthorized,
+        constraint = owner_reward_account.mint == vault.reward_mint @ ErrorCode::InvalidRewardMint
+    )]
+    pub owner_reward_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub nft_program: Program<'info, pangi_nft::program::PangiNft>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(shard: u8)]
+pub struct InitializeVotingPower<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + VotingPowerSupply::INIT_SPACE,
+        seeds = [b"ve_supply".as_ref(), token_mint.key().as_ref(), &[shard]],
+        bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+    pub token_mint: Account<'info, Mint>,   // Staked mint the shard tracks
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeGlobalCaps<'info> {
+    #[account(
+        init,
         payer = authority,
-        space = 8 + Vault::INIT_SPACE,
-        seeds = [b"vault", nft_mint.key().as_ref()],
+        space = 8 + GlobalCapRegistry::INIT_SPACE,
+        seeds = [b"global_caps"],
+        bump
+    )]
+    pub global_caps: Account<'info, GlobalCapRegistry>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(shard: u8)]
+pub struct InitializeGlobalCapShard<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + GlobalCapShard::INIT_SPACE,
+        seeds = [b"global_cap_shard".as_ref(), token_mint.key().as_ref(), &[shard]],
+        bump
+    )]
+    pub global_cap_shard: Account<'info, GlobalCapShard>,
+    pub token_mint: Account<'info, Mint>,   // Staked mint the shard counts
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetGlobalCap<'info> {
+    #[account(
+        mut,
+        seeds = [b"global_caps"],
+        bump = global_caps.bump
+    )]
+    pub global_caps: Account<'info, GlobalCapRegistry>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeVaultRegistry<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + VaultRegistry::INIT_SPACE,
+        seeds = [b"vault_registry"],
+        bump
+    )]
+    pub registry: Account<'info, VaultRegistry>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetVaultDefaults<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault_registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, VaultRegistry>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetVaultVoting<'info> {
+    #[account(
+        seeds = [b"vault_registry"],
+        bump = registry.bump
+    )]
+    pub registry: Account<'info, VaultRegistry>,
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"ve_supply", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = ve_supply.bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CheckpointStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        seeds = [b"ve_supply", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = ve_supply.bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+}
+
+#[derive(Accounts)]
+pub struct OptOutOfVoting<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        mut,
+        seeds = [b"ve_supply", vault.token_mint.as_ref(), &[vault.global_shard]],
+        bump = ve_supply.bump
+    )]
+    pub ve_supply: Account<'info, VotingPowerSupply>,
+    // Required for receipt positions (holder of the position NFT signs)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SyncScalesPoints<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        mut,
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + UserScalesPoints::INIT_SPACE,
+        seeds = [b"scales_points", owner.key().as_ref()],
+        bump
+    )]
+    pub user_points: Account<'info, UserScalesPoints>,
+    // Required for receipt positions (identifies the holder)
+    pub position_account: Option<Account<'info, TokenAccount>>,
+    pub owner: SystemAccount<'info>,            // Stake owner credited with the points
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SpendScalesPoints<'info> {
+    #[account(
+        mut,
+        seeds = [b"scales_points", owner.key().as_ref()],
+        bump = user_points.bump
+    )]
+    pub user_points: Account<'info, UserScalesPoints>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetScalesPoints<'info> {
+    #[account(
+        seeds = [b"scales_points", user_points.owner.as_ref()],
+        bump = user_points.bump
+    )]
+    pub user_points: Account<'info, UserScalesPoints>,
+}
+
+#[derive(Accounts)]
+pub struct GetStakeAt<'info> {
+    #[account(
+        seeds = [b"stake", stake_record.vault.as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+}
+
+#[derive(Accounts)]
+pub struct GetReserveHealth<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        seeds = [b"reward_reserve", vault.key().as_ref()],
         bump
     )]
+    pub reward_reserve: Account<'info, TokenAccount>,
+    #[account(
+        constraint = reward_token_account.key() == vault.reward_token_account @ ErrorCode::InvalidVaultAccount
+    )]
+    pub reward_token_account: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct GetVotingPower<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
     pub vault: Account<'info, Vault>,
-    pub nft_mint: Account<'info, Mint>,
-    pub token_mint: Account<'info, Mint>,
     #[account(
-        init,
-        payer = authority,
-        token::mint = token_mint,
-        token::authority = vault,
-        seeds = [b"vault_tokens", vault.key().as_ref()],
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
         bump
     )]
-    pub vault_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
+    pub stake_record: Account<'info, StakeRecord>,
+}
+
+#[derive(Accounts)]
+pub struct PreviewPosition<'info> {
+    #[account(
+        seeds = [b"vault", vault.nft_mint.as_ref()],
+        bump = vault.bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(
+        seeds = [b"stake", vault.key().as_ref(), stake_record.authority.as_ref()],
+        bump
+    )]
+    pub stake_record: Account<'info, StakeRecord>,
+}
+
+/// Vault account - Self-custody staking vault
+/// Master NFT creates vault and installs Guardian reporting configuration
+/// Stake is held in token_mint ($PANGI); rewards are paid in reward_mint ($CATH)
+/// Reward amounts are computed in raw token_mint units and paid 1:1 in reward_mint units
+#[account]
+#[derive(InitSpace, Default)]
+pub struct Vault {
+    pub nft_mint: Pubkey,              // Master NFT that created this vault
+    pub authority: Pubkey,             // Vault creator (Master NFT holder at creation; checks use live NFT ownership)
+    pub token_mint: Pubkey,            // PANGI token mint
+    pub vault_token_account: Pubkey,   // Vault's token account (holds staked tokens)
+    pub total_staked: u64,             // Total tokens staked in this vault
+    pub reward_rate: u16,              // Reward rate at creation in basis points (epoch 0)
+    pub lock_duration: i64,            // Lock duration in seconds
+    pub created_at: i64,               // Vault creation timestamp
+    pub last_reward_update: i64,       // Vault-level reward accounting is current up to here
+    pub total_penalties_collected: u64, // Total 15% penalties returned to pool
+    pub is_active: bool,               // Vault active status
+    pub bump: u8,                      // PDA bump seed
+    pub nft_reward_rate: u64,          // Tokens per day for a staked Common Hatchling
+    pub total_nfts_staked: u32,        // Hatchlings currently in escrow
+    pub reward_mint: Pubkey,           // Mint rewards are paid in ($CATH; may equal token_mint)
+    pub reward_token_account: Pubkey,  // Vault's reward pool token account
+    pub auto_compound: bool,           // Permissionless compound crank enabled
+    pub access_mode: VaultAccessMode,  // Who may deposit
+    pub allowlist_root: [u8; 32],      // Merkle root of eligible wallets (Allowlist mode)
+    pub max_total_staked: u64,         // Vault TVL cap on deposits (0 = uncapped; compounding is exempt)
+    pub max_user_stake: u64,           // Per-user stake cap on deposits (0 = uncapped; compounding is exempt)
+    pub allow_partial_fill: bool,      // Fill deposits up to a cap instead of rejecting
+    pub deactivated_at: i64,           // Deactivation timestamp (stake rewards stop accruing)
+    pub stake_count: u32,              // Open stake records (must be 0 to close the vault)
+    pub unbonding_period: i64,         // Delay between request_unstake and complete_unstake (0 = instant)
+    pub total_unbonding: u64,          // Tokens in unbonding entries (not staked, not yet released)
+    pub receipt_mode: bool,            // Deposits mint transferable position NFTs
+    pub reward_epochs: [RewardEpoch; MAX_REWARD_EPOCHS], // Reward schedule (first reward_epoch_count used)
+    pub reward_epoch_count: u8,        // Scheduled epochs
+    pub penalty_curve: PenaltyCurve,   // Early-unlock penalty curve
+    pub penalty_bps: u16,              // Flat / LinearDecay penalty (LinearDecay: at stake start)
+    pub penalty_steps: [PenaltyStep; MAX_PENALTY_STEPS], // Stepped curve (first penalty_step_count used)
+    pub penalty_step_count: u8,        // Stepped curve entries
+    pub penalize_principal: bool,      // Also take the penalty bps from withdrawn principal
+    pub total_principal_penalties: u64, // Principal moved to the reward pool by early unlocks
+    pub voting_approved: bool,         // Registry authority counts this vault's stakes as governance voting power
+    pub ve_curve: VotingPowerCurve,    // This vault's share of its ve_supply shard
+    pub ve_epoch: u32,                 // Bumped each time ve_curve is taken out of the shard as a whole
+    pub registry_index: u64,           // Position in the vault registry index
+    pub global_shard: u8,              // ve_supply / global cap shard this vault's stakes count in
+    pub referral_bps: u16,             // Share of referred stakers' rewards paid to referrers
+    pub referral_pending: u64,         // Referral shares credited but not yet paid, still in the reward pool
+    pub reward_reserve: Pubkey,        // Insurance reserve token account (default = none)
+    pub reserve_bps: u16,              // Share of early-unlock penalties routed to the reserve
+    pub reserve_pending: u64,          // Penalties earmarked for the reserve, still in the reward pool
+    pub total_reserve_funded: u64,     // Total moved into the reserve (penalties and deposits)
+    pub total_reserve_drawn: u64,      // Total drawn to cover reward pool shortfalls
+    pub total_rewards_accrued: u64,    // Base stake rewards accrued vault-wide up to last_reward_update
+    pub reward_index: u128,            // Cumulative base rewards per REWARD_INDEX_PRECISION staked units (stakes settle against it)
+    pub total_crank_tips: u64,         // Total paid to update_vault crankers
+    pub crank_tips_pending: u64,       // Crank share of accrued rewards not yet paid, still in the reward pool
+    pub last_crank_at: i64,            // Accrual time of the last update_vault crank
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub enum PenaltyCurve {
+    #[default]
+    Flat,                              // penalty_bps regardless of timing
+    LinearDecay,                       // penalty_bps at stake start, zero at unlock
+    Stepped,                           // Table of (min_remaining, penalty_bps)
+}
+
+/// Stepped curve entry: `penalty_bps` applies while at least `min_remaining` seconds of lock remain
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub struct PenaltyStep {
+    pub min_remaining: i64,            // Seconds before unlock_at
+    pub penalty_bps: u16,              // Penalty in basis points
+}
+
+/// One reward schedule entry: `rate` applies from `start_time` until the next epoch
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub struct RewardEpoch {
+    pub start_time: i64,               // Epoch start timestamp
+    pub rate: u16,                     // Reward rate in basis points
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub enum VaultAccessMode {
+    #[default]
+    OwnerOnly,                         // Master NFT holder only
+    Open,                              // Any wallet
+    Allowlist,                         // Wallets proven against allowlist_root
+}
+
+/// StakeRecord - Individual user stake position
+/// Tracks user's staked tokens and Guardian reporting configuration
+#[account]
+#[derive(InitSpace, Default)]
+pub struct StakeRecord {
+    pub vault: Pubkey,                 // Parent vault
+    pub authority: Pubkey,             // User who staked (position mint for receipt positions)
+    pub amount: u64,                   // Amount staked (tokens remain in user custody)
+    pub staked_at: i64,                // Stake creation timestamp
+    pub unlock_at: i64,                // Unlock timestamp (when Guardian reports)
+    pub last_claim: i64,               // Last reward claim timestamp
+    pub total_claimed: u64,            // Total rewards claimed
+    pub ve_amount: u64,                // Amount counted in global voting power supply
+    pub ve_unlock_at: i64,             // Week-aligned lock end counted in global voting power supply
+    pub ve_epoch: u32,                 // vault.ve_epoch the ve_amount was counted under
+    pub nft_boost_bps: u16,            // APR boost from owner's staked Hatchlings
+    pub total_compounded: u64,         // Rewards compounded into amount (subset of total_claimed)
+    pub unbonding_amount: u64,         // Tokens in this stake's open unbonding entries
+    pub next_unbonding_id: u64,        // Seed id of the next unbonding entry
+    pub position_mint: Pubkey,         // Position NFT whose holder owns the stake (default = none)
+    pub pending_rewards: u64,          // Settled, unclaimed rewards
+    pub rewards_accrued_at: i64,       // Rewards settled into pending_rewards up to here
+    pub reward_index_paid: u128,       // Vault reward_index at the stake's last settlement
+    pub referrer: Pubkey,              // Referrer set on first deposit (default = none)
+    pub referral_pending: u64,         // Referrer's unclaimed share of paid rewards (paid from the pool)
+    pub scales_points: u128,           // Scales points earned by this stake (never decreases)
+    pub points_accrued_at: i64,        // Scales points settled up to here
+    pub points_synced: u128,           // scales_points already credited to the owner's aggregate
+    pub balance_checkpoints: [BalanceCheckpoint; MAX_BALANCE_CHECKPOINTS], // Ring of past balances
+    pub checkpoint_head: u8,           // Next ring slot to write
+    pub checkpoint_count: u8,          // Ring slots in use
+    pub ve_opt_out: bool,              // Permanently left out of voting power (owners that cannot vote, e.g. the stPANGI pool)
+    // Guardian reporting configuration (installed by Master) lives in the
+    // GuardianConfig PDA [b"guardian", stake_record]
+}
+
+/// ReferrerStats - a referrer's totals in one vault
+/// Referral rewards are credited per referred stake (StakeRecord.referral_pending)
+#[account]
+#[derive(InitSpace)]
+pub struct ReferrerStats {
+    pub vault: Pubkey,                 // Parent vault
+    pub referrer: Pubkey,              // Referrer wallet
+    pub referred_count: u64,           // Stakes referred
+    pub total_claimed: u64,            // Referral rewards claimed
+    pub last_claim_at: i64,            // Last claim timestamp
+    pub bump: u8,                      // PDA bump seed
+}
+
+/// Stake balance after a change, for historical snapshots
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub struct BalanceCheckpoint {
+    pub slot: u64,                     // Slot of the change
+    pub timestamp: i64,                // Time of the change
+    pub amount: u64,                   // stake.amount after the change
+    pub ve_unlock_at: i64,             // stake.ve_unlock_at after the change (0 = not in the voting supply)
+    pub ve_epoch: u32,                 // stake.ve_epoch after the change
+}
+
+/// UserScalesPoints - a wallet's non-transferable scales points across all stakes
+#[account]
+#[derive(InitSpace)]
+pub struct UserScalesPoints {
+    pub owner: Pubkey,                 // Wallet the points belong to
+    pub total_earned: u128,            // Points synced from stakes
+    pub total_spent: u128,             // Points spent via spend_scales_points
+    pub updated_at: i64,               // Last sync or spend timestamp
+    pub bump: u8,                      // PDA bump seed
+}
+
+/// GuardianConfig - Guardian NFT delegation for one stake
+/// Installed by the Master NFT holder; Guardian reports OUT to report_address only
+#[account]
+#[derive(InitSpace)]
+pub struct GuardianConfig {
+    pub vault: Pubkey,                 // Parent vault
+    pub stake_record: Pubkey,          // Stake this Guardian serves
+    pub master: Pubkey,                // Master NFT holder that last configured it
+    pub guardian_nft_mint: Pubkey,     // Guardian NFT; its holder acts as Guardian
+    pub permissions: u8,               // GUARDIAN_PERMISSION_* bitmask
+    pub report_address: Pubkey,        // Public address Guardian reports are sent to
+    pub installed_at: i64,             // Install timestamp
+    pub updated_at: i64,               // Last update timestamp
+    pub last_report_at: i64,           // Last Guardian report timestamp (rate limit)
+    pub report_count: u64,             // Reports sent for this stake
+    pub reported_unlock_at: i64,       // unlock_at of the last Unlock report
+    pub reported_total_claimed: u64,   // stake.total_claimed at the last Claim report
+    pub bump: u8,                      // PDA bump seed
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum GuardianReportKind {
+    Unlock,                            // Lock period ended
+    Claim,                             // Rewards were claimed
+}
+
+/// GuardianBond - PANGI posted by a Guardian NFT holder for one stake
+/// Slashable through a dispute; slashes go to the conservation fund
+#[account]
+#[derive(InitSpace)]
+pub struct GuardianBond {
+    pub vault: Pubkey,                 // Parent vault
+    pub stake_record: Pubkey,          // Stake the Guardian serves
+    pub guardian_nft_mint: Pubkey,     // Guardian NFT; its holder controls the bond
+    pub bond_token_account: Pubkey,    // PDA token account holding the bond
+    pub amount: u64,                   // Tokens currently bonded
+    pub total_slashed: u64,            // Tokens slashed to the conservation fund
+    pub exit_requested_at: i64,        // Bond exit request timestamp (0 = none)
+    pub dispute_state: GuardianDisputeState, // Current dispute
+    pub dispute_amount: u64,           // Amount the open dispute would slash
+    pub disputed_by: Pubkey,           // Master NFT holder or registry authority
+    pub evidence_hash: [u8; 32],       // Hash of off-chain evidence
+    pub challenge_ends_at: i64,        // End of the challenge window
+    pub bump: u8,                      // PDA bump seed
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum GuardianDisputeState {
+    None,                              // No dispute
+    Open,                              // Slashes once the challenge window ends
+    Challenged,                        // Guardian contested; registry authority decides
+}
+
+/// NftStakeRecord - Hatchling NFT held in vault escrow
+/// Earns a rarity-weighted reward stream and boosts the owner's token stake
+#[account]
+#[derive(InitSpace)]
+pub struct NftStakeRecord {
+    pub vault: Pubkey,                 // Parent vault
+    pub owner: Pubkey,                 // Wallet that staked the NFT
+    pub nft_mint: Pubkey,              // Hatchling mint in escrow
+    pub rarity_weight: u16,            // Reward weight (100 = 1x nft_reward_rate)
+    pub boost_bps: u16,                // APR boost applied to owner's stake record
+    pub staked_at: i64,                // NFT stake timestamp
+    pub last_claim: i64,               // Last NFT reward claim timestamp
+    pub total_claimed: u64,            // Total NFT rewards claimed
+    pub bump: u8,                      // PDA bump seed
+}
+
+/// UnbondingEntry - Tokens released from a stake, waiting out the vault's unbonding period
+/// PDA: [b"unbonding", stake_record, id]
+#[account]
+#[derive(InitSpace)]
+pub struct UnbondingEntry {
+    pub vault: Pubkey,                 // Parent vault
+    pub stake_record: Pubkey,          // Stake the tokens came from
+    pub owner: Pubkey,                 // Stake owner
+    pub id: u64,                       // Per-stake entry id
+    pub amount: u64,                   // Tokens unbonding (earn no rewards)
+    pub requested_at: i64,             // request_unstake timestamp
+    pub release_at: i64,               // complete_unstake allowed from here
+    pub bump: u8,                      // PDA bump seed
+}
+
+/// VotingPowerSupply - vePANGI voting power checkpoint of one shard of vaults
+/// shard_voting_power(t) = (bias - slope × (t - last_checkpoint)) / VOTING_POWER_MAX_LOCK,
+/// with slope dropping by slope_changes at each week boundary where locks end
+/// Total supply of a mint is the sum over its GLOBAL_SHARD_COUNT shards
+#[account]
+#[derive(InitSpace)]
+pub struct VotingPowerSupply {
+    pub mint: Pubkey,                  // Staked mint (vault.token_mint of its vaults)
+    pub shard: u8,                     // Shard index (vault.global_shard of its vaults)
+    pub curve: VotingPowerCurve,       // Locks counted across the shard's vaults
+    pub bump: u8,                      // PDA bump seed
+}
+
+/// VotingPowerCurve - decaying sum of vePANGI locks (a shard supply, or one vault's share of it)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct VotingPowerCurve {
+    pub bias: i128,                    // Σ amount × (lock_end - last_checkpoint)
+    pub slope: i128,                   // Σ amount of running locks (decay per second)
+    pub slope_changes: [i128; VE_SLOPE_WEEKS], // Slope ending at each upcoming week (ring by week index)
+    pub last_checkpoint: i64,          // Timestamp bias was last advanced to
+}
+
+impl Default for VotingPowerCurve {
+    fn default() -> Self {
+        Self {
+            bias: 0,
+            slope: 0,
+            slope_changes: [0; VE_SLOPE_WEEKS],
+            last_checkpoint: 0,
+        }
+    }
+}
+
+/// GlobalCapRegistry - TVL cap across all vaults of each staked mint
+/// Read-only on deposits; staked totals live in the per-mint GlobalCapShard accounts
+#[account]
+#[derive(InitSpace)]
+pub struct GlobalCapRegistry {
+    pub authority: Pubkey,             // May update the global cap
+    pub max_total_staked: u64,         // Global TVL cap per staked mint on deposits (0 = uncapped; compounding is exempt)
+    pub bump: u8,                      // PDA bump seed
+}
+
+/// GlobalCapShard - tokens of one mint staked across the vaults of one shard
+#[account]
+#[derive(InitSpace)]
+pub struct GlobalCapShard {
+    pub mint: Pubkey,                  // Staked mint (vault.token_mint of its vaults)
+    pub shard: u8,                     // Shard index (vault.global_shard of its vaults)
+    pub total_staked: u64,             // Tokens staked across this shard's vaults
+    pub bump: u8,                      // PDA bump seed
+}
+
+/// VaultRegistry - counts every vault and holds the defaults new vaults are validated against
+#[account]
+#[derive(InitSpace)]
+pub struct VaultRegistry {
+    pub authority: Pubkey,             // May update the defaults
+    pub vault_count: u64,              // Vaults created (next registry_index)
+    pub min_lock_duration: i64,        // Shortest lock a new vault may use
+    pub max_lock_duration: i64,        // Longest lock a new vault may use
+    pub max_reward_rate: u16,          // Highest reward rate (bps) a vault may schedule
+    pub bump: u8,                      // PDA bump seed
+}
+
+/// VaultIndexPage - page `page` lists registry indexes page × VAULT_INDEX_PAGE_SIZE onward
+#[account]
+#[derive(InitSpace)]
+pub struct VaultIndexPage {
+    pub page: u64,                     // Page number
+    pub count: u8,                     // Entries in use
+    pub entries: [VaultIndexEntry; VAULT_INDEX_PAGE_SIZE],
+    pub bump: u8,                      // PDA bump seed
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub struct VaultIndexEntry {
+    pub vault: Pubkey,                 // Vault PDA
+    pub nft_mint: Pubkey,              // Master NFT the vault is seeded by
+    pub reward_rate: u16,              // Reward rate at creation (bps)
+    pub status: VaultStatus,           // Lifecycle status
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub enum VaultStatus {
+    #[default]
+    Active,                            // Accepting deposits
+    Inactive,                          // Deactivated, winding down
+    Closed,                            // Vault account closed
+}
+
+/// Return data of get_voting_power
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VotingPower {
+    pub stake_record: Pubkey,
+    pub authority: Pubkey,
+    pub voting_power: u64,
+    pub total_voting_power: u64,
+    pub timestamp: i64,
+}
+
+/// Return data of get_stake_at
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StakeSnapshot {
+    pub stake_record: Pubkey,
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+    pub amount: u64,
+}
+
+/// Return data of get_reserve_health
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReserveHealth {
+    pub vault: Pubkey,
+    pub reserve_balance: u64,
+    pub reward_pool_balance: u64,
+    pub reserve_pending: u64,       // Earmarked penalties not yet swept into the reserve
+    pub total_funded: u64,
+    pub total_drawn: u64,
+    pub daily_emission: u64,        // Stake rewards accruing per day at the current rate
+    pub coverage_days: u64,         // Days pool + reserve can pay (u64::MAX when nothing accrues)
+    pub timestamp: i64,
+}
+
+/// Return data of get_scales_points
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScalesPointsBalance {
+    pub owner: Pubkey,
+    pub total_earned: u128,
+    pub total_spent: u128,
+    pub balance: u128,
+    pub timestamp: i64,
+}
+
+/// Return data of preview_position
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PositionPreview {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub amount: u64,
+    pub pending_rewards: u64,       // Settled + accrued, claimable once unlocked
+    pub is_early_unlock: bool,      // Withdrawing now would be an early unlock
+    pub early_unlock_forfeited_rewards: u64, // Rewards forfeited if the full stake is withdrawn now
+    pub early_unlock_penalty: u64,  // Same amount, booked to the pool (reserve accounting)
+    pub early_unlock_principal_penalty: u64, // Principal moved to the reward pool if the full stake is withdrawn now
+    pub seconds_to_unlock: i64,     // 0 once unlocked (or vault deactivated)
+    pub effective_apr_bps: u16,     // Current epoch rate + NFT boost (0 if vault deactivated)
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultCreatedEvent {
+    pub nft_mint: Pubkey,
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub token_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_rate: u16,
+    pub lock_duration: i64,
+    pub unbonding_period: i64,
+    pub registry_index: u64,
+    pub global_shard: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensDepositedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+    pub total_staked: u64,
+    pub unlock_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensWithdrawnEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+    pub pending_rewards: u64,       // Settled rewards left on the stake (claimable once unlocked)
+    pub penalty_to_pool: u64,       // Curve penalty returned to pool (0 if normal unlock)
+    pub principal_penalty: u64,     // Principal moved to the reward pool (0 if normal unlock)
+    pub reward_mint: Pubkey,        // Mint rewards are denominated in
+    pub remaining_stake: u64,
+    pub is_early_unlock: bool,      // true if withdrawn before unlock_at
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsClaimedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,          // Stake owner (rewards recipient)
+    pub claimed_by: Pubkey,         // Owner or Guardian NFT holder
+    pub amount: u64,
+    pub reward_mint: Pubkey,        // Mint the rewards were paid in
+    pub total_claimed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ScalesPointsSyncedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub owner: Pubkey,
+    pub credited: u128,
+    pub balance: u128,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ScalesPointsSpentEvent {
+    pub owner: Pubkey,
+    pub amount: u128,
+    pub balance: u128,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralRecordedEvent {
+    pub vault: Pubkey,
+    pub referrer: Pubkey,
+    pub staker: Pubkey,
+    pub referred_count: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimedEvent {
+    pub vault: Pubkey,
+    pub referrer: Pubkey,
+    pub stake_record: Pubkey,
+    pub amount: u64,
+    pub reward_mint: Pubkey,
+    pub total_claimed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralShareUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub referral_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultUpdatedEvent {
+    pub vault: Pubkey,
+    pub cranker: Pubkey,
+    pub rewards_accrued: u64,       // Accrued since the previous update
+    pub total_rewards_accrued: u64,
+    pub reward_index: u128,
+    pub tip: u64,                   // Paid to the cranker from the reward pool
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsCompoundedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,          // Stake owner
+    pub compounded_by: Pubkey,      // Owner or auto-compound cranker
+    pub amount: u64,
+    pub reward_mint: Pubkey,
+    pub new_stake: u64,
+    pub total_staked: u64,
+    pub is_auto: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AutoCompoundUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub enabled: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianInstalledEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub permissions: u8,
+    pub report_address: Pubkey,
+    pub master: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianUpdatedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub permissions: u8,
+    pub report_address: Pubkey,
+    pub master: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianRevokedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub master: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianBondPostedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub guardian: Pubkey,
+    pub amount: u64,
+    pub bond_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianDisputeOpenedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub disputed_by: Pubkey,
+    pub slash_amount: u64,
+    pub evidence_hash: [u8; 32],
+    pub challenge_ends_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianDisputeChallengedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub guardian: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianDisputeDismissedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub dismissed_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianSlashedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub disputed_by: Pubkey,
+    pub evidence_hash: [u8; 32],
+    pub slashed: u64,
+    pub bond_amount: u64,           // Bond remaining after the slash
+    pub conservation_fund: Pubkey,  // Slash recipient
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianBondExitRequestedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub guardian: Pubkey,
+    pub withdrawable_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GuardianBondWithdrawnEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub guardian: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+/// One-way Guardian → public address report
+#[event]
+pub struct GuardianReportEvent {
+    pub report_address: Pubkey,     // Recipient configured by the Master
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub owner: Pubkey,
+    pub guardian_nft_mint: Pubkey,
+    pub kind: GuardianReportKind,
+    pub amount_staked: u64,
+    pub unlock_at: i64,
+    pub total_claimed: u64,
+    pub claimed_since_last_report: u64,
+    pub report_index: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AccessModeUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub access_mode: VaultAccessMode,
+    pub allowlist_root: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardEpochAddedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub epoch_index: u8,
+    pub start_time: i64,
+    pub rate: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PenaltyCurveUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub curve: PenaltyCurve,
+    pub penalty_bps: u16,
+    pub steps: Vec<PenaltyStep>,
+    pub penalize_principal: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReceiptModeUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub enabled: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionMintedEvent {
+    pub vault: Pubkey,
+    pub stake_record: Pubkey,
+    pub position_mint: Pubkey,
+    pub depositor: Pubkey,
+    pub amount: u64,
+    pub unlock_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnstakeRequestedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub unbonding_entry: Pubkey,
+    pub id: u64,
+    pub amount: u64,
+    pub release_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnstakeCompletedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub unbonding_entry: Pubkey,
+    pub id: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UnbondingPeriodUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub unbonding_period: i64,
+    pub timestamp: i64,
+}
+
+/// Principal-only withdrawal; rewards forfeited to the pool
+#[event]
+pub struct EmergencyWithdrawEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+    pub forfeited_rewards: u64,
+    pub reward_mint: Pubkey,
+    pub total_staked: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsSweptEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub reward_mint: Pubkey,
+    pub rewards_swept: u64,
+    pub principal_swept: u64,       // Residual dust in the principal account
+    pub reserve_swept: u64,         // Insurance reserve balance (0 if not passed)
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReserveConfigUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub reward_reserve: Pubkey,
+    pub reserve_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReserveFundedEvent {
+    pub vault: Pubkey,
+    pub funder: Pubkey,             // Depositor, or the crank caller for penalties
+    pub amount: u64,
+    pub from_penalties: bool,       // Swept early-unlock penalties (false = direct deposit)
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReserveDrawnEvent {
+    pub vault: Pubkey,
+    pub amount: u64,                // Moved from the reserve into the reward pool
+    pub shortfall: u64,             // Pool shortfall at the time (amount < shortfall if reserve ran dry)
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReserveHealthEvent {
+    pub vault: Pubkey,
+    pub reserve_balance: u64,
+    pub reward_pool_balance: u64,
+    pub reserve_pending: u64,
+    pub daily_emission: u64,
+    pub coverage_days: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakeRecordClosedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,          // Stake owner (rent recipient)
+    pub closed_by: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultClosedEvent {
+    pub vault: Pubkey,
+    pub nft_mint: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DepositCapsUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub max_total_staked: u64,
+    pub max_user_stake: u64,
+    pub allow_partial_fill: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultDefaultsUpdatedEvent {
+    pub authority: Pubkey,
+    pub min_lock_duration: i64,
+    pub max_lock_duration: i64,
+    pub max_reward_rate: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GlobalCapUpdatedEvent {
+    pub authority: Pubkey,
+    pub max_total_staked: u64,
+    pub timestamp: i64,
+}
+
+/// Deposit over a cap was partially filled
+#[event]
+pub struct DepositClampedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub requested: u64,
+    pub filled: u64,
+    pub vault_room: u64,            // u64::MAX when uncapped
+    pub user_room: u64,
+    pub global_room: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultDeactivatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EarlyUnlockEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+    pub forfeited_rewards: u64,  // Curve penalty taken from the withdrawn share of rewards
+    pub reserve_earmarked: u64,   // Slice of both penalties earmarked for the insurance reserve
+    pub reward_mint: Pubkey,      // Mint rewards are denominated in
+    pub unlock_at: i64,           // Original unlock timestamp
+    pub unlocked_at: i64,         // Actual unlock timestamp (early)
+    pub days_early: i64,          // How many days early
+    pub penalty_curve: PenaltyCurve, // Curve the penalty was taken from
+    pub penalty_bps: u16,         // Penalty applied at unlocked_at
+    pub principal_penalty: u64,   // Principal moved to the reward pool (0 unless the vault penalizes principal)
+}
+
+#[event]
+pub struct NftRewardRateUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub nft_reward_rate: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NftStakedEvent {
+    pub vault: Pubkey,
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub rarity_weight: u16,
+    pub boost_bps: u16,
+    pub total_nfts_staked: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NftRewardsClaimedEvent {
+    pub vault: Pubkey,
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub amount: u64,
+    pub reward_mint: Pubkey,
+    pub total_claimed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NftUnstakedEvent {
+    pub vault: Pubkey,
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub rewards_paid: u64,
+    pub rewards_forfeited: u64,        // Accrued rewards the pool could not cover (only with forfeit_unpaid)
+    pub reward_mint: Pubkey,
+    pub total_claimed: u64,
+    pub total_nfts_staked: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultVotingUpdatedEvent {
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub approved: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VotingOptedOutEvent {
+    pub stake_record: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VotingPowerInitializedEvent {
+    pub ve_supply: Pubkey,
+    pub mint: Pubkey,
+    pub shard: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VotingPowerCheckpointEvent {
+    pub stake_record: Pubkey,
+    pub authority: Pubkey,
+    pub voting_power: u64,
+    pub shard_voting_power: u64,    // Voting power of the stake's ve_supply shard
+    pub timestamp: i64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Vault authority mismatch")]
+    VaultAuthorityMismatch,
+    #[msg("Unauthorized: caller is not the authority")]
+    Unauthorized,
+    #[msg("Arithmetic overflow detected")]
+    Overflow,
+    #[msg("Arithmetic underflow detected")]
+    Underflow,
+    #[msg("Amount too small for staking")]
+    AmountTooSmall,
+    #[msg("Amount too large for staking")]
+    AmountTooLarge,
+    #[msg("Insufficient balance for operation")]
+    InsufficientBalance,
+    #[msg("Insufficient staked amount for withdrawal")]
+    InsufficientStake,
+    #[msg("Tokens are still locked")]
+    StillLocked,
+    #[msg("Reward rate above the registry maximum")]
+    RewardRateTooHigh,
+    #[msg("Lock duration below the registry minimum")]
+    LockDurationTooShort,
+    #[msg("Lock duration above the registry maximum")]
+    LockDurationTooLong,
+    #[msg("Vault is inactive")]
+    VaultInactive,
+    #[msg("Vault is already inactive")]
+    VaultAlreadyInactive,
+    #[msg("No rewards available to claim")]
+    NoRewardsToClaim,
+    #[msg("Insufficient vault balance for rewards")]
+    InsufficientVaultBalance,
+    #[msg("Invalid vault token account")]
+    InvalidVaultAccount,
+    #[msg("Deposit cooldown active - please wait before depositing again")]
+    DepositCooldownActive,
+    #[msg("Claim cooldown active - please wait before claiming again")]
+    ClaimCooldownActive,
+    #[msg("Division by zero")]
+    DivisionByZero,
+    #[msg("Invalid NFT (must be a supply=1, decimals=0 Hatchling held by the owner)")]
+    InvalidNftMint,
+    #[msg("Token account mint does not match the vault reward mint")]
+    InvalidRewardMint,
+    #[msg("Compounding requires the reward mint to equal the stake mint")]
+    RewardMintMismatch,
+    #[msg("Principal penalties require the reward mint to equal the stake mint")]
+    PrincipalPenaltyMintMismatch,
+    #[msg("Auto-compound is disabled for this vault")]
+    AutoCompoundDisabled,
+    #[msg("Invalid Guardian permission bits")]
+    InvalidGuardianPermissions,
+    #[msg("Guardian config does not belong to this stake")]
+    InvalidGuardianConfig,
+    #[msg("Guardian lacks the required permission")]
+    GuardianPermissionDenied,
+    #[msg("Signer does not hold the Guardian NFT")]
+    NotGuardianHolder,
+    #[msg("Signer does not hold the Master NFT")]
+    NotMasterHolder,
+    #[msg("Wallet is not eligible to deposit into this vault")]
+    DepositNotAllowed,
+    #[msg("Allowlist mode requires a non-zero Merkle root")]
+    InvalidAllowlistRoot,
+    #[msg("Deposit exceeds the vault, per-user or global stake cap")]
+    DepositCapExceeded,
+    #[msg("Vault must be deactivated first")]
+    VaultStillActive,
+    #[msg("Vault still has open positions")]
+    PositionsStillOpen,
+    #[msg("Stake record still holds tokens or NFTs")]
+    StakeNotEmpty,
+    #[msg("Nothing to sweep")]
+    NothingToSweep,
+    #[msg("Vault token accounts must be swept before closing")]
+    VaultNotSwept,
+    #[msg("Unbonding period must be between 0 and 30 days")]
+    InvalidUnbondingPeriod,
+    #[msg("This vault uses unbonding - use request_unstake")]
+    UnbondingRequired,
+    #[msg("Unbonding is not enabled for this vault")]
+    UnbondingDisabled,
+    #[msg("Unbonding period has not ended yet")]
+    UnbondingNotComplete,
+    #[msg("Vault is in receipt mode - use deposit_position")]
+    ReceiptModeEnabled,
+    #[msg("Vault is not in receipt mode")]
+    ReceiptModeDisabled,
+    #[msg("Position NFT account missing or not held")]
+    InvalidPositionAccount,
+    #[msg("Reward schedule is full")]
+    RewardScheduleFull,
+    #[msg("Invalid early-unlock penalty curve")]
+    InvalidPenaltyCurve,
+    #[msg("Invalid vault registry defaults")]
+    InvalidVaultDefaults,
+    #[msg("Vault index entry does not match this vault")]
+    InvalidVaultIndex,
+    #[msg("Invalid referrer")]
+    InvalidReferrer,
+    #[msg("Referral share exceeds maximum")]
+    ReferralShareTooHigh,
+    #[msg("Referrer has unclaimed rewards on this stake: pass the referrer accounts to pay them out")]
+    ReferralRewardsPending,
+    #[msg("Insufficient scales points")]
+    InsufficientScalesPoints,
+    #[msg("Reward epochs must start in the future, after the last scheduled epoch")]
+    RetroactiveRewardEpoch,
+    #[msg("Guardian report cooldown active - please wait before reporting again")]
+    ReportCooldownActive,
+    #[msg("Nothing new to report for this stake")]
+    NothingToReport,
+    #[msg("Guardian bond already has an open dispute")]
+    DisputeAlreadyOpen,
+    #[msg("No dispute to act on")]
+    NoOpenDispute,
+    #[msg("Slash amount must be positive and within the bond")]
+    InvalidSlashAmount,
+    #[msg("Challenge window has closed")]
+    ChallengeWindowClosed,
+    #[msg("Challenge window still active")]
+    ChallengeWindowActive,
+    #[msg("Guardian bond exit not requested or delay not elapsed")]
+    BondExitNotReady,
+    #[msg("Account is not the conservation fund")]
+    InvalidConservationFund,
+    #[msg("Token mint does not match the vault's stake mint")]
+    InvalidTokenMint,
+    #[msg("Snapshot timestamp is in the future")]
+    SnapshotInFuture,
+    #[msg("Balance history at this timestamp is no longer stored")]
+    SnapshotHistoryUnavailable,
+    #[msg("Account is not the vault's reward reserve")]
+    InvalidRewardReserve,
+    #[msg("Reward reserve already initialized")]
+    RewardReserveAlreadyInitialized,
+    #[msg("Reward reserve not initialized")]
+    RewardReserveNotInitialized,
+    #[msg("Reserve share exceeds maximum (50%)")]
+    ReserveShareTooHigh,
+    #[msg("Vault reward accounting was updated recently")]
+    VaultUpdateTooSoon,
+    #[msg("Stake has scales points not yet synced to the owner")]
+    ScalesPointsUnsynced,
+    #[msg("Invalid or missing ve_supply / global cap shard")]
+    InvalidShard,
+    #[msg("Stake is already out of the voting power supply")]
+    AlreadyOptedOut,
+    #[msg("Only program-address owners can leave the voting power supply")]
+    OwnerNotProgramAddress,
+    #[msg("Voting vaults must charge a principal penalty on every early unlock")]
+    VotingRequiresPrincipalPenalty,
 }
 
-#[derive(Accounts)]
-pub struct DepositTokens<'info> {
-    #[account(
-        mut,
-        seeds = [b"vault", vault.nft_mint.as_ref()],
-        bump = vault.bump
-    )]
-    pub vault: Account<'info, Vault>,
-    #[account(
-        init_if_needed,
-        payer = authority,
-        space = 8 + StakeRecord::INIT_SPACE,
-        seeds = [b"stake", vault.key().as_ref(), authority.key().as_ref()],
-        bump
-    )]
-    pub stake_record: Account<'info, StakeRecord>,
-    #[account(
-        mut,
-        constraint = user_token_account.owner == authority.key() @ ErrorCode::Unauthorized
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
-    )]
-    pub vault_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-}
+// ============================================
+// Reward Accrual Property Tests
+// ============================================
 
-#[derive(Accounts)]
-pub struct WithdrawTokens<'info> {
-    #[account(
-        mut,
-        seeds = [b"vault", vault.nft_mint.as_ref()],
-        bump = vault.bump
-    )]
-    pub vault: Account<'info, Vault>,
-    #[account(
-        mut,
-        seeds = [b"stake", vault.key().as_ref(), authority.key().as_ref()],
-        bump
-    )]
-    pub stake_record: Account<'info, StakeRecord>,
-    #[account(
-        mut,
-        constraint = user_token_account.owner == authority.key() @ ErrorCode::Unauthorized
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
-    )]
-    pub vault_token_account: Account<'info, TokenAccount>,
-    pub authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+#[cfg(test)]
+mod reward_accrual_tests {
+    use super::*;
+
+    const RATE: u16 = 1200; // 12% APR
+    const SCHEDULE: &[RewardEpoch] = &[RewardEpoch { start_time: 0, rate: RATE }];
+    const START: i64 = 1_700_000_000;
+    const CASES: u64 = 500;
+    const SECONDS_PER_YEAR: u128 = 365 * 24 * 60 * 60;
+    const DENOMINATOR: u128 = REWARD_RATE_DENOMINATOR as u128 * SECONDS_PER_YEAR;
+
+    // Deterministic xorshift64: failures reproduce from the case seed, no extra dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Self {
+            Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+        }
+
+        fn next(&mut self) -> u64 {
+            let mut x = self.0;
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            self.0 = x;
+            x
+        }
+
+        fn range(&mut self, lo: u64, hi: u64) -> u64 {
+            lo + self.next() % (hi - lo + 1)
+        }
+    }
+
+    fn new_stake(amount: u64, at: i64) -> StakeRecord {
+        StakeRecord {
+            amount,
+            last_claim: at,
+            rewards_accrued_at: at,
+            ..Default::default()
+        }
+    }
+
+    // Vault reward index at `t` for a vault whose accounting starts at START
+    fn index_at(schedule: &[RewardEpoch], t: i64) -> u128 {
+        calculate_pending_rewards(REWARD_INDEX_PRECISION, schedule, START, t).unwrap() as u128
+    }
+
+    // Settle as settle_stake_rewards does, against the index at `t`
+    fn settle_at(stake: &mut StakeRecord, schedule: &[RewardEpoch], t: i64) {
+        settle_rewards(stake, index_at(schedule, t), t).unwrap();
+    }
+
+    // Base rewards for `amount` between two index readings
+    fn from_index(amount: u64, from: u128, to: u128) -> u64 {
+        (amount as u128 * (to - from) / REWARD_INDEX_PRECISION as u128) as u64
+    }
+
+    // Unrounded reward numerator for one constant-balance segment
+    fn exact(amount: u64, seconds: i64) -> u128 {
+        amount as u128 * RATE as u128 * seconds as u128
+    }
+
+    #[test]
+    fn prop_rewards_conserved_across_balance_changes() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let mut now = START;
+            let mut stake = new_stake(rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT), now);
+            let mut exact_total: u128 = 0;
+            let mut claimed: u64 = 0;
+            let mut settlements: u128 = 0;
+            let mut index_slack: u128 = 0;
+
+            for _ in 0..rng.range(1, 20) {
+                let dt = rng.range(0, 90 * SECONDS_PER_DAY as u64) as i64;
+                exact_total += exact(stake.amount, dt);
+                now += dt;
+
+                settle_at(&mut stake, SCHEDULE, now);
+                settlements += 1;
+                // A floored index step is off by < 1 unit per REWARD_INDEX_PRECISION staked
+                index_slack += 1 + stake.amount as u128 / REWARD_INDEX_PRECISION as u128;
+
+                match rng.range(0, 2) {
+                    // Top-up
+                    0 => stake.amount += rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT),
+                    // Partial withdrawal
+                    1 => stake.amount -= rng.range(0, stake.amount),
+                    // Claim
+                    _ => {
+                        claimed += stake.pending_rewards;
+                        stake.pending_rewards = 0;
+                    }
+                }
+            }
+
+            let paid = (claimed + stake.pending_rewards) as u128;
+            let expected = exact_total / DENOMINATOR;
+            assert!(
+                paid <= expected + index_slack,
+                "seed {seed}: paid {paid} > accrued {expected}"
+            );
+            assert!(
+                expected <= paid + settlements + index_slack,
+                "seed {seed}: lost {} base units over {settlements} settlements",
+                expected.saturating_sub(paid)
+            );
+        }
+    }
+
+    #[test]
+    fn prop_partial_withdrawal_keeps_accrued_rewards() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let amount = rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT);
+            let mut stake = new_stake(amount, START);
+            let t1 = START + rng.range(1, 365 * SECONDS_PER_DAY as u64) as i64;
+            let t2 = t1 + rng.range(1, 365 * SECONDS_PER_DAY as u64) as i64;
+            let withdrawn = rng.range(1, amount);
+
+            settle_at(&mut stake, SCHEDULE, t1);
+            stake.amount -= withdrawn;
+            settle_at(&mut stake, SCHEDULE, t2);
+
+            let (i1, i2) = (index_at(SCHEDULE, t1), index_at(SCHEDULE, t2));
+            let expected = from_index(amount, 0, i1) + from_index(amount - withdrawn, i1, i2);
+            assert_eq!(stake.pending_rewards, expected, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn prop_top_up_is_not_retroactive() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let amount = rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT);
+            let top_up = rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT);
+            let mut stake = new_stake(amount, START);
+            let t1 = START + rng.range(0, 365 * SECONDS_PER_DAY as u64) as i64;
+            let t2 = t1 + rng.range(0, 365 * SECONDS_PER_DAY as u64) as i64;
+
+            settle_at(&mut stake, SCHEDULE, t1);
+            stake.amount += top_up;
+            settle_at(&mut stake, SCHEDULE, t2);
+
+            let (i1, i2) = (index_at(SCHEDULE, t1), index_at(SCHEDULE, t2));
+            let expected = from_index(amount, 0, i1) + from_index(amount + top_up, i1, i2);
+            assert_eq!(stake.pending_rewards, expected, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn prop_settlement_is_idempotent_and_monotonic() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let mut stake = new_stake(rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT), START);
+            let t = START + rng.range(0, 365 * SECONDS_PER_DAY as u64) as i64;
+
+            settle_at(&mut stake, SCHEDULE, t);
+            let settled = (stake.pending_rewards, stake.rewards_accrued_at, stake.reward_index_paid);
+
+            // Same timestamp again: nothing new accrues
+            settle_at(&mut stake, SCHEDULE, t);
+            let state = (stake.pending_rewards, stake.rewards_accrued_at, stake.reward_index_paid);
+            assert_eq!(state, settled, "seed {seed}");
+
+            // Earlier accrual time (vault deactivated in the past): no change, no rewind
+            let earlier = START + rng.range(0, (t - START) as u64) as i64;
+            settle_at(&mut stake, SCHEDULE, earlier);
+            let state = (stake.pending_rewards, stake.rewards_accrued_at, stake.reward_index_paid);
+            assert_eq!(state, settled, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn prop_boost_accrues_on_top_of_the_index() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let amount = rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT);
+            let mut stake = new_stake(amount, START);
+            stake.nft_boost_bps = rng.range(0, 2 * MAX_NFT_BOOST_BPS as u64) as u16;
+            let t = START + rng.range(0, 365 * SECONDS_PER_DAY as u64) as i64;
+
+            settle_at(&mut stake, SCHEDULE, t);
+
+            // Boost above MAX_NFT_BOOST_BPS earns nothing extra
+            let boost = stake.nft_boost_bps.min(MAX_NFT_BOOST_BPS) as u128;
+            let expected = from_index(amount, 0, index_at(SCHEDULE, t))
+                + (amount as u128 * boost * (t - START) as u128 / DENOMINATOR) as u64;
+            assert_eq!(stake.pending_rewards, expected, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn prop_scales_points_survive_withdrawals() {
+        let lock_duration = 90 * SECONDS_PER_DAY;
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let mut now = START;
+            let mut stake = new_stake(rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT), now);
+            stake.points_accrued_at = now;
+            let mut exact_total: u128 = 0;
+            let mut settlements: u128 = 0;
+
+            for _ in 0..rng.range(1, 20) {
+                let dt = rng.range(0, 90 * SECONDS_PER_DAY as u64) as i64;
+                exact_total += stake.amount as u128 * dt as u128 * 15000;
+                now += dt;
+
+                let before = stake.scales_points;
+                settle_points(&mut stake, lock_duration, now).unwrap();
+                settlements += 1;
+                assert!(stake.scales_points >= before, "seed {seed}");
+
+                // Withdraw some or all: points already earned stay
+                stake.amount -= rng.range(0, stake.amount);
+            }
+
+            // Per-settlement flooring loses < 1 point each
+            let expected = exact_total / (SCALES_MULTIPLIER_DENOMINATOR as u128 * SECONDS_PER_DAY as u128);
+            assert!(stake.scales_points <= expected, "seed {seed}");
+            assert!(expected - stake.scales_points <= settlements, "seed {seed}");
+        }
+    }
+
+    // Random schedule: creation epoch at START plus up to MAX_REWARD_EPOCHS - 1 later epochs
+    fn random_schedule(rng: &mut Rng) -> Vec<RewardEpoch> {
+        let mut schedule = vec![RewardEpoch { start_time: START, rate: rng.range(0, 10000) as u16 }];
+        for _ in 1..rng.range(1, MAX_REWARD_EPOCHS as u64) {
+            let start_time = schedule.last().unwrap().start_time
+                + rng.range(1, 120 * SECONDS_PER_DAY as u64) as i64;
+            schedule.push(RewardEpoch { start_time, rate: rng.range(0, 10000) as u16 });
+        }
+        schedule
+    }
+
+    // Reference: rate in force at `t`
+    fn rate_at(schedule: &[RewardEpoch], t: i64) -> u16 {
+        schedule.iter().rev().find(|epoch| epoch.start_time <= t).unwrap().rate
+    }
+
+    #[test]
+    fn prop_schedule_integrates_across_epochs() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let schedule = random_schedule(&mut rng);
+            let amount = rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT);
+            let horizon = (schedule.last().unwrap().start_time - START) as u64 + 365 * SECONDS_PER_DAY as u64;
+            let from = START + rng.range(0, horizon) as i64;
+            let to = from + rng.range(0, horizon) as i64;
+
+            // Reference: split [from, to) at every epoch boundary and sum constant-rate segments
+            let mut cuts: Vec<i64> = schedule
+                .iter()
+                .map(|epoch| epoch.start_time)
+                .filter(|t| *t > from && *t < to)
+                .collect();
+            cuts.insert(0, from);
+            cuts.push(to);
+            let numerator: u128 = cuts
+                .windows(2)
+                .map(|w| amount as u128 * rate_at(&schedule, w[0]) as u128 * (w[1] - w[0]) as u128)
+                .sum();
+            let expected = (numerator / DENOMINATOR) as u64;
+
+            let rewards = calculate_pending_rewards(amount, &schedule, from, to).unwrap();
+            assert_eq!(rewards, expected, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn prop_settling_across_epochs_conserves_rewards() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let schedule = random_schedule(&mut rng);
+            let mut stake = new_stake(rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT), START);
+            let end = schedule.last().unwrap().start_time + 365 * SECONDS_PER_DAY;
+
+            // Index rounding: < 1 unit per REWARD_INDEX_PRECISION staked, plus one per settlement
+            let mut max_loss: u64 = 1 + stake.amount / REWARD_INDEX_PRECISION;
+            let mut now = START;
+            while now < end {
+                now = (now + rng.range(1, 60 * SECONDS_PER_DAY as u64) as i64).min(end);
+                settle_at(&mut stake, &schedule, now);
+                max_loss += 1;
+            }
+
+            let single_shot = calculate_pending_rewards(stake.amount, &schedule, START, end).unwrap();
+            assert!(stake.pending_rewards <= single_shot, "seed {seed}");
+            assert!(single_shot - stake.pending_rewards <= max_loss, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn prop_future_epochs_are_not_retroactive() {
+        for seed in 0..CASES {
+            let mut rng = Rng::new(seed);
+            let mut schedule = random_schedule(&mut rng);
+            schedule.truncate(MAX_REWARD_EPOCHS - 1);
+            let amount = rng.range(MIN_STAKE_AMOUNT, MAX_STAKE_AMOUNT);
+            let last_start = schedule.last().unwrap().start_time;
+            let now = last_start + rng.range(0, 365 * SECONDS_PER_DAY as u64) as i64;
+
+            let before = calculate_pending_rewards(amount, &schedule, START, now).unwrap();
+            schedule.push(RewardEpoch {
+                start_time: now + rng.range(1, 365 * SECONDS_PER_DAY as u64) as i64,
+                rate: rng.range(0, 10000) as u16,
+            });
+            let after = calculate_pending_rewards(amount, &schedule, START, now).unwrap();
+
+            assert_eq!(before, after, "seed {seed}");
+        }
+    }
 }
 
-#[derive(Accounts)]
-pub struct ClaimRewards<'info> {
-    #[account(
-        seeds = [b"vault", vault.nft_mint.as_ref()],
-        bump = vault.bump
-    )]
-    pub vault: Account<'info, Vault>,
-    #[account(
-        mut,
-        seeds = [b"stake", vault.key().as_ref(), authority.key().as_ref()],
-        bump
-    )]
-    pub stake_record: Account<'info, StakeRecord>,
-    #[account(
-        mut,
-        constraint = user_token_account.owner == authority.key() @ ErrorCode::Unauthorized
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = vault_token_account.key() == vault.vault_token_account @ ErrorCode::InvalidVaultAccount
-    )]
-    pub vault_token_account: Account<'info, TokenAccount>,
-    pub authority: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+// ============================================
+// Guardian Permission Tests
+// ============================================
+
+#[cfg(test)]
+mod guardian_permission_tests {
+    use super::*;
+    use anchor_spl::token::spl_token::{
+        solana_program::program_pack::Pack,
+        state::{Account as SplAccount, AccountState},
+    };
+
+    fn config(stake_record: Pubkey, guardian_nft_mint: Pubkey, permissions: u8) -> GuardianConfig {
+        GuardianConfig {
+            vault: Pubkey::new_unique(),
+            stake_record,
+            master: Pubkey::new_unique(),
+            guardian_nft_mint,
+            permissions,
+            report_address: Pubkey::new_unique(),
+            installed_at: 0,
+            updated_at: 0,
+            last_report_at: 0,
+            report_count: 0,
+            reported_unlock_at: 0,
+            reported_total_claimed: 0,
+            bump: 255,
+        }
+    }
+
+    fn nft_account(mint: Pubkey, owner: Pubkey, amount: u64) -> TokenAccount {
+        let mut data = [0u8; SplAccount::LEN];
+        SplAccount {
+            mint,
+            owner,
+            amount,
+            state: AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        TokenAccount::try_deserialize_unchecked(&mut &data[..]).unwrap()
+    }
+
+    #[test]
+    fn guardian_acts_only_within_granted_permissions() {
+        let (stake, mint, guardian) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let config = config(stake, mint, GUARDIAN_PERMISSION_CLAIM | GUARDIAN_PERMISSION_REPORT);
+        let nft = nft_account(mint, guardian, 1);
+
+        assert!(verify_guardian(&config, &nft, stake, guardian, GUARDIAN_PERMISSION_CLAIM).is_ok());
+        assert!(verify_guardian(&config, &nft, stake, guardian, GUARDIAN_PERMISSION_REPORT).is_ok());
+        assert!(verify_guardian(&config, &nft, stake, guardian, GUARDIAN_PERMISSION_COMPOUND).is_err());
+        assert!(verify_guardian(
+            &config,
+            &nft,
+            stake,
+            guardian,
+            GUARDIAN_PERMISSION_CLAIM | GUARDIAN_PERMISSION_COMPOUND
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn config_serves_only_its_own_stake() {
+        let (stake, mint, guardian) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let config = config(stake, mint, GUARDIAN_PERMISSION_CLAIM);
+        let nft = nft_account(mint, guardian, 1);
+
+        assert!(verify_guardian(&config, &nft, Pubkey::new_unique(), guardian, GUARDIAN_PERMISSION_CLAIM).is_err());
+    }
+
+    #[test]
+    fn only_the_current_guardian_nft_holder_passes() {
+        let (stake, mint, guardian) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let config = config(stake, mint, GUARDIAN_PERMISSION_CLAIM);
+
+        // Someone else signing with the holder's account
+        let nft = nft_account(mint, guardian, 1);
+        assert!(verify_guardian(&config, &nft, stake, Pubkey::new_unique(), GUARDIAN_PERMISSION_CLAIM).is_err());
+        // A token of another mint
+        let other = nft_account(Pubkey::new_unique(), guardian, 1);
+        assert!(verify_guardian(&config, &other, stake, guardian, GUARDIAN_PERMISSION_CLAIM).is_err());
+        // The NFT was transferred away
+        let emptied = nft_account(mint, guardian, 0);
+        assert!(verify_guardian(&config, &emptied, stake, guardian, GUARDIAN_PERMISSION_CLAIM).is_err());
+        assert!(verify_guardian_holder(mint, &emptied, guardian).is_err());
+        assert!(verify_guardian_holder(mint, &nft, guardian).is_ok());
+    }
 }
 
-#[derive(Accounts)]
-pub struct DeactivateVault<'info> {
-    #[account(
-        mut,
-        seeds = [b"vault", vault.nft_mint.as_ref()],
-        bump = vault.bump
-    )]
-    pub vault: Account<'info, Vault>,
-    pub authority: Signer<'info>,
+// ============================================
+// Allowlist Proof Tests
+// ============================================
+
+#[cfg(test)]
+mod allowlist_proof_tests {
+    use super::*;
+
+    fn leaf(wallet: &Pubkey) -> [u8; 32] {
+        hashv(&[wallet.as_ref()]).to_bytes()
+    }
+
+    // Sorted-pair parent, as verify_allowlist_proof hashes it
+    fn parent(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
+        if a <= b {
+            hashv(&[&a, &b]).to_bytes()
+        } else {
+            hashv(&[&b, &a]).to_bytes()
+        }
+    }
+
+    // Four-wallet tree: returns (wallets, root, proof for each wallet)
+    fn tree() -> ([Pubkey; 4], [u8; 32], [[[u8; 32]; 2]; 4]) {
+        let wallets = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let leaves = wallets.map(|wallet| leaf(&wallet));
+        let left = parent(leaves[0], leaves[1]);
+        let right = parent(leaves[2], leaves[3]);
+        let proofs = [
+            [leaves[1], right],
+            [leaves[0], right],
+            [leaves[3], left],
+            [leaves[2], left],
+        ];
+        (wallets, parent(left, right), proofs)
+    }
+
+    #[test]
+    fn every_listed_wallet_verifies() {
+        let (wallets, root, proofs) = tree();
+        for (wallet, proof) in wallets.iter().zip(proofs.iter()) {
+            assert!(verify_allowlist_proof(&root, *wallet, proof));
+        }
+    }
+
+    #[test]
+    fn single_leaf_tree_needs_no_proof() {
+        let wallet = Pubkey::new_unique();
+        assert!(verify_allowlist_proof(&leaf(&wallet), wallet, &[]));
+        assert!(!verify_allowlist_proof(&leaf(&wallet), Pubkey::new_unique(), &[]));
+    }
+
+    #[test]
+    fn bad_proofs_are_rejected() {
+        let (wallets, root, proofs) = tree();
+
+        // Unlisted wallet with a valid proof
+        assert!(!verify_allowlist_proof(&root, Pubkey::new_unique(), &proofs[0]));
+        // Listed wallet with another wallet's proof
+        assert!(!verify_allowlist_proof(&root, wallets[0], &proofs[2]));
+        // Proof levels swapped, truncated, or tampered
+        assert!(!verify_allowlist_proof(&root, wallets[0], &[proofs[0][1], proofs[0][0]]));
+        assert!(!verify_allowlist_proof(&root, wallets[0], &proofs[0][..1]));
+        let mut tampered = proofs[0];
+        tampered[1][0] ^= 1;
+        assert!(!verify_allowlist_proof(&root, wallets[0], &tampered));
+    }
 }
 
-/// Vault account - Self-custody staking vault
-/// Master NFT creates vault and installs Guardian reporting configuration
-#[account]
-#[derive(InitSpace)]
-pub struct Vault {
-    pub nft_mint: Pubkey,              // Master NFT that created this vault
-    pub authority: Pubkey,             // Vault authority (Master NFT holder)
-    pub token_mint: Pubkey,            // PANGI token mint
-    pub vault_token_account: Pubkey,   // Vault's token account (holds staked tokens)
-    pub total_staked: u64,             // Total tokens staked in this vault
-    pub reward_rate: u16,              // Reward rate in basis points
-    pub lock_duration: i64,            // Lock duration in seconds
-    pub created_at: i64,               // Vault creation timestamp
-    pub last_reward_update: i64,       // Last reward calculation timestamp
-    pub total_penalties_collected: u64, // Total 15% penalties returned to pool
-    pub is_active: bool,               // Vault active status
-    pub bump: u8,                      // PDA bump seed
-}
+// ============================================
+// Deposit Cap Tests
+// ============================================
+
+#[cfg(test)]
+mod deposit_cap_tests {
+    use super::*;
+
+    // Serialized GlobalCapShard account data, as the program stores it
+    fn shard_data(mint: Pubkey, shard: u8, total_staked: u64) -> Vec<u8> {
+        let mut data = Vec::new();
+        GlobalCapShard { mint, shard, total_staked, bump: 255 }
+            .try_serialize(&mut data)
+            .unwrap();
+        data
+    }
+
+    // total_staked_for_mint over shard accounts holding `datas`, owned by `owner`
+    fn staked_across(mint: Pubkey, owner: Pubkey, datas: &mut [Vec<u8>]) -> Result<u64> {
+        let keys: Vec<Pubkey> = datas.iter().map(|_| Pubkey::new_unique()).collect();
+        let mut lamports = vec![0u64; datas.len()];
+        let infos: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(datas.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, false, lamports, data, &owner, false, 0)
+            })
+            .collect();
+        total_staked_for_mint(&infos, &mint)
+    }
+
+    #[test]
+    fn deposit_within_room_is_taken_whole() {
+        assert_eq!(deposit_fill(5_000, 5_000, false).unwrap(), 5_000);
+        assert_eq!(deposit_fill(5_000, u64::MAX, false).unwrap(), 5_000);
+    }
+
+    #[test]
+    fn over_the_cap_fills_the_room_only_when_allowed() {
+        let room = MIN_STAKE_AMOUNT * 3;
+        assert_eq!(deposit_fill(room + 1, room, true).unwrap(), room);
+        assert!(deposit_fill(room + 1, room, false).is_err());
+    }
+
+    #[test]
+    fn partial_fill_below_min_stake_is_rejected() {
+        assert!(deposit_fill(MIN_STAKE_AMOUNT * 2, MIN_STAKE_AMOUNT - 1, true).is_err());
+        assert!(deposit_fill(MIN_STAKE_AMOUNT * 2, 0, true).is_err());
+        assert_eq!(
+            deposit_fill(MIN_STAKE_AMOUNT * 2, MIN_STAKE_AMOUNT, true).unwrap(),
+            MIN_STAKE_AMOUNT
+        );
+    }
+
+    #[test]
+    fn global_cap_room_counts_every_shard_of_the_mint() {
+        let mint = Pubkey::new_unique();
+        let mut datas: Vec<Vec<u8>> = (0..GLOBAL_SHARD_COUNT)
+            .map(|i| shard_data(mint, i, 1_000 * (i as u64 + 1)))
+            .collect();
+        // 1_000 * (1 + 2 + ... + 8)
+        let total = staked_across(mint, ID, &mut datas).unwrap();
+        assert_eq!(total, 36_000);
+
+        // No single shard is near the cap, but together they leave little room
+        assert_eq!(cap_room(40_000, total), 4_000);
+        assert_eq!(cap_room(30_000, total), 0);
+    }
+
+    #[test]
+    fn global_total_rejects_incomplete_or_foreign_shards() {
+        let mint = Pubkey::new_unique();
+        let shards = |mint: Pubkey| -> Vec<Vec<u8>> {
+            (0..GLOBAL_SHARD_COUNT).map(|i| shard_data(mint, i, 1_000)).collect()
+        };
 
-/// StakeRecord - Individual user stake position
-/// Tracks user's staked tokens and Guardian reporting configuration
-#[account]
-#[derive(InitSpace)]
-pub struct StakeRecord {
-    pub vault: Pubkey,                 // Parent vault
-    pub authority: Pubkey,             // User who staked (Master NFT holder)
-    pub amount: u64,                   // Amount staked (tokens remain in user custody)
-    pub staked_at: i64,                // Stake creation timestamp
-    pub unlock_at: i64,                // Unlock timestamp (when Guardian reports)
-    pub last_claim: i64,               // Last reward claim timestamp
-    pub total_claimed: u64,            // Total rewards claimed
-    // Guardian reporting configuration (installed by Master)
-    // Guardian reports OUT to public address when unlock_at is reached
-    // Guardian receives NO data from PANGI (one-way reporting)
+        // A missing shard would undercount the mint's total
+        let mut missing = shards(mint);
+        missing.pop();
+        assert!(staked_across(mint, ID, &mut missing).is_err());
+
+        // The same shard passed twice
+        let mut duplicated = shards(mint);
+        duplicated[1] = shard_data(mint, 0, 1_000);
+        assert!(staked_across(mint, ID, &mut duplicated).is_err());
+
+        // Another mint's shards, or accounts the program does not own
+        assert!(staked_across(mint, ID, &mut shards(Pubkey::new_unique())).is_err());
+        assert!(staked_across(mint, Pubkey::new_unique(), &mut shards(mint)).is_err());
+    }
 }
 
-#[event]
-pub struct VaultCreatedEvent {
-    pub nft_mint: Pubkey,
-    pub vault: Pubkey,
-    pub authority: Pubkey,
-    pub reward_rate: u16,
-    pub lock_duration: i64,
-    pub timestamp: i64,
+// ============================================
+// Unbonding Queue Tests
+// ============================================
+
+#[cfg(test)]
+mod unbonding_tests {
+    use super::*;
+
+    const T0: i64 = 1_700_000_000;
+
+    fn entry(requested_at: i64, unbonding_period: i64) -> UnbondingEntry {
+        UnbondingEntry {
+            vault: Pubkey::new_unique(),
+            stake_record: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            id: 0,
+            amount: 1_000,
+            requested_at,
+            release_at: requested_at + unbonding_period,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn entry_releases_once_the_period_has_passed() {
+        let entry = entry(T0, 7 * SECONDS_PER_DAY);
+        assert!(!unbonding_released(&entry, true, T0));
+        assert!(!unbonding_released(&entry, true, T0 + 7 * SECONDS_PER_DAY - 1));
+        assert!(unbonding_released(&entry, true, T0 + 7 * SECONDS_PER_DAY));
+    }
+
+    #[test]
+    fn deactivation_releases_every_entry_at_once() {
+        let entry = entry(T0, MAX_UNBONDING_PERIOD);
+        assert!(unbonding_released(&entry, false, T0));
+    }
 }
 
-#[event]
-pub struct TokensDepositedEvent {
-    pub vault: Pubkey,
-    pub authority: Pubkey,
-    pub amount: u64,
-    pub total_staked: u64,
-    pub unlock_at: i64,
-    pub timestamp: i64,
+// ============================================
+// Early-Unlock Penalty Curve Tests
+// ============================================
+
+#[cfg(test)]
+mod penalty_curve_tests {
+    use super::*;
+
+    const STAKED_AT: i64 = 1_700_000_000;
+    const LOCK: i64 = 100 * SECONDS_PER_DAY;
+    const UNLOCK_AT: i64 = STAKED_AT + LOCK;
+
+    fn vault_with(curve: PenaltyCurve, penalty_bps: u16, steps: &[PenaltyStep]) -> Vault {
+        let mut vault = Vault {
+            penalty_curve: curve,
+            penalty_bps,
+            penalty_step_count: steps.len() as u8,
+            ..Default::default()
+        };
+        vault.penalty_steps[..steps.len()].copy_from_slice(steps);
+        vault
+    }
+
+    fn bps_at(vault: &Vault, now: i64) -> u16 {
+        early_unlock_penalty_bps(vault, STAKED_AT, UNLOCK_AT, now).unwrap()
+    }
+
+    #[test]
+    fn flat_applies_until_unlock() {
+        let vault = vault_with(PenaltyCurve::Flat, 1500, &[]);
+        assert_eq!(bps_at(&vault, STAKED_AT), 1500);
+        assert_eq!(bps_at(&vault, UNLOCK_AT - 1), 1500);
+        assert_eq!(bps_at(&vault, UNLOCK_AT), 0);
+        assert_eq!(bps_at(&vault, UNLOCK_AT + 1), 0);
+    }
+
+    #[test]
+    fn linear_decay_scales_with_remaining_lock() {
+        let vault = vault_with(PenaltyCurve::LinearDecay, 2000, &[]);
+        assert_eq!(bps_at(&vault, STAKED_AT), 2000);
+        // Before staked_at the remaining lock is capped at the full duration
+        assert_eq!(bps_at(&vault, STAKED_AT - SECONDS_PER_DAY), 2000);
+        assert_eq!(bps_at(&vault, STAKED_AT + LOCK / 2), 1000);
+        assert_eq!(bps_at(&vault, STAKED_AT + LOCK * 3 / 4), 500);
+        // Rounds down: one second before unlock is effectively free
+        assert_eq!(bps_at(&vault, UNLOCK_AT - 1), 0);
+        assert_eq!(bps_at(&vault, UNLOCK_AT), 0);
+    }
+
+    #[test]
+    fn linear_decay_with_empty_lock_is_free() {
+        let vault = vault_with(PenaltyCurve::LinearDecay, 2000, &[]);
+        assert_eq!(early_unlock_penalty_bps(&vault, UNLOCK_AT, UNLOCK_AT, UNLOCK_AT - 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn stepped_uses_largest_step_within_remaining_lock() {
+        let steps = [
+            PenaltyStep { min_remaining: 0, penalty_bps: 200 },
+            PenaltyStep { min_remaining: 30 * SECONDS_PER_DAY, penalty_bps: 800 },
+            PenaltyStep { min_remaining: 60 * SECONDS_PER_DAY, penalty_bps: 1500 },
+        ];
+        let vault = vault_with(PenaltyCurve::Stepped, 0, &steps);
+
+        assert_eq!(bps_at(&vault, STAKED_AT), 1500);
+        // Exactly min_remaining left: that step applies
+        assert_eq!(bps_at(&vault, UNLOCK_AT - 60 * SECONDS_PER_DAY), 1500);
+        assert_eq!(bps_at(&vault, UNLOCK_AT - 60 * SECONDS_PER_DAY + 1), 800);
+        assert_eq!(bps_at(&vault, UNLOCK_AT - 30 * SECONDS_PER_DAY), 800);
+        assert_eq!(bps_at(&vault, UNLOCK_AT - 30 * SECONDS_PER_DAY + 1), 200);
+        assert_eq!(bps_at(&vault, UNLOCK_AT - 1), 200);
+        assert_eq!(bps_at(&vault, UNLOCK_AT), 0);
+    }
+
+    #[test]
+    fn penalty_is_curve_share_of_withdrawn_rewards() {
+        // Half the stake withdrawn at 20%: 20% of half the settled rewards is forfeited
+        assert_eq!(calculate_early_unlock_penalty(10_000, 500, 1_000, 2000).unwrap(), 1_000);
+        // Full withdrawal at 20% keeps 80% of the rewards claimable
+        assert_eq!(calculate_early_unlock_penalty(10_000, 1_000, 1_000, 2000).unwrap(), 2_000);
+        // A free point on the curve forfeits nothing
+        assert_eq!(calculate_early_unlock_penalty(10_000, 1_000, 1_000, 0).unwrap(), 0);
+        // The penalty never exceeds the withdrawn share
+        assert_eq!(calculate_early_unlock_penalty(10_000, 1_000, 1_000, 10_000).unwrap(), 10_000);
+    }
+
+    #[test]
+    fn stepped_without_matching_step_is_free() {
+        let steps = [PenaltyStep { min_remaining: 10 * SECONDS_PER_DAY, penalty_bps: 900 }];
+        let vault = vault_with(PenaltyCurve::Stepped, 0, &steps);
+        assert_eq!(bps_at(&vault, UNLOCK_AT - 10 * SECONDS_PER_DAY), 900);
+        assert_eq!(bps_at(&vault, UNLOCK_AT - 10 * SECONDS_PER_DAY + 1), 0);
+    }
 }
 
-#[event]
-pub struct TokensWithdrawnEvent {
-    pub vault: Pubkey,
-    pub authority: Pubkey,
-    pub amount: u64,
-    pub pending_rewards: u64,       // Rewards paid to user (proportional - 15% if early)
-    pub penalty_to_pool: u64,       // 15% penalty returned to pool (0 if normal unlock)
-    pub remaining_stake: u64,
-    pub is_early_unlock: bool,      // true if withdrawn before unlock_at
-    pub timestamp: i64,
+// ============================================
+// Voting Power Supply Tests
+// ============================================
+
+#[cfg(test)]
+mod voting_power_tests {
+    use super::*;
+
+    const START: i64 = 1_700_000_000;
+
+    fn new_supply() -> VotingPowerSupply {
+        VotingPowerSupply {
+            mint: Pubkey::default(),
+            shard: 0,
+            curve: VotingPowerCurve {
+                last_checkpoint: START,
+                ..Default::default()
+            },
+            bump: 0,
+        }
+    }
+
+    // Active, governance-approved vault with a flat principal penalty
+    fn voting_vault() -> Vault {
+        Vault {
+            is_active: true,
+            penalty_curve: PenaltyCurve::Flat,
+            penalty_bps: 500,
+            penalize_principal: true,
+            voting_approved: true,
+            ve_curve: VotingPowerCurve {
+                last_checkpoint: START,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn lock(supply: &mut VotingPowerSupply, amount: u64, unlock_at: i64, now: i64) -> StakeRecord {
+        let mut stake = StakeRecord {
+            amount,
+            unlock_at,
+            ..Default::default()
+        };
+        checkpoint_voting_power(supply, &mut voting_vault(), &mut stake, now).unwrap();
+        stake
+    }
+
+    // Supply matches Σ per-stake power up to one unit of rounding per stake
+    fn assert_matches(supply: &VotingPowerSupply, stakes: &[&StakeRecord], t: i64) {
+        let total = shard_voting_power_at(supply, t).unwrap();
+        let sum: u64 = stakes
+            .iter()
+            .map(|stake| voting_power_at(stake.amount, stake.unlock_at, t).unwrap())
+            .sum();
+        assert!(total >= sum && total - sum <= stakes.len() as u64, "t {t}: {total} vs {sum}");
+    }
+
+    #[test]
+    fn expired_locks_leave_supply_without_checkpoint() {
+        let mut supply = new_supply();
+        let short = lock(&mut supply, 1_000_000_000, START + 30 * SECONDS_PER_DAY, START);
+        let long = lock(&mut supply, 5_000_000_000, START + 300 * SECONDS_PER_DAY, START);
+
+        for day in [0, 10, 29, 30, 31, 100, 299, 300, 400] {
+            assert_matches(&supply, &[&short, &long], START + day * SECONDS_PER_DAY);
+        }
+        assert_eq!(shard_voting_power_at(&supply, START + 400 * SECONDS_PER_DAY).unwrap(), 0);
+    }
+
+    #[test]
+    fn bias_and_slope_reach_zero_when_last_lock_ends() {
+        let mut supply = new_supply();
+        lock(&mut supply, 7_000_000, START + 20 * SECONDS_PER_DAY, START);
+        lock(&mut supply, 3_000_000, START + 200 * SECONDS_PER_DAY, START);
+
+        advance_voting_curve(&mut supply.curve, START + 2 * MAX_LOCK_DURATION).unwrap();
+        assert_eq!(supply.curve.bias, 0);
+        assert_eq!(supply.curve.slope, 0);
+        assert!(supply.curve.slope_changes.iter().all(|change| *change == 0));
+    }
+
+    #[test]
+    fn relocking_moves_the_scheduled_slope_change() {
+        let mut supply = new_supply();
+        let mut vault = voting_vault();
+        let mut stake = StakeRecord {
+            amount: 2_000_000_000,
+            unlock_at: START + 60 * SECONDS_PER_DAY,
+            ..Default::default()
+        };
+        checkpoint_voting_power(&mut supply, &mut vault, &mut stake, START).unwrap();
+        let other = lock(&mut supply, 1_000_000_000, START + 90 * SECONDS_PER_DAY, START);
+
+        // Top-up with a later unlock 10 days in
+        let now = START + 10 * SECONDS_PER_DAY;
+        stake.amount = 3_000_000_000;
+        stake.unlock_at = now + 180 * SECONDS_PER_DAY;
+        checkpoint_voting_power(&mut supply, &mut vault, &mut stake, now).unwrap();
+
+        for day in [10, 59, 60, 61, 90, 189, 190, 191] {
+            assert_matches(&supply, &[&stake, &other], START + day * SECONDS_PER_DAY);
+        }
+
+        // Full exit removes the stake's remaining slope change
+        let now = START + 100 * SECONDS_PER_DAY;
+        stake.amount = 0;
+        checkpoint_voting_power(&mut supply, &mut vault, &mut stake, now).unwrap();
+        advance_voting_curve(&mut supply.curve, START + 400 * SECONDS_PER_DAY).unwrap();
+        assert_eq!((supply.curve.bias, supply.curve.slope), (0, 0));
+    }
+
+    #[test]
+    fn lock_ends_are_week_aligned() {
+        assert_eq!(ve_lock_end(3 * VE_WEEK + 5), 3 * VE_WEEK);
+        assert_eq!(ve_lock_end(3 * VE_WEEK), 3 * VE_WEEK);
+        // Locks ending within the current week carry no voting power
+        let now = 10 * VE_WEEK + 1;
+        assert_eq!(voting_power_at(1_000, 11 * VE_WEEK - 1, now).unwrap(), 0);
+        assert!(voting_power_at(1_000_000_000, 11 * VE_WEEK, now).unwrap() > 0);
+    }
+
+    #[test]
+    fn stakes_in_unapproved_vaults_carry_no_voting_power() {
+        let mut supply = new_supply();
+        let mut vault = Vault {
+            voting_approved: false,
+            ..voting_vault()
+        };
+        let mut stake = StakeRecord {
+            amount: 1_000_000_000,
+            unlock_at: START + 100 * SECONDS_PER_DAY,
+            ..Default::default()
+        };
+        checkpoint_voting_power(&mut supply, &mut vault, &mut stake, START).unwrap();
+        assert_eq!((stake.ve_amount, stake.ve_unlock_at), (0, 0));
+        assert_eq!(shard_voting_power_at(&supply, START).unwrap(), 0);
+
+        // Approval counts the stake from its next checkpoint
+        vault.voting_approved = true;
+        checkpoint_voting_power(&mut supply, &mut vault, &mut stake, START).unwrap();
+        assert_matches(&supply, &[&stake], START);
+    }
+
+    #[test]
+    fn deactivation_removes_the_vault_from_the_supply_at_once() {
+        let mut supply = new_supply();
+        let mut vault = voting_vault();
+        let mut stakes: Vec<StakeRecord> = [(1_000_000_000, 40), (3_000_000_000, 200)]
+            .iter()
+            .map(|&(amount, days)| StakeRecord {
+                amount,
+                unlock_at: START + days * SECONDS_PER_DAY,
+                ..Default::default()
+            })
+            .collect();
+        for stake in stakes.iter_mut() {
+            checkpoint_voting_power(&mut supply, &mut vault, stake, START).unwrap();
+        }
+        let other = lock(&mut supply, 2_000_000_000, START + 100 * SECONDS_PER_DAY, START);
+
+        // Stakes unlock penalty-free from here: only the other vault's stake still counts
+        let now = START + 10 * SECONDS_PER_DAY;
+        vault.is_active = false;
+        remove_vault_voting_power(&mut supply, &mut vault, now).unwrap();
+        for day in [10, 39, 40, 41, 100, 199, 200, 201] {
+            assert_matches(&supply, &[&other], START + day * SECONDS_PER_DAY);
+        }
+
+        // Later checkpoints of the removed stakes do not take them out a second time
+        for stake in stakes.iter_mut() {
+            checkpoint_voting_power(&mut supply, &mut vault, stake, now + SECONDS_PER_DAY).unwrap();
+            assert_eq!(stake.ve_amount, 0);
+        }
+        assert_matches(&supply, &[&other], now + SECONDS_PER_DAY);
+        advance_voting_curve(&mut supply.curve, START + 400 * SECONDS_PER_DAY).unwrap();
+        assert_eq!((supply.curve.bias, supply.curve.slope), (0, 0));
+        assert!(supply.curve.slope_changes.iter().all(|change| *change == 0));
+    }
+
+    #[test]
+    fn voting_vaults_charge_principal_on_every_early_exit() {
+        let step = |min_remaining, penalty_bps| PenaltyStep { min_remaining, penalty_bps };
+        let vault = |curve, penalty_bps, steps: &[PenaltyStep]| {
+            let mut vault = Vault {
+                is_active: true,
+                penalty_curve: curve,
+                penalty_bps,
+                penalty_step_count: steps.len() as u8,
+                penalize_principal: true,
+                voting_approved: true,
+                ..Default::default()
+            };
+            vault.penalty_steps[..steps.len()].copy_from_slice(steps);
+            vault
+        };
+
+        assert!(vault_carries_voting_power(&vault(PenaltyCurve::Flat, 500, &[])));
+        assert!(vault_carries_voting_power(&vault(
+            PenaltyCurve::Stepped,
+            0,
+            &[step(0, 200), step(30 * SECONDS_PER_DAY, 800)]
+        )));
+
+        // No principal penalty, a zero penalty, or one that fades before unlock
+        assert!(!vault_carries_voting_power(&Vault {
+            penalize_principal: false,
+            ..vault(PenaltyCurve::Flat, 500, &[])
+        }));
+        assert!(!vault_carries_voting_power(&vault(PenaltyCurve::Flat, 0, &[])));
+        assert!(!vault_carries_voting_power(&vault(PenaltyCurve::LinearDecay, 500, &[])));
+        assert!(!vault_carries_voting_power(&vault(
+            PenaltyCurve::Stepped,
+            0,
+            &[step(SECONDS_PER_DAY, 200), step(30 * SECONDS_PER_DAY, 800)]
+        )));
+        assert!(!vault_carries_voting_power(&vault(
+            PenaltyCurve::Stepped,
+            0,
+            &[step(0, 0), step(30 * SECONDS_PER_DAY, 800)]
+        )));
+
+        // Not approved by the registry authority, or deactivated (stakes unlock penalty-free)
+        assert!(!vault_carries_voting_power(&Vault {
+            voting_approved: false,
+            ..vault(PenaltyCurve::Flat, 500, &[])
+        }));
+        assert!(!vault_carries_voting_power(&Vault {
+            is_active: false,
+            ..vault(PenaltyCurve::Flat, 500, &[])
+        }));
+    }
 }
 
-#[event]
-pub struct RewardsClaimedEvent {
-    pub vault: Pubkey,
-    pub authority: Pubkey,
-    pub amount: u64,
-    pub total_claimed: u64,
-    pub timestamp: i64,
+// ============================================
+// Guardian Bond Dispute Tests
+// ============================================
+
+#[cfg(test)]
+mod guardian_bond_tests {
+    use super::*;
+
+    const T0: i64 = 1_700_000_000;
+
+    fn bond(amount: u64) -> GuardianBond {
+        GuardianBond {
+            vault: Pubkey::new_unique(),
+            stake_record: Pubkey::new_unique(),
+            guardian_nft_mint: Pubkey::new_unique(),
+            bond_token_account: Pubkey::new_unique(),
+            amount,
+            total_slashed: 0,
+            exit_requested_at: 0,
+            dispute_state: GuardianDisputeState::None,
+            dispute_amount: 0,
+            disputed_by: Pubkey::default(),
+            evidence_hash: [0u8; 32],
+            challenge_ends_at: 0,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn dispute_is_bounded_by_the_bond_and_exclusive() {
+        let mut bond = bond(1_000);
+        assert!(open_dispute(&mut bond, Pubkey::new_unique(), 0, [1u8; 32], T0).is_err());
+        assert!(open_dispute(&mut bond, Pubkey::new_unique(), 1_001, [1u8; 32], T0).is_err());
+
+        open_dispute(&mut bond, Pubkey::new_unique(), 1_000, [1u8; 32], T0).unwrap();
+        assert!(bond.dispute_state == GuardianDisputeState::Open);
+        assert_eq!(bond.challenge_ends_at, T0 + GUARDIAN_CHALLENGE_WINDOW);
+
+        // One dispute at a time
+        assert!(open_dispute(&mut bond, Pubkey::new_unique(), 500, [2u8; 32], T0).is_err());
+    }
+
+    #[test]
+    fn challenge_only_inside_the_window() {
+        let mut late = bond(1_000);
+        open_dispute(&mut late, Pubkey::new_unique(), 400, [1u8; 32], T0).unwrap();
+        assert!(challenge_dispute(&mut late, T0 + GUARDIAN_CHALLENGE_WINDOW).is_err());
+
+        let mut timely = bond(1_000);
+        open_dispute(&mut timely, Pubkey::new_unique(), 400, [1u8; 32], T0).unwrap();
+        challenge_dispute(&mut timely, T0 + GUARDIAN_CHALLENGE_WINDOW - 1).unwrap();
+        assert!(timely.dispute_state == GuardianDisputeState::Challenged);
+        // Challenged disputes go to the registry authority, never to the permissionless slash
+        assert!(challenge_dispute(&mut timely, T0).is_err());
+        assert!(require_unchallenged_slash(&timely, T0 + 2 * GUARDIAN_CHALLENGE_WINDOW).is_err());
+    }
+
+    #[test]
+    fn unchallenged_slash_waits_for_the_window() {
+        let mut bond = bond(1_000);
+        assert!(require_unchallenged_slash(&bond, T0).is_err());
+
+        open_dispute(&mut bond, Pubkey::new_unique(), 400, [1u8; 32], T0).unwrap();
+        assert!(require_unchallenged_slash(&bond, T0 + GUARDIAN_CHALLENGE_WINDOW - 1).is_err());
+        assert!(require_unchallenged_slash(&bond, T0 + GUARDIAN_CHALLENGE_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn cleared_dispute_allows_a_new_one() {
+        let mut bond = bond(1_000);
+        open_dispute(&mut bond, Pubkey::new_unique(), 400, [1u8; 32], T0).unwrap();
+        clear_dispute(&mut bond);
+
+        assert!(bond.dispute_state == GuardianDisputeState::None);
+        assert_eq!(bond.dispute_amount, 0);
+        assert_eq!(bond.disputed_by, Pubkey::default());
+        assert_eq!(bond.challenge_ends_at, 0);
+        open_dispute(&mut bond, Pubkey::new_unique(), 1_000, [2u8; 32], T0 + 1).unwrap();
+    }
 }
 
-#[event]
-pub struct VaultDeactivatedEvent {
-    pub vault: Pubkey,
-    pub authority: Pubkey,
-    pub timestamp: i64,
+// ============================================
+// Balance Checkpoint Tests
+// ============================================
+
+#[cfg(test)]
+mod balance_checkpoint_tests {
+    use super::*;
+
+    const START: i64 = 1_700_000_000;
+
+    fn clock_at(unix_timestamp: i64) -> Clock {
+        Clock {
+            slot: (unix_timestamp - START) as u64 * 2,
+            unix_timestamp,
+            ..Default::default()
+        }
+    }
+
+    fn set_balance(stake: &mut StakeRecord, amount: u64, at: i64) {
+        stake.amount = amount;
+        record_balance_checkpoint(stake, &clock_at(at));
+    }
+
+    #[test]
+    fn queries_return_balance_in_force() {
+        let mut stake = StakeRecord::default();
+        set_balance(&mut stake, 100, START);
+        set_balance(&mut stake, 250, START + 10);
+        set_balance(&mut stake, 0, START + 20);
+
+        assert_eq!(stake_at(&stake, START - 1).unwrap(), 0);
+        assert_eq!(stake_at(&stake, START).unwrap(), 100);
+        assert_eq!(stake_at(&stake, START + 9).unwrap(), 100);
+        assert_eq!(stake_at(&stake, START + 10).unwrap(), 250);
+        assert_eq!(stake_at(&stake, START + 25).unwrap(), 0);
+    }
+
+    #[test]
+    fn same_second_changes_keep_final_balance() {
+        let mut stake = StakeRecord::default();
+        set_balance(&mut stake, 100, START);
+        set_balance(&mut stake, 40, START);
+
+        assert_eq!(stake.checkpoint_count, 1);
+        assert_eq!(stake_at(&stake, START).unwrap(), 40);
+    }
+
+    #[test]
+    fn ring_keeps_latest_history() {
+        let mut stake = StakeRecord::default();
+        let changes = MAX_BALANCE_CHECKPOINTS as i64 + 5;
+        for i in 0..changes {
+            set_balance(&mut stake, (i as u64 + 1) * 10, START + i * 100);
+        }
+
+        // Every retained change is still queryable
+        for i in changes - MAX_BALANCE_CHECKPOINTS as i64..changes {
+            assert_eq!(stake_at(&stake, START + i * 100 + 50).unwrap(), (i as u64 + 1) * 10);
+        }
+        // Overwritten history is reported, not guessed
+        assert!(stake_at(&stake, START + 100).is_err());
+    }
 }
 
-#[event]
-pub struct EarlyUnlockEvent {
-    pub vault: Pubkey,
-    pub authority: Pubkey,
-    pub amount: u64,
-    pub forfeited_rewards: u64,  // Rewards that would have been earned
-    pub unlock_at: i64,           // Original unlock timestamp
-    pub unlocked_at: i64,         // Actual unlock timestamp (early)
-    pub days_early: i64,          // How many days early
+// ============================================
+// Reward Reserve Tests
+// ============================================
+
+#[cfg(test)]
+mod reward_reserve_tests {
+    use super::*;
+
+    #[test]
+    fn coverage_days_rounds_down_and_saturates_without_emissions() {
+        assert_eq!(reserve_coverage_days(1_000, 300), 3);
+        assert_eq!(reserve_coverage_days(299, 300), 0);
+        assert_eq!(reserve_coverage_days(1_000, 0), u64::MAX);
+    }
+
+    #[test]
+    fn daily_emission_matches_annual_rate() {
+        // 1000 bps on 365M tokens pays 36.5M a year, 100k a day
+        let schedule = [RewardEpoch { start_time: 0, rate: 1000 }];
+        let daily = calculate_pending_rewards(365_000_000, &schedule, 0, SECONDS_PER_DAY).unwrap();
+        assert_eq!(daily, 100_000);
+    }
 }
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Vault authority mismatch")]
-    VaultAuthorityMismatch,
-    #[msg("Unauthorized: caller is not the authority")]
-    Unauthorized,
-    #[msg("Arithmetic overflow detected")]
-    Overflow,
-    #[msg("Arithmetic underflow detected")]
-    Underflow,
-    #[msg("Amount too small for staking")]
-    AmountTooSmall,
-    #[msg("Amount too large for staking")]
-    AmountTooLarge,
-    #[msg("Insufficient balance for operation")]
-    InsufficientBalance,
-    #[msg("Insufficient staked amount for withdrawal")]
-    InsufficientStake,
-    #[msg("Tokens are still locked")]
-    StillLocked,
-    #[msg("Reward rate too high (max 100%)")]
-    RewardRateTooHigh,
-    #[msg("Lock duration too short (minimum 1 minute)")]
-    LockDurationTooShort,
-    #[msg("Lock duration too long (maximum 1 year)")]
-    LockDurationTooLong,
-    #[msg("Vault is inactive")]
-    VaultInactive,
-    #[msg("Vault is already inactive")]
-    VaultAlreadyInactive,
-    #[msg("No rewards available to claim")]
-    NoRewardsToClaim,
-    #[msg("Insufficient vault balance for rewards")]
-    InsufficientVaultBalance,
-    #[msg("Invalid vault token account")]
-    InvalidVaultAccount,
-    #[msg("Deposit cooldown active - please wait before depositing again")]
-    DepositCooldownActive,
-    #[msg("Claim cooldown active - please wait before claiming again")]
-    ClaimCooldownActive,
-    #[msg("Division by zero")]
-    DivisionByZero,
+// ============================================
+// Vault Reward Accounting Tests
+// ============================================
+
+#[cfg(test)]
+mod vault_accounting_tests {
+    use super::*;
+
+    #[test]
+    fn reward_index_tracks_per_token_accrual() {
+        let schedule = [
+            RewardEpoch { start_time: 0, rate: 1000 },
+            RewardEpoch { start_time: 40 * SECONDS_PER_DAY, rate: 2500 },
+        ];
+        let (t1, t2) = (10 * SECONDS_PER_DAY, 90 * SECONDS_PER_DAY);
+        let index_delta = calculate_pending_rewards(REWARD_INDEX_PRECISION, &schedule, t1, t2).unwrap();
+
+        for amount in [MIN_STAKE_AMOUNT, 123_456_789_012, MAX_STAKE_AMOUNT] {
+            let accrued = calculate_pending_rewards(amount, &schedule, t1, t2).unwrap() as u128;
+            let from_index = amount as u128 * index_delta as u128 / REWARD_INDEX_PRECISION as u128;
+            // Index rounding loses at most one unit per REWARD_INDEX_PRECISION staked
+            assert!(accrued >= from_index);
+            assert!(accrued - from_index <= 1 + amount as u128 / REWARD_INDEX_PRECISION as u128);
+        }
+    }
+
+    #[test]
+    fn nft_rewards_are_only_forfeited_on_request() {
+        assert_eq!(nft_rewards_payable(500, 1_000, false).unwrap(), 500);
+        assert_eq!(nft_rewards_payable(500, 1_000, true).unwrap(), 500);
+        // Underfunded pool: fail, or pay what it covers when the owner opts to forfeit the rest
+        assert!(nft_rewards_payable(500, 200, false).is_err());
+        assert_eq!(nft_rewards_payable(500, 200, true).unwrap(), 200);
+        assert_eq!(nft_rewards_payable(500, 0, true).unwrap(), 0);
+    }
+
+    #[test]
+    fn crank_tips_come_out_of_staker_rewards() {
+        let mut vault = Vault {
+            is_active: true,
+            total_staked: 987_654_321_000,
+            reward_epoch_count: 1,
+            ..Default::default()
+        };
+        vault.reward_epochs[0] = RewardEpoch { start_time: 0, rate: 1500 };
+
+        // Uncranked updates (deposits, claims) and cranks both set the tip share aside
+        for t in [3 * SECONDS_PER_DAY, 3 * SECONDS_PER_DAY + 60, 45 * SECONDS_PER_DAY] {
+            advance_vault_rewards(&mut vault, t).unwrap();
+        }
+        let staker_rewards =
+            vault.total_staked as u128 * vault.reward_index / REWARD_INDEX_PRECISION as u128;
+        let committed = staker_rewards + vault.crank_tips_pending as u128;
+
+        assert!(vault.crank_tips_pending > 0);
+        assert!(committed <= vault.total_rewards_accrued as u128);
+        // Rounding leaves at most a few units per update uncommitted
+        let slack_per_update = 2 + vault.total_staked as u128 / REWARD_INDEX_PRECISION as u128;
+        assert!(vault.total_rewards_accrued as u128 - committed <= 3 * slack_per_update);
+    }
 }
@@ -115,7 +115,7 @@ pub mod special_distribution {
 
         // Initialize NFT allocation record
         let nft_allocation = &mut ctx.accounts.nft_allocation;
-        nft_allocation.distribution_config = ctx.accounts.distribution_config.key();
+        nft_allocation.distribution_config = config.key();
         nft_allocation.nft_mint = ctx.accounts.nft_mint.key();
         nft_allocation.owner = ctx.accounts.nft_owner.key();
         nft_allocation.total_allocation = allocation;
@@ -186,11 +186,8 @@ pub mod special_distribution {
         nft_allocation.last_claim = clock.unix_timestamp;
 
         // Transfer tokens
-        let seeds = &[
-            b"distribution_config",
-            &[config.bump],
-        ];
-        let signer = &[&seeds[..]];
+        let seeds: &[&[u8]] = &[b"distribution_config", &[config.bump]];
+        let signer = &[seeds];
 
         token::transfer(
             CpiContext::new_with_signer(
@@ -256,11 +253,8 @@ pub mod special_distribution {
         );
 
         // Transfer tokens to vault
-        let seeds = &[
-            b"distribution_config",
-            &[config.bump],
-        ];
-        let signer = &[&seeds[..]];
+        let seeds: &[&[u8]] = &[b"distribution_config", &[config.bump]];
+        let signer = &[seeds];
 
         token::transfer(
             CpiContext::new_with_signer(
@@ -332,6 +326,30 @@ pub mod special_distribution {
 
         Ok(())
     }
+
+    /// Hand the distribution to a new authority (e.g. the governance authority PDA)
+    pub fn transfer_distribution_authority(
+        ctx: Context<TransferDistributionAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.distribution_config;
+
+        require!(
+            ctx.accounts.authority.key() == config.authority,
+            ErrorCode::Unauthorized
+        );
+
+        let old_authority = config.authority;
+        config.authority = new_authority;
+
+        emit!(DistributionAuthorityTransferredEvent {
+            old_authority,
+            new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
 }
 
 // Helper function to calculate claimable amount based on vesting schedule
@@ -373,7 +391,7 @@ fn calculate_claimable_amount(
     };
 
     // Calculate claimable (vested - already claimed)
-    let claimable = vested
+    let claimable = vested_amount
         .checked_sub(claimed_amount)
         .ok_or(ErrorCode::Underflow)?;
 
@@ -485,6 +503,17 @@ pub struct DeactivateDistribution<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct TransferDistributionAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"distribution_config"],
+        bump = distribution_config.bump
+    )]
+    pub distribution_config: Account<'info, DistributionConfig>,
+    pub authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct DeactivateAllocation<'info> {
     #[account(
@@ -575,6 +604,13 @@ pub struct AllocationDeactivatedEvent {
     pub timestamp: i64,
 }
 
+#[event]
+pub struct DistributionAuthorityTransferredEvent {
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Unauthorized: caller is not the authority")]